@@ -0,0 +1,496 @@
+// And-inverter graphs: every expression is lowered to two-input AND nodes with complemented edges
+// Edges follow the areabdd conventions: node 1 is the constant, edge 1 is true, -1 is false and a negative edge is a complement
+// Nodes are numbered so that children always come before their parents, and identical AND nodes are
+// structurally hashed into one, so expressions built into the same graph share their common parts
+//
+// fraig merges nodes that compute the same function even when their structure differs. Random simulation
+// groups candidate nodes by signature, and the SAT solver either proves each candidate equal to an earlier
+// node of its group or returns an input pattern that tells them apart, which refines every later comparison
+
+use std::collections::HashMap;
+use crate::parser::{Definition, Operator, Parser, Token};
+use crate::sat::{Lit, Solver};
+
+type Edge = isize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AigNode {
+    Const,
+    Input(char),
+    And(Edge, Edge)
+}
+
+#[derive(Debug, Clone)]
+pub struct AIG {
+    nodes: Vec<AigNode>, // Indexed by node; slot 0 is unused so that nodes match their edges
+    strash: HashMap<(Edge, Edge), Edge>,
+    inputs: Vec<(char, Edge)>,
+    outputs: Vec<Edge>
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FraigStats {
+    pub sat_calls: usize,
+    pub merged: usize, // Nodes proven equal to an earlier node by SAT
+    pub disproved: usize // Candidates told apart by a SAT counterexample
+}
+
+// 64 random patterns per word
+const SIM_WORDS: usize = 4;
+// Limits the SAT calls spent on a single node
+const MAX_CANDIDATES: usize = 8;
+
+impl AIG {
+    pub fn new() -> Self {
+        AIG {
+            nodes: vec![AigNode::Const, AigNode::Const],
+            strash: HashMap::new(),
+            inputs: Vec::new(),
+            outputs: Vec::new()
+        }
+    }
+
+    // Input names and their edges, in creation order
+    pub fn inputs(&self) -> &[(char, Edge)] {
+        return &self.inputs;
+    }
+
+    pub fn outputs(&self) -> &[Edge] {
+        return &self.outputs;
+    }
+
+    pub fn add_output(&mut self, edge: Edge) {
+        self.outputs.push(edge);
+    }
+
+    pub fn node(&self, edge: Edge) -> AigNode {
+        return self.nodes[edge.unsigned_abs()];
+    }
+
+    pub fn and_count(&self) -> usize {
+        return self.nodes.iter().filter(|node| matches!(node, AigNode::And(_, _))).count();
+    }
+}
+
+impl Default for AIG {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the edge of the named input, creating it if it is new
+pub fn input(aig: &mut AIG, var: char) -> Edge {
+    if let Some((_, edge)) = aig.inputs.iter().find(|(name, _)| *name == var) {
+        return *edge;
+    }
+
+    let edge = aig.nodes.len() as Edge;
+    aig.nodes.push(AigNode::Input(var));
+    aig.inputs.push((var, edge));
+    return edge;
+}
+
+pub fn and(aig: &mut AIG, a: Edge, b: Edge) -> Edge {
+    if a == -1 || b == -1 || a == -b {
+        return -1;
+    } else if a == 1 || a == b {
+        return b;
+    } else if b == 1 {
+        return a;
+    }
+
+    let key = (a.min(b), a.max(b));
+    if let Some(edge) = aig.strash.get(&key) {
+        return *edge;
+    }
+
+    let edge = aig.nodes.len() as Edge;
+    aig.nodes.push(AigNode::And(key.0, key.1));
+    aig.strash.insert(key, edge);
+    return edge;
+}
+
+pub fn or(aig: &mut AIG, a: Edge, b: Edge) -> Edge {
+    return -and(aig, -a, -b);
+}
+
+pub fn xor(aig: &mut AIG, a: Edge, b: Edge) -> Edge {
+    let lhs = and(aig, a, -b);
+    let rhs = and(aig, -a, b);
+    return or(aig, lhs, rhs);
+}
+
+pub fn ite(aig: &mut AIG, c: Edge, t: Edge, e: Edge) -> Edge {
+    let lhs = and(aig, c, t);
+    let rhs = and(aig, -c, e);
+    return or(aig, lhs, rhs);
+}
+
+// Lowers every expression of the session into one graph, with an output per expression
+// Inputs are created in ast_order; fixed (variable, value) pairs become constants
+pub fn build(parser: &Parser, fixed: &[(char, bool)]) -> AIG {
+    let mut aig = AIG::new();
+    let mut vars: HashMap<char, Edge> = HashMap::new();
+    for var in parser.ast_order.keys() {
+        let edge = match fixed.iter().find(|(c, _)| c == var) {
+            Some((_, val)) => if *val { 1 } else { -1 },
+            None => input(&mut aig, *var)
+        };
+        vars.insert(*var, edge);
+    }
+
+    // Definitions are lowered once per distinct argument list and shared between the outputs
+    let mut shared: HashMap<(usize, Vec<Edge>), Edge> = HashMap::new();
+    for expr in parser.exprs.iter() {
+        let out = build_helper(&mut aig, &expr.shared_rpn, &parser.defs, &vars, &[], &mut shared);
+        aig.add_output(out);
+    }
+
+    return aig;
+}
+
+fn build_helper(aig: &mut AIG, rpn: &[Token], defs: &[Definition], vars: &HashMap<char, Edge>, params: &[Edge],
+    shared: &mut HashMap<(usize, Vec<Edge>), Edge>) -> Edge {
+    let mut edge_stack: Vec<Edge> = Vec::with_capacity(rpn.len());
+
+    for t in rpn.iter() {
+        match t {
+            Token::VAR(c) => edge_stack.push(vars[c]),
+            Token::PARAM(ndx) => edge_stack.push(params[*ndx]),
+            Token::DEF(id) => {
+                let args = edge_stack.split_off(edge_stack.len() - defs[*id].params.len());
+                let out = match shared.get(&(*id, args.clone())) {
+                    Some(out) => *out,
+                    None => {
+                        let out = build_helper(aig, &defs[*id].body.shared_rpn, defs, vars, &args, shared);
+                        shared.insert((*id, args), out);
+                        out
+                    }
+                };
+                edge_stack.push(out);
+            },
+            Token::OP(Operator::NOT) => {
+                let top = edge_stack.last_mut().unwrap();
+                *top = -(*top);
+            },
+            Token::OP(Operator::ITE) => {
+                let e = edge_stack.pop().unwrap();
+                let t = edge_stack.pop().unwrap();
+                let c = edge_stack.pop().unwrap();
+                let out = ite(aig, c, t, e);
+                edge_stack.push(out);
+            },
+            Token::OP(op) => {
+                let rhs = edge_stack.pop().unwrap();
+                let lhs = edge_stack.pop().unwrap();
+                let out = match op {
+                    Operator::AND => and(aig, lhs, rhs),
+                    Operator::OR => or(aig, lhs, rhs),
+                    Operator::XOR => xor(aig, lhs, rhs),
+                    _ => unreachable!()
+                };
+                edge_stack.push(out);
+            },
+            _ => panic!("Unexpected token while building AIG")
+        }
+    }
+
+    return edge_stack.pop().expect("No edge left to assign as output");
+}
+
+// Evaluates an edge with values indexed like inputs()
+pub fn evaluate(aig: &AIG, edge: Edge, values: &[bool]) -> bool {
+    let mut node_values: Vec<bool> = vec![true; aig.nodes.len()];
+    for ((_, input), val) in aig.inputs.iter().zip(values.iter()) {
+        node_values[*input as usize] = *val;
+    }
+
+    for id in 2..=edge.unsigned_abs() {
+        if let AigNode::And(a, b) = aig.nodes[id] {
+            node_values[id] = edge_value(&node_values, a) && edge_value(&node_values, b);
+        }
+    }
+
+    return edge_value(&node_values, edge);
+}
+
+fn edge_value(node_values: &[bool], edge: Edge) -> bool {
+    return node_values[edge.unsigned_abs()] ^ (edge < 0);
+}
+
+// Copies only the nodes that some output depends on
+pub fn cleanup(aig: &AIG) -> AIG {
+    let mut live = vec![false; aig.nodes.len()];
+    for out in aig.outputs.iter() {
+        live[out.unsigned_abs()] = true;
+    }
+    for id in (2..aig.nodes.len()).rev() {
+        if let (true, AigNode::And(a, b)) = (live[id], aig.nodes[id]) {
+            live[a.unsigned_abs()] = true;
+            live[b.unsigned_abs()] = true;
+        }
+    }
+
+    // Inputs are all kept, so that the input list stays the same
+    let mut res = AIG::new();
+    let mut map: Vec<Edge> = vec![0; aig.nodes.len()];
+    map[1] = 1;
+    for id in 2..aig.nodes.len() {
+        map[id] = match aig.nodes[id] {
+            AigNode::Input(c) => input(&mut res, c),
+            AigNode::And(a, b) if live[id] => and(&mut res, remap(&map, a), remap(&map, b)),
+            _ => 0
+        };
+    }
+    for out in aig.outputs.iter() {
+        res.add_output(remap(&map, *out));
+    }
+
+    return res;
+}
+
+fn remap(map: &[Edge], edge: Edge) -> Edge {
+    let mapped = map[edge.unsigned_abs()];
+    return if edge < 0 { -mapped } else { mapped };
+}
+
+// Returns an equivalent graph in which no two nodes compute the same function, up to complement,
+// so outputs that are equivalent end up on the same edge
+pub fn fraig(aig: &AIG) -> (AIG, FraigStats) {
+    let mut stats = FraigStats::default();
+    let mut sims = simulate(aig);
+
+    let mut res = AIG::new();
+    let mut map: Vec<Edge> = vec![0; aig.nodes.len()];
+    map[1] = 1;
+
+    // The solver mirrors res: every node of res gets a literal as soon as it exists
+    let mut solver = Solver::new();
+    let mut lits: Vec<Lit> = vec![0, solver.new_var()];
+    solver.add_clause(&[lits[1]]);
+
+    // Candidate groups are keyed on the initial signature, normalized so that complements share a group
+    let mut classes: HashMap<Vec<u64>, Vec<usize>> = HashMap::new();
+    for id in 1..aig.nodes.len() {
+        let edge = match aig.nodes[id] {
+            AigNode::Const => 1,
+            AigNode::Input(c) => input(&mut res, c),
+            AigNode::And(a, b) => and(&mut res, remap(&map, a), remap(&map, b))
+        };
+        encode_new_nodes(&res, &mut solver, &mut lits);
+
+        let (key, phase) = normalize(&sims[id][..SIM_WORDS]);
+        let candidates = classes.entry(key).or_default();
+        let mut merged = None;
+        for rep in candidates.iter().take(MAX_CANDIDATES) {
+            // The node should equal the representative, complemented if their phases differ
+            let sign = phase * normalize(&sims[*rep][..SIM_WORDS]).1;
+            let matches = sims[id].iter().zip(sims[*rep].iter()).all(|(x, y)| *x == if sign < 0 { !*y } else { *y });
+            if !matches {
+                continue;
+            }
+
+            let rep_edge = map[*rep] * sign;
+            if rep_edge == edge {
+                merged = Some(rep_edge);
+                break;
+            }
+
+            stats.sat_calls += 1;
+            match prove_equal(&mut solver, edge_lit(&lits, edge), edge_lit(&lits, rep_edge)) {
+                None => {
+                    stats.merged += 1;
+                    merged = Some(rep_edge);
+                    break;
+                },
+                Some(model) => {
+                    // Simulate the distinguishing pattern everywhere so later comparisons can use it
+                    stats.disproved += 1;
+                    let pattern: Vec<bool> = aig.inputs.iter()
+                        .map(|(c, _)| res.inputs.iter().find(|(name, _)| name == c)
+                            .map(|(_, e)| model[lits[*e as usize].unsigned_abs() - 1])
+                            .unwrap_or(false))
+                        .collect();
+                    add_pattern(aig, &mut sims, &pattern);
+                }
+            }
+        }
+
+        match merged {
+            Some(rep_edge) => map[id] = rep_edge,
+            None => {
+                map[id] = edge;
+                candidates.push(id);
+            }
+        }
+    }
+
+    for out in aig.outputs.iter() {
+        res.add_output(remap(&map, *out));
+    }
+
+    return (cleanup(&res), stats);
+}
+
+// Random simulation of every node; inputs get deterministic pseudo-random words
+fn simulate(aig: &AIG) -> Vec<Vec<u64>> {
+    let mut state: u64 = 0x9e37_79b9_7f4a_7c15;
+    let mut sims: Vec<Vec<u64>> = vec![Vec::new(); aig.nodes.len()];
+    for id in 1..aig.nodes.len() {
+        sims[id] = match aig.nodes[id] {
+            AigNode::Const => vec![!0; SIM_WORDS],
+            AigNode::Input(_) => (0..SIM_WORDS).map(|_| {
+                // xorshift64
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            }).collect(),
+            AigNode::And(a, b) => (0..SIM_WORDS).map(|w| sim_word(&sims, a, w) & sim_word(&sims, b, w)).collect()
+        };
+    }
+
+    return sims;
+}
+
+// Appends one word holding a single input pattern to every node's signature
+fn add_pattern(aig: &AIG, sims: &mut [Vec<u64>], pattern: &[bool]) {
+    let w = sims[1].len();
+    for id in 1..aig.nodes.len() {
+        let word = match aig.nodes[id] {
+            AigNode::Const => !0,
+            AigNode::Input(c) => {
+                let ndx = aig.inputs.iter().position(|(name, _)| *name == c).unwrap();
+                if pattern[ndx] { !0 } else { 0 }
+            },
+            AigNode::And(a, b) => sim_word(sims, a, w) & sim_word(sims, b, w)
+        };
+        sims[id].push(word);
+    }
+}
+
+fn sim_word(sims: &[Vec<u64>], edge: Edge, w: usize) -> u64 {
+    let word = sims[edge.unsigned_abs()][w];
+    return if edge < 0 { !word } else { word };
+}
+
+// Complements a signature whose first pattern is true, returning the phase that was applied
+fn normalize(sig: &[u64]) -> (Vec<u64>, isize) {
+    if sig[0] & 1 == 1 {
+        return (sig.iter().map(|w| !w).collect(), -1);
+    }
+
+    return (sig.to_vec(), 1);
+}
+
+fn encode_new_nodes(aig: &AIG, solver: &mut Solver, lits: &mut Vec<Lit>) {
+    for id in lits.len()..aig.nodes.len() {
+        let lit = solver.new_var();
+        if let AigNode::And(a, b) = aig.nodes[id] {
+            let (a, b) = (edge_lit(lits, a), edge_lit(lits, b));
+            solver.add_clause(&[-lit, a]);
+            solver.add_clause(&[-lit, b]);
+            solver.add_clause(&[lit, -a, -b]);
+        }
+        lits.push(lit);
+    }
+}
+
+fn edge_lit(lits: &[Lit], edge: Edge) -> Lit {
+    let lit = lits[edge.unsigned_abs()];
+    return if edge < 0 { -lit } else { lit };
+}
+
+// Returns None when x and y always agree, or otherwise a model telling them apart
+// Proven equivalences are kept as clauses to speed up later proofs
+fn prove_equal(solver: &mut Solver, x: Lit, y: Lit) -> Option<Vec<bool>> {
+    let act = solver.new_var();
+    solver.add_clause(&[-act, x, y]);
+    solver.add_clause(&[-act, -x, -y]);
+    let differ = solver.solve_with(&[act]);
+    let model = if differ { Some(solver.model().to_vec()) } else { None };
+
+    solver.add_clause(&[-act]);
+    if !differ {
+        solver.add_clause(&[-x, y]);
+        solver.add_clause(&[x, -y]);
+    }
+    return model;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn build_exprs(exprs: &[&str], fixed: &[(char, bool)]) -> AIG {
+        let parser = parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+        return build(&parser, fixed);
+    }
+
+    #[test]
+    fn structural_hashing() {
+        let aig = build_exprs(&["a & b", "b & a", "~(~a | ~b)", "a ^ b"], &[]);
+        let outs = aig.outputs();
+        assert_eq!(outs[0], outs[1]);
+        assert_eq!(outs[0], outs[2]);
+        // One node for a & b, plus the two halves of the xor and the or joining them
+        assert_eq!(4, aig.and_count());
+
+        let mut aig = AIG::new();
+        let a = input(&mut aig, 'a');
+        assert_eq!(-1, and(&mut aig, a, -a));
+        assert_eq!(1, or(&mut aig, a, -a));
+        assert_eq!(a, and(&mut aig, a, 1));
+    }
+
+    #[test]
+    fn evaluate_matches_expressions() {
+        let aig = build_exprs(&["a ? b : c", "(a | b) ^ c"], &[]);
+        for case in 0..8 {
+            let values: Vec<bool> = (0..3).map(|i| case & (1 << i) != 0).collect();
+            let (a, b, c) = (values[0], values[1], values[2]);
+            assert_eq!(if a { b } else { c }, evaluate(&aig, aig.outputs()[0], &values));
+            assert_eq!((a | b) ^ c, evaluate(&aig, aig.outputs()[1], &values));
+        }
+    }
+
+    #[test]
+    fn fraig_merges_equivalent_outputs() {
+        let aig = build_exprs(&["a & (b | c)", "(a & b) | (a & c)", "a ? b | c : a", "(a & b) | c"], &[]);
+        assert_ne!(aig.outputs()[0], aig.outputs()[1]);
+
+        let (fraiged, stats) = fraig(&aig);
+        let outs = fraiged.outputs();
+        assert_eq!(outs[0], outs[1]);
+        assert_eq!(outs[0], outs[2]);
+        assert_ne!(outs[0], outs[3]);
+        assert!(stats.merged > 0);
+        assert!(fraiged.and_count() < aig.and_count());
+
+        for case in 0..8 {
+            let values: Vec<bool> = (0..3).map(|i| case & (1 << i) != 0).collect();
+            for (old, new) in aig.outputs().iter().zip(outs.iter()) {
+                assert_eq!(evaluate(&aig, *old, &values), evaluate(&fraiged, *new, &values));
+            }
+        }
+    }
+
+    #[test]
+    fn fraig_finds_constants_and_fixed_inputs() {
+        let (fraiged, _) = fraig(&build_exprs(&["(a & b) | (a & ~b) | ~a", "(a ^ b) ^ (b ^ a)"], &[]));
+        assert_eq!(&[1, -1], fraiged.outputs());
+
+        // With b fixed to true the expressions agree
+        let (fraiged, _) = fraig(&build_exprs(&["a & b", "a | ~b"], &[('b', true)]));
+        assert_eq!(fraiged.outputs()[0], fraiged.outputs()[1]);
+    }
+
+    #[test]
+    fn definition_chains_are_built_once() {
+        // Three gates for each xor and one more for each link's and
+        let aig = build(&parser::create_session(parser::definition_chain(40)).unwrap(), &[]);
+        assert!(aig.and_count() <= 3 + 4 * 40);
+    }
+}
+
@@ -0,0 +1,202 @@
+// Algebraic normal form: every function is a unique XOR of ANDs of positive literals (Reed-Muller expansion)
+// Small sessions take the fast Mobius transform of the truth table. Larger ones compute the same polynomial
+// straight from the BDD into a ZDD of monomials, using anf(f) = anf(f0) ^ x & (anf(f0) ^ anf(f1)).
+// Since the polynomial is canonical, two expressions are equivalent exactly when their ANFs match.
+
+use std::collections::HashMap;
+use crate::areabdd::{self, BDD};
+use crate::minimize;
+use crate::parser::Parser;
+use crate::zdd::{self, ZDD};
+
+// Sessions with more variables than this skip the truth table
+pub const MAX_TABLE_VARS: usize = 16;
+
+// Monomials are sorted by degree, then by the ordering; the empty monomial is the constant 1
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Anf {
+    pub monomials: Vec<Vec<char>>,
+    pub degree: usize
+}
+
+// In-place Mobius transform over GF(2): table[m] becomes the coefficient of the monomial whose variables are
+// the set bits of m
+pub fn mobius(table: &mut [bool]) {
+    let mut step = 1;
+    while step < table.len() {
+        for ndx in 0..table.len() {
+            if ndx & step != 0 {
+                table[ndx] ^= table[ndx ^ step];
+            }
+        }
+        step <<= 1;
+    }
+}
+
+// Truth table of a BDD function, indexed like minterms with bit i holding the i-th variable
+pub fn truth_table(bdd: &BDD, root: isize, num_vars: usize) -> Vec<bool> {
+    let mut table = vec![false; 1 << num_vars];
+    for m in minimize::minterms(bdd, root, num_vars) {
+        table[m as usize] = true;
+    }
+    return table;
+}
+
+pub fn from_table(table: &[bool], names: &[char]) -> Anf {
+    let mut coefficients = table.to_vec();
+    mobius(&mut coefficients);
+
+    let monomials: Vec<Vec<char>> = coefficients.iter().enumerate()
+        .filter(|(_, c)| **c)
+        .map(|(m, _)| names.iter().enumerate().filter(|(i, _)| m & (1 << i) != 0).map(|(_, name)| *name).collect())
+        .collect();
+    return canonical(monomials, names);
+}
+
+// ANF of a BDD function as a family of monomials
+// Complement edges only toggle the constant monomial, so the memo is kept on regular edges
+pub fn from_bdd(zdd: &mut ZDD, bdd: &BDD, f: isize) -> isize {
+    let names: Vec<char> = bdd.ordering().keys().copied().collect();
+    let mut memo: HashMap<isize, isize> = HashMap::new();
+    return from_bdd_helper(zdd, bdd, f, &names, &mut memo);
+}
+
+fn from_bdd_helper(zdd: &mut ZDD, bdd: &BDD, f: isize, names: &[char], memo: &mut HashMap<isize, isize>) -> isize {
+    if f < 0 {
+        let regular = from_bdd_helper(zdd, bdd, -f, names, memo);
+        return zdd::sym_diff(zdd, regular, zdd::BASE);
+    } else if f == 1 {
+        return zdd::BASE;
+    } else if let Some(id) = memo.get(&f) {
+        return *id;
+    }
+
+    let (var, lo, hi) = areabdd::vertex_parts(bdd, f);
+    let lo = from_bdd_helper(zdd, bdd, lo, names, memo);
+    let hi = from_bdd_helper(zdd, bdd, hi, names, memo);
+
+    // Neither cofactor mentions the variable, so change multiplies their sum by it
+    let derivative = zdd::sym_diff(zdd, lo, hi);
+    let with_var = zdd::change(zdd, derivative, names[var as usize - 1]);
+    let res = zdd::sym_diff(zdd, lo, with_var);
+    memo.insert(f, res);
+    return res;
+}
+
+// Sorts each monomial and the list of them into the session's ordering
+fn canonical(mut monomials: Vec<Vec<char>>, names: &[char]) -> Anf {
+    let position: HashMap<char, usize> = names.iter().enumerate().map(|(i, name)| (*name, i)).collect();
+    for monomial in monomials.iter_mut() {
+        monomial.sort_by_key(|name| position[name]);
+    }
+    monomials.sort_by_key(|monomial| (monomial.len(), monomial.iter().map(|name| position[name]).collect::<Vec<usize>>()));
+
+    let degree = monomials.last().map_or(0, |monomial| monomial.len());
+    return Anf {monomials, degree};
+}
+
+// Writes the polynomial in the parser's syntax; the constant 1 negates the rest, and the constant functions are
+// written with the first variable since there are no constant tokens
+pub fn to_expr(anf: &Anf, names: &[char]) -> String {
+    let terms: Vec<&Vec<char>> = anf.monomials.iter().filter(|monomial| !monomial.is_empty()).collect();
+    let constant = terms.len() < anf.monomials.len();
+    if terms.is_empty() {
+        return format!("{0} {1} ~{0}", names[0], if constant { "|" } else { "&" });
+    }
+
+    let printed: Vec<String> = terms.iter().map(|monomial| {
+        let lits: Vec<String> = monomial.iter().map(|name| name.to_string()).collect();
+        if lits.len() > 1 && (terms.len() > 1 || constant) {
+            format!("({})", lits.join(" & "))
+        } else {
+            lits.join(" & ")
+        }
+    }).collect();
+
+    let sum = printed.join(" ^ ");
+    if !constant {
+        return sum;
+    } else if terms.len() == 1 {
+        return format!("~{sum}");
+    }
+    return format!("~({sum})");
+}
+
+// ANF of every expression in the session, from the truth table when it is small enough
+pub fn anf_session(parser: &Parser) -> Vec<Anf> {
+    let bdd = areabdd::build(BDD::new(), parser);
+    let names: Vec<char> = parser.ast_order.keys().copied().collect();
+
+    if names.len() <= MAX_TABLE_VARS {
+        return bdd.roots().iter().map(|root| from_table(&truth_table(&bdd, *root, names.len()), &names)).collect();
+    }
+
+    let mut zdd = ZDD::new();
+    return bdd.roots().iter().map(|root| {
+        let family = from_bdd(&mut zdd, &bdd, *root);
+        canonical(zdd::sets(&zdd, family), &names)
+    }).collect();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn mobius_is_an_involution() {
+        // a | b = a ^ b ^ ab
+        let mut table = vec![false, true, true, true];
+        mobius(&mut table);
+        assert_eq!(vec![false, true, true, true], table);
+        mobius(&mut table);
+        assert_eq!(vec![false, true, true, true], table);
+
+        let mut table = vec![true, false, false, true, false, true, true, false];
+        mobius(&mut table);
+        assert_eq!(vec![true, true, true, false, true, false, false, false], table);
+    }
+
+    #[test]
+    fn polynomials_and_degree() {
+        let exprs = vec![String::from("a | b"), String::from("~(a ^ b) | c"), String::from("a ? b : c"), String::from("~a & a")];
+        let parser = parser::create_session(exprs).unwrap();
+        let names: Vec<char> = parser.ast_order.keys().copied().collect();
+        let anfs = anf_session(&parser);
+
+        let printed: Vec<String> = anfs.iter().map(|anf| to_expr(anf, &names)).collect();
+        assert_eq!(vec!["a ^ b ^ (a & b)", "~(a ^ b ^ (a & c) ^ (b & c))", "c ^ (a & b) ^ (a & c)", "a & ~a"], printed);
+        assert_eq!(vec![2, 2, 2, 0], anfs.iter().map(|anf| anf.degree).collect::<Vec<usize>>());
+
+        // The printed polynomial is the same function
+        let check = parser::create_session(vec![String::from("~(a ^ b) | c"), printed[1].clone()]).unwrap();
+        let bdd = areabdd::build(BDD::new(), &check);
+        assert_eq!(bdd.roots()[0], bdd.roots()[1]);
+    }
+
+    #[test]
+    fn bdd_matches_truth_table() {
+        let exprs = vec![String::from("(a & b) | (c ^ ~d) | (a ? e : ~b)"), String::from("~((a | b) & (c | d | e))")];
+        let parser = parser::create_session(exprs).unwrap();
+        let names: Vec<char> = parser.ast_order.keys().copied().collect();
+        let bdd = areabdd::build(BDD::new(), &parser);
+
+        let mut zdd = ZDD::new();
+        for root in bdd.roots().iter() {
+            let family = from_bdd(&mut zdd, &bdd, *root);
+            let expected = from_table(&truth_table(&bdd, *root, names.len()), &names);
+            assert_eq!(expected, canonical(zdd::sets(&zdd, family), &names));
+            assert_eq!(Some(expected.degree), zdd::max_cardinality(&zdd, family));
+        }
+    }
+
+    #[test]
+    fn wide_parity_stays_linear() {
+        // Twenty variables skip the truth table; parity has one monomial per variable
+        let expr: Vec<String> = ('a'..='t').map(|c| c.to_string()).collect();
+        let parser = parser::create_session(vec![expr.join(" ^ ")]).unwrap();
+        let anf = anf_session(&parser).pop().unwrap();
+        assert_eq!(20, anf.monomials.len());
+        assert_eq!(1, anf.degree);
+    }
+}
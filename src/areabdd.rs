@@ -4,92 +4,174 @@
 // This may also be a good time to consider a BDD re-ordering? (If doing dynamic ordering)
 
 use linked_hash_map::LinkedHashMap;
-use std::collections::{HashMap};
-use std::rc::Rc;
-use crate::parser::{Operator, Parser, Token};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::parser::{Definition, Operator, Parser, Token};
+use crate::cache::{CacheStats, ComputedTable};
 
 type Edge = isize;
 type ID = isize;
 
+// Default memory budget for the operation caches, in bytes
+pub const DEFAULT_CACHE_BUDGET: usize = 1 << 22;
+
 #[derive(Debug, Clone)]
 pub struct BDD {
-    vertex_lookup: HashMap<Rc<Vertex>, ID>,
-    id_lookup: HashMap<ID, Rc<Vertex>>,
-    ref_counts: HashMap<ID, usize>,
+    vertices: Vec<Vertex>, // Indexed by ID; slot 0 is unused so that IDs match their edges
+    unique_tables: Vec<UniqueTable>, // Indexed by variable
+    ref_counts: Vec<usize>, // Indexed by ID; external references plus live parents
     roots: Vec<Edge>,
-    computed_cache: HashMap<Expr, Edge>,
+    computed_cache: ComputedTable<Expr>,
+    quant_cache: ComputedTable<QuantExpr>,
+    subst_cache: ComputedTable<SubstExpr>,
     dead_count: usize,
-    ordering: HashMap<char, usize>
+    ordering: LinkedHashMap<char, usize>
 }
 
-#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
 pub struct Vertex {
     var: isize, // This value should never be < 1 for non-terminal nodes
-    lo: Option<Edge>,
-    hi: Option<Edge>
+    lo: Edge, // Both children of the terminal are 0
+    hi: Edge
 }
 
+// Open-addressing hash table over the vertices of a single variable, keyed on their children
+// Slots hold vertex IDs, with 0 marking an empty slot; vertices are never removed
+#[derive(Debug, Clone, Default)]
+struct UniqueTable {
+    slots: Vec<ID>,
+    len: usize
+}
+
+// Computed table entries are ite(f, g, h) triples in standard form
 #[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
 pub struct Expr {
-    op: Operator,
+    f: Edge,
+    g: Edge,
+    h: Edge
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+enum QuantOp {
+    Exists,
+    Forall,
+    AndExists
+}
+
+// Quantified variables are passed around as a cube (the conjunction of their positive literals)
+// Single-operand quantifiers use the 1-terminal as their rhs
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct QuantExpr {
+    op: QuantOp,
     lhs: Edge,
-    rhs: Edge
+    rhs: Edge,
+    cube: Edge
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+enum SubstOp {
+    Restrict,
+    Constrain,
+    Compose
+}
+
+// Restrict stores the fixed value as a terminal in rhs; Constrain has no var and uses 0
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+pub struct SubstExpr {
+    op: SubstOp,
+    lhs: Edge,
+    rhs: Edge,
+    var: isize
 }
 
 impl BDD {
     pub fn new() -> Self {
-        let mut vertex_lookup: HashMap<Rc<Vertex>, ID> = HashMap::with_capacity(50); // Arbitrary
-        let mut id_lookup: HashMap<ID, Rc<Vertex>> = HashMap::with_capacity(50); // Arbitrary
-        let ref_counts: HashMap<ID, usize> = HashMap::with_capacity(50); // Arbitrary
-        let computed_cache: HashMap<Expr, Edge> = HashMap::with_capacity(50); // Arbitrary
-        let ordering: HashMap<char, usize> = HashMap::with_capacity(5); // Arbitrary
+        return Self::with_cache_budget(DEFAULT_CACHE_BUDGET);
+    }
 
-        // A terminal node has a variable # 0 and no low or high children
-        let terminal_true = Rc::new(Vertex {var: 0, lo: None, hi: None});
+    // The operation caches are lossy and never grow past budget bytes between them
+    // Half of the budget goes to ite; quantification and substitution get a quarter each
+    pub fn with_cache_budget(budget: usize) -> Self {
+        let mut vertices: Vec<Vertex> = Vec::with_capacity(50); // Arbitrary
+        let mut ref_counts: Vec<usize> = Vec::with_capacity(50); // Arbitrary
+        let computed_cache: ComputedTable<Expr> = ComputedTable::with_budget(budget / 2);
+        let quant_cache: ComputedTable<QuantExpr> = ComputedTable::with_budget(budget / 4);
+        let subst_cache: ComputedTable<SubstExpr> = ComputedTable::with_budget(budget / 4);
+        let ordering: LinkedHashMap<char, usize> = LinkedHashMap::new();
 
-        // A terminal node has an ID of 1
-        vertex_lookup.insert(Rc::clone(&terminal_true), 1);
-        id_lookup.insert(1, terminal_true);
+        // A terminal node has a variable # 0 and no low or high children
+        // A terminal node has an ID of 1, so a placeholder fills ID 0
+        let terminal_true = Vertex {var: 0, lo: 0, hi: 0};
+        vertices.push(terminal_true);
+        vertices.push(terminal_true);
+        ref_counts.push(0);
+        ref_counts.push(0);
 
         let roots = Vec::new();
 
         Self {
-            vertex_lookup,
-            id_lookup,
+            vertices,
+            unique_tables: Vec::new(),
             ref_counts,
             roots,
             computed_cache,
+            quant_cache,
+            subst_cache,
             dead_count: 0,
             ordering
         }
     }
 
+    // Root edges of every expression built into the BDD, in build order
+    pub fn roots(&self) -> &[isize] {
+        return &self.roots;
+    }
+
+    // Hit, miss and eviction counts summed over every operation cache
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.computed_cache.stats().merge(&self.quant_cache.stats()).merge(&self.subst_cache.stats());
+    }
+
+    // Statistics for the ite cache alone
+    pub fn ite_cache_stats(&self) -> CacheStats {
+        return self.computed_cache.stats();
+    }
+
+    // Variable names mapped to their levels, from the top of the BDD down
+    pub fn ordering(&self) -> &LinkedHashMap<char, usize> {
+        return &self.ordering;
+    }
+
+    // Number of vertices ever created, including the terminal
+    pub fn vertex_count(&self) -> usize {
+        return self.vertices.len() - 1;
+    }
+
+    // Number of vertices that nothing references any more
+    pub fn dead_count(&self) -> usize {
+        return self.dead_count;
+    }
+
+    // References held on the vertex behind an edge; the terminal is never counted
+    pub fn ref_count(&self, id: isize) -> usize {
+        return self.ref_counts[id.unsigned_abs()];
+    }
 
 }
 
+impl Default for BDD {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl PartialEq for BDD {
     fn eq(&self, other: &Self) -> bool {
-        fn map_eq<K, V>(a: &HashMap<K, V>, b: &HashMap<K,V>) -> bool
-        where 
-            K: Eq + std::hash::Hash,
-            V: Eq
-        {
-            for (key, val_a) in a.iter() {
-                if let Some(val_b) = b.get(key) {
-                    if val_a != val_b {
-                        return false
-                    }
-                } else {
-                    return false
-                }
-            }
-            return true
-        }
-        // Compare Vertex Lookup table
-       return map_eq(&self.vertex_lookup, &other.vertex_lookup) &&
-              map_eq(&self.id_lookup, &other.id_lookup) &&
-              map_eq(&self.ref_counts, &other.ref_counts) &&
-              map_eq(&self.computed_cache, &other.computed_cache) &&
+        // The unique tables are derived from the vertices, so they are not compared
+       return self.vertices == other.vertices &&
+              self.ref_counts == other.ref_counts &&
+              self.computed_cache == other.computed_cache &&
+              self.quant_cache == other.quant_cache &&
+              self.subst_cache == other.subst_cache &&
               self.dead_count == other.dead_count;
     }
 
@@ -98,16 +180,109 @@ impl PartialEq for BDD {
 // Adds a variable with the given name into the BDD
 // Places it last in the current ordering
 pub fn add_var(bdd: &mut BDD, var: char) {
-    if let Some(_) = bdd.ordering.get(&var) {
+    if bdd.ordering.contains_key(&var) {
         panic!("Variable already exists in BDD"); // Change to resolve gracefully
     } else {
-        let var_id = bdd.ordering.len();
+        let var_id = bdd.ordering.len() + 1; // Level 0 is reserved for the terminal
         bdd.ordering.insert(var, var_id);
         let res = make(bdd,  var_id as isize, -1, 1);
+        inc_ref(bdd, &res);
         bdd.roots.push(res);
     }
 }
 
+// Returns the level of the named variable, placing it last in the ordering if it is new
+fn ensure_var(bdd: &mut BDD, var: char) -> usize {
+    if let Some(level) = bdd.ordering.get(&var) {
+        return *level;
+    }
+
+    let var_id = bdd.ordering.len() + 1; // Level 0 is reserved for the terminal
+    bdd.ordering.insert(var, var_id);
+    return var_id;
+}
+
+// Returns the function of a single variable
+pub fn var_edge(bdd: &mut BDD, var: char) -> isize {
+    let level = ensure_var(bdd, var);
+    return make(bdd, level as isize, -1, 1);
+}
+
+// Splits the vertex behind a non-terminal edge into its (var, lo, hi) parts
+// Unlike cofactors, any complement on the edge itself is left to the caller
+pub(crate) fn vertex_parts(bdd: &BDD, id: isize) -> (isize, isize, isize) {
+    let vertex = bdd.vertices[id.unsigned_abs()];
+    return (vertex.var, vertex.lo, vertex.hi);
+}
+
+impl UniqueTable {
+    fn find(&self, vertices: &[Vertex], lo: Edge, hi: Edge) -> Option<ID> {
+        if self.slots.is_empty() {
+            return None;
+        }
+
+        let mask = self.slots.len() - 1;
+        let mut ndx = hash_children(lo, hi) & mask;
+        loop {
+            let id = self.slots[ndx];
+            if id == 0 {
+                return None;
+            }
+
+            let vertex = &vertices[id as usize];
+            if vertex.lo == lo && vertex.hi == hi {
+                return Some(id);
+            }
+            ndx = (ndx + 1) & mask;
+        }
+    }
+
+    // The table doubles whenever it would become more than 3/4 full
+    fn insert(&mut self, vertices: &[Vertex], id: ID) {
+        if (self.len + 1) * 4 > self.slots.len() * 3 {
+            let old = std::mem::take(&mut self.slots);
+            self.slots = vec![0; (old.len() * 2).max(16)];
+            for old_id in old.into_iter().filter(|old_id| *old_id != 0) {
+                self.place(vertices, old_id);
+            }
+        }
+
+        self.place(vertices, id);
+        self.len += 1;
+    }
+
+    fn place(&mut self, vertices: &[Vertex], id: ID) {
+        let mask = self.slots.len() - 1;
+        let vertex = &vertices[id as usize];
+        let mut ndx = hash_children(vertex.lo, vertex.hi) & mask;
+        while self.slots[ndx] != 0 {
+            ndx = (ndx + 1) & mask;
+        }
+        self.slots[ndx] = id;
+    }
+}
+
+fn hash_children(lo: Edge, hi: Edge) -> usize {
+    let h = (lo as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15) ^ (hi as u64).wrapping_mul(0xc2b2_ae3d_27d4_eb4f);
+    return (h ^ (h >> 29)) as usize;
+}
+
+// Stores a new vertex and returns its ID
+// New vertices start out dead; they come alive once something outside the BDD references them
+fn add_vertex(bdd: &mut BDD, vertex: Vertex) -> ID {
+    let id = bdd.vertices.len() as ID;
+    bdd.vertices.push(vertex);
+    bdd.ref_counts.push(0);
+    bdd.dead_count += 1;
+
+    let var = vertex.var as usize;
+    if bdd.unique_tables.len() <= var {
+        bdd.unique_tables.resize_with(var + 1, UniqueTable::default);
+    }
+    bdd.unique_tables[var].insert(&bdd.vertices, id);
+    return id;
+}
+
 fn make(bdd: &mut BDD, var: isize, mut lo: Edge, mut hi: Edge) -> Edge {
     if lo == hi {
         return lo;
@@ -126,121 +301,153 @@ fn make(bdd: &mut BDD, var: isize, mut lo: Edge, mut hi: Edge) -> Edge {
     }
 
     assert!(var > 0); // Variables should always be greater than 0
-    let tmp = Rc::new(Vertex {var, lo: Some(lo), hi: Some(hi)});
-    if let Some(id) = bdd.vertex_lookup.get(&tmp) {
-        let ret = *id;
-        inc_ref(bdd, &ret);
-        return ret;
+    let existing = bdd.unique_tables.get(var as usize).and_then(|table| table.find(&bdd.vertices, lo, hi));
+    if let Some(id) = existing {
+        return id * complement_flag;
     } else {
-        let id = isize::try_from(bdd.vertex_lookup.len()).unwrap() + 1;
-        bdd.vertex_lookup.insert(Rc::clone(&tmp), id);
-        bdd.id_lookup.insert(id, tmp);
-        bdd.ref_counts.insert(id, 1);
+        let id = add_vertex(bdd, Vertex {var, lo, hi});
         return id * complement_flag;
     }
 }
 
 
+// Binary operators are rewritten in terms of ite
 pub fn apply(bdd: &mut BDD, op: &Operator, lhs: isize, rhs: isize) -> isize { // Functions passed into apply are no longer roots
-    // dec_ref(self, &lhs);
-    // dec_ref(self, &rhs);
+    match op {
+        Operator::AND => ite(bdd, lhs, rhs, -1),
+        Operator::OR => ite(bdd, lhs, 1, rhs),
+        Operator::XOR => ite(bdd, lhs, -rhs, rhs),
+        _ => panic!("Non-binary operator {:?} is passed into bin op apply", op)
+    }
+}
 
-    let parity = lhs * rhs;
-    if parity > 0 {
-        return apply_helper(bdd, op, lhs, rhs, false);
-    } else {
-        return apply_helper(bdd, op, lhs, rhs, true);
+// If-then-else: (f & g) | (~f & h)
+pub fn ite(bdd: &mut BDD, f: isize, g: isize, h: isize) -> isize {
+    match standard_triple(f, g, h, |id| top_var(bdd, id)) {
+        IteForm::Done(res) => res,
+        IteForm::Triple(f, g, h, complement_flag) => ite_helper(bdd, f, g, h) * complement_flag
     }
 }
 
-fn apply_helper(bdd: &mut BDD, op: &Operator, lhs: isize, rhs: isize, mut complemented: bool) -> isize {
+// Result of bringing an ite call into standard form
+pub(crate) enum IteForm {
+    Done(isize), // Terminal case; no recursion is needed
+    Triple(isize, isize, isize, isize) // Regular f and g, then h, and the complement to apply to the result
+}
 
-    let expr = Expr {op: *op, lhs, rhs}; if bdd.computed_cache.contains_key(&expr) {
-        let id = *bdd.computed_cache.get(&expr).unwrap();
-        inc_ref(bdd, &id);
+// Rewrites ite(f, g, h) so that equivalent calls share one computed table entry
+// top_var gives the variable at the top of an edge, with terminals below every variable
+pub(crate) fn standard_triple(f: isize, g: isize, h: isize, top_var: impl Fn(isize) -> isize) -> IteForm {
+    // Terminal cases
+    if f == 1 {
+        return IteForm::Done(g);
+    } else if f == -1 {
+        return IteForm::Done(h);
+    } else if g == h {
+        return IteForm::Done(g);
+    }
+
+    // Operands equal to f (or its complement) can be replaced by constants
+    let (mut f, mut g, mut h) = (f, g, h);
+    if g == f {
+        g = 1;
+    } else if g == -f {
+        g = -1;
+    }
+
+    if h == f {
+        h = -1;
+    } else if h == -f {
+        h = 1;
+    }
+
+    if g == h {
+        return IteForm::Done(g);
+    } else if g == 1 && h == -1 {
+        return IteForm::Done(f);
+    } else if g == -1 && h == 1 {
+        return IteForm::Done(-f);
+    }
+
+    // Whether a sits above b in the ordering; ties are broken by vertex ID
+    let precedes = |a: isize, b: isize| {
+        let (a_var, b_var) = (top_var(a), top_var(b));
+        a_var < b_var || (a_var == b_var && a.abs() < b.abs())
+    };
+
+    // Standard triples: of the equivalent argument orders, put the operand with the topmost variable first
+    if g == 1 { // f | h
+        if precedes(h, f) { (f, h) = (h, f); }
+    } else if h == -1 { // f & g
+        if precedes(g, f) { (f, g) = (g, f); }
+    } else if h == 1 { // ~f | g
+        if precedes(g, f) { (f, g) = (-g, -f); }
+    } else if g == -1 { // ~f & h
+        if precedes(h, f) { (f, h) = (-h, -f); }
+    } else if g == -h { // f ^ h
+        if precedes(g, f) { (f, g, h) = (g, f, -f); }
+    }
+
+    // The first operand is kept regular by swapping the branches
+    if f < 0 {
+        f = -f;
+        (g, h) = (h, g);
+    }
+
+    // The second operand is kept regular by complementing the result instead
+    let mut complement_flag: isize = 1;
+    if g < 0 {
+        g = -g;
+        h = -h;
+        complement_flag = -1;
+    }
+
+    return IteForm::Triple(f, g, h, complement_flag);
+}
+
+fn ite_helper(bdd: &mut BDD, f: isize, g: isize, h: isize) -> isize {
+    let expr = Expr {f, g, h};
+    if let Some(id) = bdd.computed_cache.get(&expr) {
         return id;
+    }
 
-    // The following 4 cases are the special cases lifted straight from Bryant; best to refactor at some point later
-    } else if lhs.abs() == 1 && rhs.abs() == 1 { // Both LHS and RHS are leaves
+    let min_var = top_var(bdd, f).min(top_var(bdd, g)).min(top_var(bdd, h));
 
-        let mut lhs_val = get_id_bool(lhs);
-        let mut rhs_val = get_id_bool(rhs);
-        if complemented {
-            lhs_val = !lhs_val;
-            rhs_val = !rhs_val;
-        }
+    let (f_lo, f_hi) = cofactors(bdd, f, min_var);
+    let (g_lo, g_hi) = cofactors(bdd, g, min_var);
+    let (h_lo, h_hi) = cofactors(bdd, h, min_var);
 
-        match op {
-            Operator::AND => get_const_id(lhs_val && rhs_val),
-            Operator::OR => get_const_id(lhs_val || rhs_val),
-            Operator::XOR => get_const_id(lhs_val ^ rhs_val),
-            _ => panic!("Unary operator NOT is passed into bin op apply")
-        }
-        
-    } else if lhs.abs() == 1 || rhs.abs() == 1 { // Either only one of LHS or RHS are leaves
-        if complemented { // The leaf is the 0-terminal (complemented 1-terminal)
-            match (op, lhs, rhs) {
-                (Operator::AND, 1|-1, _) | (Operator::AND, _, 1|-1) => -1,
-                (Operator::OR | Operator::XOR, 1|-1, rhs) => rhs,
-                (Operator::OR | Operator::XOR, lhs, 1|-1) => lhs,
-                _ => panic!("Encountered invalid case in apply")
-            }
-        } else {
-            // dbg!(&lhs);
-            // dbg!(&rhs);
-            match (op, lhs, rhs) { // The leaf is the 1-terminal (not complemented)
-                (Operator::AND, 1|-1, rhs) => rhs,
-                (Operator::AND, lhs, 1|-1) => lhs,
-                (Operator::OR, 1|-1, _) | (Operator::OR, _, 1|-1) => 1,
-                (Operator::XOR, 1|-1, rhs) => -rhs,
-                (Operator::XOR, lhs, 1|-1) => -lhs,
-                _ => panic!("Encountered invalid case in apply")
-            }
-        }
-    } else if lhs == rhs { // LHS is equivalent to RHS
-        match op {
-            Operator::AND => lhs,
-            Operator::OR => 1,
-            Operator::XOR => -1,
-            _ => panic!("Unary operator NOT is passed into bin op apply")
-        }
-    } else if lhs.abs() == rhs.abs() { // LHS and RHS are complements to each other
-        match op {
-            Operator::AND => -1,
-            Operator::OR | Operator::XOR => 1,
-            _ => panic!("Unary operator NOT is passed into bin op apply")
-        }
-    } else { // Not in special case; recursively calculating cofactors
-        let lhs_vertex = Rc::clone(bdd.id_lookup.get(&lhs.abs()).unwrap());
-        let rhs_vertex = Rc::clone(bdd.id_lookup.get(&rhs.abs()).unwrap());
-
-        let lhs_var: isize = lhs_vertex.var;
-        let rhs_var: isize = rhs_vertex.var;
-        let min_var: isize = if lhs_var < rhs_var { lhs_var } else { rhs_var };
-
-        let (mut lhs_lo, mut lhs_hi) = (lhs, lhs);
-        let (mut rhs_lo, mut rhs_hi) = (rhs, rhs);
-        if lhs_var == min_var {
-            (lhs_lo, lhs_hi) = (lhs_vertex.lo.unwrap(), lhs_vertex.hi.unwrap());
-            dec_ref(bdd, &lhs); // dec_ref the root(s) that is being split
-        }
+    let hi_cofactor = ite(bdd, f_hi, g_hi, h_hi);
+    let lo_cofactor = ite(bdd, f_lo, g_lo, h_lo);
 
-        if rhs_var == min_var {
-            (rhs_lo, rhs_hi) = (rhs_vertex.lo.unwrap(), rhs_vertex.hi.unwrap());
-            dec_ref(bdd, &rhs); // dec_ref the root(s) that is being split
-        }
+    let res = make(bdd, min_var, lo_cofactor, hi_cofactor);
 
-        let hi_cofactor = apply_helper(bdd, op, lhs_hi, rhs_hi, complemented);
+    bdd.computed_cache.insert(expr, res);
+    return res;
+}
 
-        if (lhs_lo * rhs_lo) < 0 {
-            complemented = !complemented;
-        }
-        let lo_cofactor = apply_helper(bdd, op, lhs_lo, rhs_lo, complemented);
+// Returns the variable at the top of the given edge; terminals sit below every variable
+fn top_var(bdd: &BDD, id: isize) -> isize {
+    if id.abs() == 1 {
+        return isize::MAX;
+    }
 
-        let res = make(bdd, min_var, lo_cofactor, hi_cofactor);
+    return bdd.vertices[id.unsigned_abs()].var;
+}
 
-        bdd.computed_cache.insert(Expr {op: *op, lhs, rhs}, res);
-        return res;
+// Returns the (lo, hi) cofactors of an edge with respect to var
+// A complemented edge passes its complement down onto both children
+fn cofactors(bdd: &BDD, id: isize, var: isize) -> (isize, isize) {
+    if top_var(bdd, id) != var {
+        return (id, id);
+    }
+
+    let vertex = &bdd.vertices[id.unsigned_abs()];
+    let (lo, hi) = (vertex.lo, vertex.hi);
+    if id < 0 {
+        return (-lo, -hi);
+    } else {
+        return (lo, hi);
     }
 }
 
@@ -256,37 +463,42 @@ fn get_id_bool(val: isize) -> bool {
     return val == 1
 }
 
-fn inc_ref (bdd: &mut BDD, id: &isize) {
-    let abs_id = id.abs();
+// Reference counts only move when something outside the BDD takes or releases an edge (see func::Func)
+// A live vertex holds one reference on each child, so a vertex that comes alive or dies passes the change down
+pub(crate) fn inc_ref(bdd: &mut BDD, id: &isize) {
+    let abs_id = id.unsigned_abs();
 
-    let vertex = Rc::clone(bdd.id_lookup.get(&abs_id).unwrap());
+    let vertex = bdd.vertices[abs_id];
     match vertex.var {
         0 => return,
         _ => {
-            let count = bdd.ref_counts.get_mut(&abs_id).unwrap();
+            let count = &mut bdd.ref_counts[abs_id];
             *count += 1;
-            inc_ref(bdd, &vertex.lo.unwrap());
-            inc_ref(bdd, &vertex.hi.unwrap());
+            if *count == 1 { // Vertex is being revived
+                bdd.dead_count -= 1;
+                inc_ref(bdd, &vertex.lo);
+                inc_ref(bdd, &vertex.hi);
+            }
         }
     }
 }
 
-fn dec_ref(bdd: &mut BDD, id: &isize) {
-    let abs_id = id.abs();
+pub(crate) fn dec_ref(bdd: &mut BDD, id: &isize) {
+    let abs_id = id.unsigned_abs();
 
-    let vertex = Rc::clone(bdd.id_lookup.get(&abs_id).unwrap());
+    let vertex = bdd.vertices[abs_id];
     match vertex.var {
         0  => return,
         _ => {
-            let count = bdd.ref_counts.get_mut(&abs_id).unwrap();
+            let count = &mut bdd.ref_counts[abs_id];
+            assert!(*count > 0, "Released a reference to dead vertex {abs_id}");
             *count -= 1;
 
             if *count == 0 {
-                bdd.dead_count += 1
+                bdd.dead_count += 1;
+                dec_ref(bdd, &vertex.lo);
+                dec_ref(bdd, &vertex.hi);
             }
-
-            dec_ref(bdd, &vertex.lo.unwrap());
-            dec_ref(bdd, &vertex.hi.unwrap());
         }
     }
 }
@@ -295,33 +507,118 @@ fn dec_ref(bdd: &mut BDD, id: &isize) {
 // TODO: For the time being, it uses the ast_order to determine variable ordering
 // The output is a BDD with the target equations built into it
 pub fn build(mut bdd: BDD, parser: &Parser) -> BDD {
+    for var in parser.ast_order.keys() {
+        ensure_var(&mut bdd, *var);
+    }
+
+    let order_map = bdd.ordering.clone();
+    let mut shared: HashMap<(usize, Vec<isize>), isize> = HashMap::new();
     for e in parser.exprs.iter() {
-        bdd = build_helper(bdd, &e.rpn, &parser.ast_order);
+        bdd = build_helper(bdd, &e.shared_rpn, &order_map, &parser.defs, &mut shared);
     }
 
     return bdd;
 }
 
-fn build_helper(mut bdd: BDD, eq: &Vec<Token>, order_map: &LinkedHashMap<char, usize>) -> BDD {
+// Builds the session's care expression, or the constant true when there is none
+// Like the results of apply, the edge is not a root and holds no reference
+pub fn build_care(bdd: &mut BDD, parser: &Parser) -> isize {
+    match parser.care.as_ref() {
+        None => 1,
+        Some(care) => {
+            for var in parser.ast_order.keys() {
+                ensure_var(bdd, *var);
+            }
+            let order_map = bdd.ordering.clone();
+            build_rpn(bdd, &care.shared_rpn, &order_map, &parser.defs, &[], &mut HashMap::new())
+        }
+    }
+}
+
+// Whether f and g agree everywhere care holds: f & care == g & care
+pub fn equal_under(bdd: &mut BDD, f: isize, g: isize, care: isize) -> bool {
+    return apply(bdd, &Operator::AND, f, care) == apply(bdd, &Operator::AND, g, care);
+}
+
+// Builds expression ndx of the parser as a new root, for sessions that grow one expression at a time
+pub fn add_root(bdd: &mut BDD, parser: &Parser, ndx: usize) {
+    let root = build_expr(bdd, parser, ndx);
+    inc_ref(bdd, &root);
+    bdd.roots.push(root);
+}
+
+// Rebuilds root ndx from the parser's expression ndx and releases the old root's references
+// The new root is referenced first, so vertices it shares with the old one never die in between
+pub fn replace_root(bdd: &mut BDD, parser: &Parser, ndx: usize) {
+    let root = build_expr(bdd, parser, ndx);
+    inc_ref(bdd, &root);
+    let old = std::mem::replace(&mut bdd.roots[ndx], root);
+    dec_ref(bdd, &old);
+}
+
+pub fn remove_root(bdd: &mut BDD, ndx: usize) {
+    let old = bdd.roots.remove(ndx);
+    dec_ref(bdd, &old);
+}
+
+fn build_expr(bdd: &mut BDD, parser: &Parser, ndx: usize) -> isize {
+    for var in parser.ast_order.keys() {
+        ensure_var(bdd, *var);
+    }
+    let order_map = bdd.ordering.clone();
+    return build_rpn(bdd, &parser.exprs[ndx].shared_rpn, &order_map, &parser.defs, &[], &mut HashMap::new());
+}
+
+fn build_helper(mut bdd: BDD, eq: &[Token], order_map: &LinkedHashMap<char, usize>, defs: &[Definition],
+    shared: &mut HashMap<(usize, Vec<isize>), isize>) -> BDD {
+    let root = build_rpn(&mut bdd, eq, order_map, defs, &[], shared);
+    inc_ref(&mut bdd, &root);
+    bdd.roots.push(root);
+
+    return bdd;
+}
+
+// Each definition is built once per distinct list of argument edges, so a named subformula is shared by every
+// use and a macro applied to the same arguments twice is only built once
+fn build_rpn(bdd: &mut BDD, eq: &[Token], order_map: &LinkedHashMap<char, usize>, defs: &[Definition], params: &[isize],
+    shared: &mut HashMap<(usize, Vec<isize>), isize>) -> isize {
     let mut op_stack: Vec<isize> = Vec::new();
 
     for t in eq.iter() {
         match t {
+            Token::PARAM(ndx) => op_stack.push(params[*ndx]),
+            Token::DEF(id) => {
+                let args = op_stack.split_off(op_stack.len() - defs[*id].params.len());
+                let res = match shared.get(&(*id, args.clone())) {
+                    Some(res) => *res,
+                    None => {
+                        let res = build_rpn(bdd, &defs[*id].body.shared_rpn, order_map, defs, &args, shared);
+                        shared.insert((*id, args), res);
+                        res
+                    }
+                };
+                op_stack.push(res);
+            },
             Token::VAR(c) => {
-                let var_num = order_map.get(c).unwrap().clone();
+                let var_num = *order_map.get(c).unwrap();
                 assert!(var_num > 0);
-                let node_id = make(&mut bdd, var_num as isize, -1, 1);
+                let node_id = make(bdd, var_num as isize, -1, 1);
                 op_stack.push(node_id);
             },
             Token::OP(op) => {
                 if *op == Operator::NOT {
-                    let top = op_stack.get_mut(0).unwrap();
+                    let top = op_stack.last_mut().unwrap();
                     *top = -(*top);
+                } else if *op == Operator::ITE {
+                    let h = op_stack.pop().unwrap();
+                    let g = op_stack.pop().unwrap();
+                    let f = op_stack.pop().unwrap();
+                    let res = ite(bdd, f, g, h);
+                    op_stack.push(res);
                 } else {
                     let rhs = op_stack.pop().unwrap();
                     let lhs = op_stack.pop().unwrap();
-                    let res = apply(&mut bdd, op, lhs, rhs);
-                    dbg!(res);
+                    let res = apply(bdd, op, lhs, rhs);
                     op_stack.push(res);
                 }
             },
@@ -332,52 +629,395 @@ fn build_helper(mut bdd: BDD, eq: &Vec<Token>, order_map: &LinkedHashMap<char, u
 
     // TODO: What if there are multiple nodes left on the stack..?
     if op_stack.len() == 1 {
-        let root = op_stack.pop().unwrap();
-        bdd.roots.push(root);
-    } else {
-        panic!("No vertex left to assign as root");
+        return op_stack.pop().unwrap();
     }
+    panic!("No vertex left to assign as root");
+}
 
-    return bdd;
+// Evaluates the function rooted at the given edge
+// values is indexed by position in the BDD's ordering, like ast::Node::evaluate
+pub fn evaluate(bdd: &BDD, root: isize, values: &[bool]) -> bool {
+    let mut curr = root;
+    while curr.abs() != 1 {
+        let var = top_var(bdd, curr);
+        let (lo, hi) = cofactors(bdd, curr, var);
+        curr = if values[var as usize - 1] { hi } else { lo };
+    }
+
+    return get_id_bool(curr);
 }
 
+// Returns an assignment on which the function holds, indexed like evaluate
+// Variables that the chosen path skips are set to false
+pub fn pick_one(bdd: &BDD, root: isize) -> Option<Vec<bool>> {
+    if root == -1 {
+        return None;
+    }
+
+    let mut values = vec![false; bdd.ordering.len()];
+    let mut curr = root;
+    while curr.abs() != 1 {
+        let var = top_var(bdd, curr);
+        let (lo, hi) = cofactors(bdd, curr, var);
+        if hi != -1 {
+            values[var as usize - 1] = true;
+            curr = hi;
+        } else {
+            curr = lo;
+        }
+    }
+
+    return Some(values);
+}
+
+// Counts the satisfying assignments of root over every variable in the ordering
 pub fn satisfy_count(bdd: &BDD, root: isize) -> usize {
+    let num_vars = bdd.ordering.len() as u32;
+    let mut memo: HashMap<isize, usize> = HashMap::new();
+    let count = count_helper(bdd, root, num_vars, &mut memo);
+
+    // Variables above the root are free
+    return count * 2usize.pow(var_level(bdd, root, num_vars) - 1);
+}
 
+// Level of the variable at the top of an edge; terminals sit one below the last variable
+fn var_level(bdd: &BDD, id: isize, num_vars: u32) -> u32 {
+    if id.abs() == 1 {
+        return num_vars + 1;
+    }
+
+    return top_var(bdd, id) as u32;
+}
+
+// Counts assignments to the variables from the edge's level downwards
+fn count_helper(bdd: &BDD, id: isize, num_vars: u32, memo: &mut HashMap<isize, usize>) -> usize {
+    if id.abs() == 1 {
+        return if id == 1 { 1 } else { 0 };
+    } else if id < 0 { // Complemented edges count the remaining assignments
+        let level = var_level(bdd, id, num_vars);
+        return 2usize.pow(num_vars - level + 1) - count_helper(bdd, -id, num_vars, memo);
+    } else if let Some(count) = memo.get(&id) {
+        return *count;
+    }
 
-        
+    let vertex = bdd.vertices[id as usize];
+    let level = vertex.var as u32;
+    let (lo, hi) = (vertex.lo, vertex.hi);
 
-    return 0;
+    let lo_sat = 2usize.pow(var_level(bdd, lo, num_vars) - level - 1);
+    let hi_sat = 2usize.pow(var_level(bdd, hi, num_vars) - level - 1);
+
+    let count = lo_sat * count_helper(bdd, lo, num_vars, memo) + hi_sat * count_helper(bdd, hi, num_vars, memo);
+    memo.insert(id, count);
+    return count;
 }
 
-fn count_helper(bdd: &BDD, vertex_id: isize, mut complemented: bool) -> usize {
-    if vertex_id.abs() == 1 {
-        if complemented { return 1; } else { return 0; }
-    } else {
-        let vertex = bdd.id_lookup.get(&vertex_id.abs()).unwrap();
-        let lo_var = bdd.id_lookup.get(&vertex.lo.unwrap()).unwrap().var;
-        let hi_var = bdd.id_lookup.get(&vertex.hi.unwrap()).unwrap().var;
-        let mut lo_sat: usize = 2usize.pow(u32::try_from(lo_var).unwrap() - u32::try_from(vertex.var).unwrap() - 1);
-        let mut hi_sat: usize = 2usize.pow(u32::try_from(hi_var).unwrap() - u32::try_from(vertex.var).unwrap() - 1);
-
-        if vertex.lo.unwrap().abs() == 1 {
-            lo_sat = 2usize.pow(bdd.ordering.len() as u32 - u32::try_from(vertex.var).unwrap());
+// Builds the cube of the named variables, skipping any the BDD does not know about
+fn make_cube(bdd: &mut BDD, vars: &[char]) -> isize {
+    let mut levels: Vec<usize> = vars.iter().filter_map(|c| bdd.ordering.get(c).copied()).collect();
+    levels.sort_unstable();
+    levels.dedup();
+
+    let mut cube = 1;
+    for level in levels.into_iter().rev() {
+        cube = make(bdd, level as isize, -1, cube);
+    }
+
+    return cube;
+}
+
+// Existentially quantifies vars out of f: f[v := 0] | f[v := 1] for each v
+pub fn exists(bdd: &mut BDD, vars: &[char], f: isize) -> isize {
+    let cube = make_cube(bdd, vars);
+    return quant_helper(bdd, QuantOp::Exists, f, cube);
+}
+
+// Universally quantifies vars out of f: f[v := 0] & f[v := 1] for each v
+pub fn forall(bdd: &mut BDD, vars: &[char], f: isize) -> isize {
+    let cube = make_cube(bdd, vars);
+    return quant_helper(bdd, QuantOp::Forall, f, cube);
+}
+
+// Relational product: computes exists(vars, f & g) without building f & g in full
+pub fn and_exists(bdd: &mut BDD, vars: &[char], f: isize, g: isize) -> isize {
+    let cube = make_cube(bdd, vars);
+    return and_exists_helper(bdd, f, g, cube);
+}
+
+// Drops the variables from the top of the cube that sit above var; they do not occur below it
+fn skip_cube(bdd: &BDD, mut cube: isize, var: isize) -> isize {
+    while cube != 1 && top_var(bdd, cube) < var {
+        cube = cofactors(bdd, cube, top_var(bdd, cube)).1;
+    }
+
+    return cube;
+}
+
+fn quant_helper(bdd: &mut BDD, op: QuantOp, f: isize, cube: isize) -> isize {
+    if f.abs() == 1 {
+        return f;
+    }
+
+    let var = top_var(bdd, f);
+    let cube = skip_cube(bdd, cube, var);
+    if cube == 1 {
+        return f;
+    }
+
+    let expr = QuantExpr {op, lhs: f, rhs: 1, cube};
+    if let Some(id) = bdd.quant_cache.get(&expr) {
+        return id;
+    }
+
+    let (lo, hi) = cofactors(bdd, f, var);
+    let res = if top_var(bdd, cube) == var {
+        let (combine, absorbing) = match op {
+            QuantOp::Exists => (Operator::OR, 1),
+            QuantOp::Forall => (Operator::AND, -1),
+            QuantOp::AndExists => unreachable!("and_exists is handled by and_exists_helper")
+        };
+
+        let rest = cofactors(bdd, cube, var).1;
+        let lo_res = quant_helper(bdd, op, lo, rest);
+        if lo_res == absorbing {
+            absorbing
+        } else {
+            let hi_res = quant_helper(bdd, op, hi, rest);
+            apply(bdd, &combine, lo_res, hi_res)
         }
+    } else {
+        let lo_res = quant_helper(bdd, op, lo, cube);
+        let hi_res = quant_helper(bdd, op, hi, cube);
+        make(bdd, var, lo_res, hi_res)
+    };
+
+    bdd.quant_cache.insert(expr, res);
+    return res;
+}
+
+fn and_exists_helper(bdd: &mut BDD, f: isize, g: isize, cube: isize) -> isize {
+    if f == -1 || g == -1 || f == -g {
+        return -1;
+    } else if f == 1 || f == g {
+        return quant_helper(bdd, QuantOp::Exists, g, cube);
+    } else if g == 1 {
+        return quant_helper(bdd, QuantOp::Exists, f, cube);
+    }
 
-        if vertex.hi.unwrap().abs() == 1 {
-            hi_sat = 2usize.pow(bdd.ordering.len() as u32 - u32::try_from(vertex.var).unwrap());
+    // AND is commutative, so both operand orders share a cache entry
+    let (f, g) = if f < g { (f, g) } else { (g, f) };
+    let f_var = top_var(bdd, f);
+    let g_var = top_var(bdd, g);
+    let var = if f_var < g_var { f_var } else { g_var };
+
+    let cube = skip_cube(bdd, cube, var);
+    if cube == 1 {
+        return apply(bdd, &Operator::AND, f, g);
+    }
+
+    let expr = QuantExpr {op: QuantOp::AndExists, lhs: f, rhs: g, cube};
+    if let Some(id) = bdd.quant_cache.get(&expr) {
+        return id;
+    }
+
+    let (f_lo, f_hi) = cofactors(bdd, f, var);
+    let (g_lo, g_hi) = cofactors(bdd, g, var);
+    let res = if top_var(bdd, cube) == var {
+        let rest = cofactors(bdd, cube, var).1;
+        let lo_res = and_exists_helper(bdd, f_lo, g_lo, rest);
+        if lo_res == 1 {
+            1
+        } else {
+            let hi_res = and_exists_helper(bdd, f_hi, g_hi, rest);
+            apply(bdd, &Operator::OR, lo_res, hi_res)
         }
+    } else {
+        let lo_res = and_exists_helper(bdd, f_lo, g_lo, cube);
+        let hi_res = and_exists_helper(bdd, f_hi, g_hi, cube);
+        make(bdd, var, lo_res, hi_res)
+    };
+
+    bdd.quant_cache.insert(expr, res);
+    return res;
+}
+
+// Fixes var to the given value in f
+pub fn restrict(bdd: &mut BDD, f: isize, var: char, value: bool) -> isize {
+    let level = match bdd.ordering.get(&var) {
+        Some(level) => *level as isize,
+        None => return f // f cannot depend on a variable the BDD does not know about
+    };
+
+    return restrict_helper(bdd, f, level, get_const_id(value));
+}
+
+fn restrict_helper(bdd: &mut BDD, f: isize, var: isize, value: isize) -> isize {
+    let f_var = top_var(bdd, f);
+    if f_var > var {
+        return f;
+    } else if f_var == var {
+        let (lo, hi) = cofactors(bdd, f, var);
+        return if get_id_bool(value) { hi } else { lo };
+    } else if f < 0 { // Restriction commutes with complement, so share the regular edge's entry
+        return -restrict_helper(bdd, -f, var, value);
+    }
+
+    let expr = SubstExpr {op: SubstOp::Restrict, lhs: f, rhs: value, var};
+    if let Some(id) = bdd.subst_cache.get(&expr) {
+        return id;
+    }
+
+    let (lo, hi) = cofactors(bdd, f, f_var);
+    let lo_res = restrict_helper(bdd, lo, var, value);
+    let hi_res = restrict_helper(bdd, hi, var, value);
+    let res = make(bdd, f_var, lo_res, hi_res);
+
+    bdd.subst_cache.insert(expr, res);
+    return res;
+}
+
+// Generalized cofactor f ↓ c (Coudert and Madre's constrain)
+// Agrees with f wherever c holds; constraining by the 0-terminal yields the 0-terminal
+pub fn constrain(bdd: &mut BDD, f: isize, c: isize) -> isize {
+    if c == -1 {
+        return -1;
+    } else if c == 1 || f.abs() == 1 {
+        return f;
+    } else if f == c {
+        return 1;
+    } else if f == -c {
+        return -1;
+    } else if f < 0 {
+        return -constrain(bdd, -f, c);
+    }
 
-        let hi_count = hi_sat * count_helper(bdd, vertex.hi.unwrap(), complemented);
+    let expr = SubstExpr {op: SubstOp::Constrain, lhs: f, rhs: c, var: 0};
+    if let Some(id) = bdd.subst_cache.get(&expr) {
+        return id;
+    }
+
+    let f_var = top_var(bdd, f);
+    let c_var = top_var(bdd, c);
+    let var = if f_var < c_var { f_var } else { c_var };
+
+    let (f_lo, f_hi) = cofactors(bdd, f, var);
+    let (c_lo, c_hi) = cofactors(bdd, c, var);
+    let res = if c_lo == -1 {
+        constrain(bdd, f_hi, c_hi)
+    } else if c_hi == -1 {
+        constrain(bdd, f_lo, c_lo)
+    } else {
+        let lo_res = constrain(bdd, f_lo, c_lo);
+        let hi_res = constrain(bdd, f_hi, c_hi);
+        make(bdd, var, lo_res, hi_res)
+    };
+
+    bdd.subst_cache.insert(expr, res);
+    return res;
+}
+
+// Substitutes g for var in f
+pub fn compose(bdd: &mut BDD, f: isize, var: char, g: isize) -> isize {
+    let level = match bdd.ordering.get(&var) {
+        Some(level) => *level as isize,
+        None => return f
+    };
+
+    return compose_helper(bdd, f, level, g);
+}
 
-        if vertex.lo.unwrap() * vertex.hi.unwrap() < 0 {
-            complemented = !complemented;
+fn compose_helper(bdd: &mut BDD, f: isize, var: isize, g: isize) -> isize {
+    let f_var = top_var(bdd, f);
+    if f_var > var {
+        return f;
+    } else if f < 0 {
+        return -compose_helper(bdd, -f, var, g);
+    }
+
+    let expr = SubstExpr {op: SubstOp::Compose, lhs: f, rhs: g, var};
+    if let Some(id) = bdd.subst_cache.get(&expr) {
+        return id;
+    }
+
+    let res = if f_var == var {
+        let (lo, hi) = cofactors(bdd, f, var);
+        ite(bdd, g, hi, lo)
+    } else { // g may sit above f, so both are split on whichever variable comes first
+        let g_var = top_var(bdd, g);
+        let top = if f_var < g_var { f_var } else { g_var };
+        let (f_lo, f_hi) = cofactors(bdd, f, top);
+        let (g_lo, g_hi) = cofactors(bdd, g, top);
+        let lo_res = compose_helper(bdd, f_lo, var, g_lo);
+        let hi_res = compose_helper(bdd, f_hi, var, g_hi);
+        make(bdd, top, lo_res, hi_res)
+    };
+
+    bdd.subst_cache.insert(expr, res);
+    return res;
+}
+
+// Renders the graph reachable from the given (label, root) pairs in Graphviz DOT format
+// Complemented edges are dotted and the rest solid; low edges end in a hollow arrowhead, high edges in a filled one
+pub fn to_dot(bdd: &BDD, roots: &[(&str, isize)]) -> String {
+    let mut names: HashMap<isize, char> = HashMap::with_capacity(bdd.ordering.len());
+    for (c, level) in bdd.ordering.iter() {
+        names.insert(*level as isize, *c);
+    }
+    let var_name = |var: isize| names.get(&var).map(|c| c.to_string()).unwrap_or(format!("x{var}"));
+
+    // Collect every vertex reachable from the roots, grouped by level
+    let mut levels: BTreeMap<isize, Vec<isize>> = BTreeMap::new();
+    let mut seen: HashSet<isize> = HashSet::new();
+    let mut stack: Vec<isize> = roots.iter().map(|(_, root)| root.abs()).collect();
+    while let Some(id) = stack.pop() {
+        if id == 1 || !seen.insert(id) {
+            continue;
         }
 
-        let lo_count = lo_sat * count_helper(bdd, vertex.hi.unwrap(), complemented);
+        let vertex = &bdd.vertices[id as usize];
+        levels.entry(vertex.var).or_default().push(id);
+        stack.push(vertex.lo.abs());
+        stack.push(vertex.hi.abs());
+    }
 
-        return lo_count + hi_count;
+    let edge_style = |id: isize, lo: bool| match (id < 0, lo) {
+        (true, true) => " [style=dotted, arrowhead=empty]",
+        (true, false) => " [style=dotted]",
+        (false, true) => " [arrowhead=empty]",
+        (false, false) => ""
+    };
 
+    let mut out = String::from("digraph BDD {\n");
+    out.push_str("    node [shape=circle];\n");
+
+    // Level labels are chained invisibly so each variable gets its own row
+    let level_labels: Vec<String> = levels.keys().map(|var| format!("\"L{var}\"")).collect();
+    if !level_labels.is_empty() {
+        out.push_str(&format!("    {{ node [shape=plaintext]; edge [style=invis]; {}; }}\n", level_labels.join(" -> ")));
+    }
+
+    for (ndx, (label, root)) in roots.iter().enumerate() {
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("    r{ndx} [shape=plaintext, label=\"{label}\"];\n"));
+        out.push_str(&format!("    r{ndx} -> n{}{};\n", root.abs(), edge_style(*root, false)));
+    }
+
+    for (var, ids) in levels.iter_mut() {
+        ids.sort_unstable();
+        let members: Vec<String> = ids.iter().map(|id| format!("n{id}")).collect();
+        out.push_str(&format!("    {{ rank=same; \"L{var}\" [label=\"{}\"]; {}; }}\n", var_name(*var), members.join("; ")));
+        for id in ids.iter() {
+            let vertex = &bdd.vertices[*id as usize];
+            let (lo, hi) = (vertex.lo, vertex.hi);
+            out.push_str(&format!("    n{id} [label=\"{}\"];\n", var_name(*var)));
+            out.push_str(&format!("    n{id} -> n{}{};\n", lo.abs(), edge_style(lo, true)));
+            out.push_str(&format!("    n{id} -> n{}{};\n", hi.abs(), edge_style(hi, false)));
+        }
     }
+
+    out.push_str("    { rank=sink; n1 [shape=box, label=\"1\"]; }\n");
+    out.push_str("}\n");
+    return out;
 }
 
 #[cfg(test)]
@@ -393,10 +1033,8 @@ mod test {
         let lo = 1;
         let hi = -1;
 
-        let expected_node = Rc::new(Vertex {var, lo: Some(-lo), hi: Some(-hi)});
-        expected.vertex_lookup.insert(expected_node.clone(), 2);
-        expected.id_lookup.insert(2, expected_node);
-        expected.ref_counts.insert(2, 1);
+        let expected_node = Vertex {var, lo: -lo, hi: -hi};
+        assert_eq!(2, add_vertex(&mut expected, expected_node));
 
         let actual_id = make(&mut actual, var, lo, hi);
         dbg!(&expected);
@@ -414,18 +1052,13 @@ mod test {
         // dbg!(&actual);
         let mut expected = actual.clone();
 
-        let expected_node = Rc::new(Vertex {var:1, lo: Some(-1), hi: Some(3)});
-        expected.vertex_lookup.insert(expected_node.clone(), 4);
-        expected.id_lookup.insert(4, expected_node);
-        expected.computed_cache.insert(Expr {op: Operator::AND, lhs: 2, rhs: 3}, 4);
-        let rc = expected.ref_counts.get_mut(&2).unwrap();
-        *rc = 0;
-        expected.ref_counts.insert(4, 1);
-        expected.dead_count = 1;
+        let expected_node = Vertex {var:1, lo: -1, hi: 3};
+        assert_eq!(4, add_vertex(&mut expected, expected_node));
+        expected.computed_cache.insert(Expr {f: 2, g: 3, h: -1}, 4);
 
         let actual_id = apply(&mut actual, &Operator::AND, 2, 3);
-        dbg!(&expected.vertex_lookup);
-        dbg!(&actual.vertex_lookup);
+        dbg!(&expected.vertices);
+        dbg!(&actual.vertices);
         assert!(expected == actual);
         assert!(4 == actual_id);
     }
@@ -439,14 +1072,9 @@ mod test {
         // dbg!(&actual);
         let mut expected = actual.clone();
 
-        let expected_node = Rc::new(Vertex {var:1, lo: Some(-3), hi: Some(3)});
-        expected.vertex_lookup.insert(expected_node.clone(), 4);
-        expected.id_lookup.insert(4, expected_node);
-        expected.computed_cache.insert(Expr {op: Operator::XOR, lhs: -2, rhs: 3}, 4);
-        let rc = expected.ref_counts.get_mut(&2).unwrap();
-        *rc = 0;
-        expected.ref_counts.insert(4, 1);
-        expected.dead_count = 1;
+        let expected_node = Vertex {var:1, lo: -3, hi: 3};
+        assert_eq!(4, add_vertex(&mut expected, expected_node));
+        expected.computed_cache.insert(Expr {f: 2, g: 3, h: -3}, 4);
 
         let actual_id = apply(&mut actual, &Operator::XOR, -2, 3);
         // dbg!(&expected);
@@ -464,14 +1092,9 @@ mod test {
         // dbg!(&actual);
         let mut expected = actual.clone();
 
-        let expected_node = Rc::new(Vertex {var:1, lo: Some(-3), hi: Some(3)});
-        expected.vertex_lookup.insert(expected_node.clone(), 4);
-        expected.id_lookup.insert(4, expected_node);
-        expected.computed_cache.insert(Expr {op: Operator::XOR, lhs: 2, rhs: 3}, -4);
-        let rc = expected.ref_counts.get_mut(&2).unwrap();
-        *rc = 0;
-        expected.ref_counts.insert(4, 1);
-        expected.dead_count = 1;
+        let expected_node = Vertex {var:1, lo: -3, hi: 3};
+        assert_eq!(4, add_vertex(&mut expected, expected_node));
+        expected.computed_cache.insert(Expr {f: 2, g: 3, h: -3}, 4);
 
         let actual_id = apply(&mut actual, &Operator::XOR, 2, 3);
         // dbg!(&expected);
@@ -493,10 +1116,265 @@ mod test {
         let mut expected_bdd = actual_bdd.clone();
         let lhs = make(&mut expected_bdd, 1, -1, 1);
         let rhs = make(&mut expected_bdd, 2, -1, 1);
-        apply(&mut expected_bdd, &Operator::AND, lhs, rhs);
+        let root = apply(&mut expected_bdd, &Operator::AND, lhs, rhs);
+        inc_ref(&mut expected_bdd, &root);
 
-        actual_bdd = build_helper(actual_bdd, &eq, &order);
+        actual_bdd = build_helper(actual_bdd, &eq, &order, &[], &mut HashMap::new());
         assert_eq!(actual_bdd, expected_bdd);
     }
 
-}
\ No newline at end of file
+    fn build_exprs(exprs: &[&str]) -> BDD {
+        let parser = crate::parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+        build(BDD::new(), &parser)
+    }
+
+    #[test]
+    fn build_matches_truth_table() {
+        let bdd = build_exprs(&["(a & b) | ~(c ^ (a & d))", "~a & ~b"]);
+        for case in 0..16 {
+            let values: Vec<bool> = (0..4).map(|i| case & (1 << i) != 0).collect();
+            let (a, b, c, d) = (values[0], values[1], values[2], values[3]);
+            assert_eq!((a && b) || !(c ^ (a && d)), evaluate(&bdd, bdd.roots[0], &values));
+            assert_eq!(!a && !b, evaluate(&bdd, bdd.roots[1], &values));
+        }
+        assert_eq!(10, satisfy_count(&bdd, bdd.roots[0]));
+        assert_eq!(4, satisfy_count(&bdd, bdd.roots[1]));
+    }
+
+    #[test]
+    fn add_var_starts_at_level_one() {
+        let mut bdd = BDD::new();
+        add_var(&mut bdd, 'a');
+        add_var(&mut bdd, 'b');
+        assert_eq!(Some(&1), bdd.ordering.get(&'a'));
+        assert_eq!(Some(&2), bdd.ordering.get(&'b'));
+        assert_eq!(2, bdd.roots.len());
+    }
+
+    #[test]
+    fn build_orders_variables_as_parsed() {
+        let bdd = build_exprs(&["c & a", "b | a"]);
+        let levels: Vec<(char, usize)> = bdd.ordering.iter().map(|(c, level)| (*c, *level)).collect();
+        assert_eq!(vec![('c', 1), ('a', 2), ('b', 3)], levels);
+        assert_eq!(2, bdd.roots.len());
+    }
+
+    #[test]
+    fn make_reuses_complemented_vertex() {
+        let mut bdd = BDD::new();
+        let first = make(&mut bdd, 1, 1, -1);
+        assert_eq!(-2, first);
+        assert_eq!(first, make(&mut bdd, 1, 1, -1));
+        assert_eq!(-first, make(&mut bdd, 1, -1, 1));
+    }
+
+    #[test]
+    fn not_negates_the_top_of_the_stack() {
+        let mut bdd = build_exprs(&["a & ~b"]);
+        let a = make(&mut bdd, 1, -1, 1);
+        let b = make(&mut bdd, 2, -1, 1);
+        assert_eq!(apply(&mut bdd, &Operator::AND, a, -b), bdd.roots[0]);
+    }
+
+    #[test]
+    fn dead_vertices_are_counted_once() {
+        let mut bdd = BDD::new();
+        let a = make(&mut bdd, 1, -1, 1);
+        assert_eq!(1, bdd.dead_count);
+        inc_ref(&mut bdd, &a);
+        inc_ref(&mut bdd, &a);
+        assert_eq!(0, bdd.dead_count);
+        dec_ref(&mut bdd, &a);
+        assert_eq!(0, bdd.dead_count);
+        dec_ref(&mut bdd, &a);
+        assert_eq!(1, bdd.dead_count);
+    }
+
+    #[test]
+    fn or_with_itself_is_identity() {
+        let mut bdd = BDD::new();
+        let a = make(&mut bdd, 1, -1, 1);
+        assert_eq!(a, apply(&mut bdd, &Operator::OR, a, a));
+        assert_eq!(-a, apply(&mut bdd, &Operator::OR, -a, -a));
+    }
+
+    #[test]
+    fn satisfy_count_handles_complements_and_constants() {
+        let bdd = build_exprs(&["(a & b) | ~(c ^ (a & d))", "c & d"]);
+        assert_eq!(6, satisfy_count(&bdd, -bdd.roots[0]));
+        assert_eq!(4, satisfy_count(&bdd, bdd.roots[1]));
+        assert_eq!(16, satisfy_count(&bdd, 1));
+        assert_eq!(0, satisfy_count(&bdd, -1));
+    }
+
+    #[test]
+    fn exists_forall() {
+        let mut bdd = build_exprs(&["(a & b) | (~a & c)", "b | c", "b & c"]);
+        let (f, disj, conj) = (bdd.roots[0], bdd.roots[1], bdd.roots[2]);
+
+        assert_eq!(disj, exists(&mut bdd, &['a'], f));
+        assert_eq!(conj, forall(&mut bdd, &['a'], f));
+        assert_eq!(1, exists(&mut bdd, &['a', 'b', 'c'], f));
+        assert_eq!(-1, forall(&mut bdd, &['a', 'b', 'c'], f));
+        assert_eq!(f, exists(&mut bdd, &[], f));
+    }
+
+    #[test]
+    fn quantification_is_ref_neutral() {
+        let parser = crate::parser::create_session(vec![String::from("(a & b) | (~a & c)"), String::from("(a & c) | d")]).unwrap();
+        let mut bdd = build(BDD::new(), &parser);
+        let (f, g) = (bdd.roots[0], bdd.roots[1]);
+        let counts: Vec<usize> = (0..=bdd.vertex_count() as isize).map(|id| bdd.ref_count(id)).collect();
+        let (vertices, dead) = (bdd.vertex_count(), bdd.dead_count());
+
+        // Results are born dead like any other vertex until the caller references them
+        let res = and_exists(&mut bdd, &['a', 'c'], f, g);
+        exists(&mut bdd, &['a'], f);
+        forall(&mut bdd, &['d'], g);
+        assert_eq!(counts, (0..=vertices as isize).map(|id| bdd.ref_count(id)).collect::<Vec<usize>>());
+        assert_eq!(dead + bdd.vertex_count() - vertices, bdd.dead_count());
+        assert_eq!(0, bdd.ref_count(res));
+    }
+
+    #[test]
+    fn and_exists_matches_exists_of_and() {
+        let mut bdd = build_exprs(&["(a ^ b) | c", "(b & d) | ~c", "a | b"]);
+        let (f, g) = (bdd.roots[0], bdd.roots[1]);
+
+        let conj = apply(&mut bdd, &Operator::AND, f, g);
+        for vars in [vec!['b'], vec!['a', 'c'], vec!['b', 'c', 'd']] {
+            let expected = exists(&mut bdd, &vars, conj);
+            assert_eq!(expected, and_exists(&mut bdd, &vars, f, g));
+            assert_eq!(expected, and_exists(&mut bdd, &vars, g, f));
+        }
+    }
+
+    #[test]
+    fn restrict_fixes_variable() {
+        let mut bdd = build_exprs(&["(a & b) | (~a & c)", "b", "c", "~(a | b)"]);
+        let (f, b, c, g) = (bdd.roots[0], bdd.roots[1], bdd.roots[2], bdd.roots[3]);
+
+        assert_eq!(b, restrict(&mut bdd, f, 'a', true));
+        assert_eq!(c, restrict(&mut bdd, f, 'a', false));
+        assert_eq!(-1, restrict(&mut bdd, g, 'b', true));
+        assert_eq!(-1, restrict(&mut bdd, g, 'a', true));
+        assert_eq!(-b, restrict(&mut bdd, g, 'a', false));
+        assert_eq!(f, restrict(&mut bdd, f, 'z', true));
+    }
+
+    #[test]
+    fn constrain_agrees_on_care_set() {
+        let mut bdd = build_exprs(&["(a ^ b) | (c & d)", "a & ~d", "b | c"]);
+        let f = bdd.roots[0];
+        for c in [bdd.roots[1], bdd.roots[2]] {
+            let res = constrain(&mut bdd, f, c);
+            for case in 0..16 {
+                let values: Vec<bool> = (0..4).map(|i| case & (1 << i) != 0).collect();
+                if evaluate(&bdd, c, &values) {
+                    assert_eq!(evaluate(&bdd, f, &values), evaluate(&bdd, res, &values));
+                }
+            }
+        }
+        assert_eq!(f, constrain(&mut bdd, f, 1));
+        assert_eq!(1, constrain(&mut bdd, f, f));
+    }
+
+    #[test]
+    fn compose_substitutes() {
+        let mut bdd = build_exprs(&["c & ~b", "a | d", "(a | d) & ~b", "c & ~(a | d)"]);
+        let (f, g) = (bdd.roots[0], bdd.roots[1]);
+        let (expected_c, expected_b) = (bdd.roots[2], bdd.roots[3]);
+
+        assert_eq!(expected_c, compose(&mut bdd, f, 'c', g));
+        assert_eq!(expected_b, compose(&mut bdd, f, 'b', g));
+    }
+
+    #[test]
+    fn ite_is_canonical() {
+        let mut bdd = build_exprs(&["a ? b : c", "(a & b) | (~a & c)", "ite(~a, c, b)", "(b & c) | (a & b) | (~a & c)"]);
+        let roots = bdd.roots.clone();
+        assert!(roots.iter().all(|r| *r == roots[0]));
+
+        let f = roots[0];
+        assert_eq!(f, ite(&mut bdd, 1, f, -f));
+        assert_eq!(-f, ite(&mut bdd, f, -1, 1));
+        assert_eq!(-1, apply(&mut bdd, &Operator::AND, f, -f));
+        assert_eq!(f, apply(&mut bdd, &Operator::OR, f, f));
+    }
+
+    #[test]
+    fn dot_export() {
+        let bdd = build_exprs(&["a ^ b", "~a"]);
+        let roots = bdd.roots().to_vec();
+        let dot = to_dot(&bdd, &[("a ^ b", roots[0]), ("~a", roots[1])]);
+
+        assert!(dot.starts_with("digraph BDD {\n"));
+        assert!(dot.contains("r0 [shape=plaintext, label=\"a ^ b\"];"));
+        assert!(dot.contains("r1 -> n2 [style=dotted];"));
+        assert!(dot.contains("{ rank=same; \"L1\" [label=\"a\"]; n2"));
+        assert!(dot.contains("\"L1\" -> \"L2\""));
+        assert!(dot.contains("n3 -> n1 [style=dotted, arrowhead=empty];"));
+        assert!(dot.contains("n4 -> n3 [style=dotted, arrowhead=empty];"));
+        assert!(dot.contains("n4 -> n3;"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn cache_shares_normalized_operands() {
+        let exprs = vec![String::from("(a & b) ^ (c | d)"), String::from("(b & a) ^ (d | c)"), String::from("~(~(c | d) ^ (b & a))")];
+        let parser = crate::parser::create_session(exprs).unwrap();
+
+        let bdd = build(BDD::new(), &parser);
+        let stats = bdd.ite_cache_stats();
+        assert!(stats.hits >= 3);
+        assert_eq!(0, stats.evictions);
+        assert!(bdd.roots().iter().all(|r| *r == bdd.roots()[0]));
+
+        // A single-slot cache thrashes but still builds the same functions
+        let tiny = build(BDD::with_cache_budget(0), &parser);
+        assert!(tiny.ite_cache_stats().evictions > 0);
+        assert!(tiny.roots().iter().all(|r| *r == tiny.roots()[0]));
+        assert_eq!(satisfy_count(&bdd, bdd.roots()[0]), satisfy_count(&tiny, tiny.roots()[0]));
+    }
+
+    #[test]
+    fn unique_table_grows() {
+        let mut bdd = BDD::new();
+        let children: Vec<isize> = (2..200).map(|var| make(&mut bdd, var, -1, 1)).collect();
+        let ids: Vec<isize> = children.iter().map(|child| make(&mut bdd, 1, -1, *child)).collect();
+
+        // Every vertex on variable 1 is distinct; remaking them must find the originals after every resize
+        for (child, id) in children.iter().zip(ids.iter()) {
+            assert_eq!(*id, make(&mut bdd, 1, -1, *child));
+        }
+        assert_eq!(1 + 2 * 198, bdd.vertex_count());
+        assert_eq!(198, bdd.unique_tables[1].len);
+    }
+
+    #[test]
+    fn equality_under_a_care_set() {
+        let mut parser = crate::parser::create_session(vec![String::from("a & b"), String::from("a & c")]).unwrap();
+        let mut bdd = build(BDD::new(), &parser);
+        assert_eq!(1, build_care(&mut bdd, &parser));
+
+        parser.set_care(String::from("b ^ ~c | ~a")).unwrap();
+        let care = build_care(&mut bdd, &parser);
+        let (f, g) = (bdd.roots[0], bdd.roots[1]);
+        assert!(equal_under(&mut bdd, f, g, care));
+        assert!(!equal_under(&mut bdd, f, g, 1));
+    }
+
+    #[test]
+    fn definitions_build_once() {
+        let bdd = build_exprs(&["carry = a & b | c & (a ^ b)", "def maj(x, y, z) = (x & y) | (x & z) | (y & z)", "maj(a, b, c)", "carry",
+            "maj(c, b, a)", "a & (b | (c & (a ^ b)))"]);
+        assert_eq!(4, bdd.roots.len());
+        assert_eq!(bdd.roots[0], bdd.roots[2]);
+        assert_eq!(bdd.roots[1], bdd.roots[3]);
+
+        // The second call with the same arguments reuses the first
+        let once = build_exprs(&["def maj(x, y, z) = (x & y) | (x & z) | (y & z)", "maj(a, ~b, c)"]);
+        let twice = build_exprs(&["def maj(x, y, z) = (x & y) | (x & z) | (y & z)", "maj(a, ~b, c)", "maj(a, ~b, c)"]);
+        assert_eq!(once.vertex_count(), twice.vertex_count());
+    }
+}
@@ -1,4 +1,6 @@
 use crate::parser::*;
+use crate::pretty::Expr;
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 use std::{thread, env};
 
@@ -57,6 +59,11 @@ impl PartialEq for ASTSession {
 }
 
 pub fn build_ast_session(inputs: &Parser) -> ASTSession {
+    return build_ast_session_fixed(inputs, &[]);
+}
+
+// Builds the session only over the cases that agree with the fixed (variable, value) pairs
+pub fn build_ast_session_fixed(inputs: &Parser, fixed: &[(char, bool)]) -> ASTSession {
     let mut res = ASTSession {
         roots: Vec::new(),
         cases: Vec::new(),
@@ -70,39 +77,25 @@ pub fn build_ast_session(inputs: &Parser) -> ASTSession {
     }
 
     let mut roots = Vec::with_capacity(inputs.exprs.len());
-
-    let mut build_ast = |rpn: &Vec<Token>| {
-        let mut node_stack = Vec::with_capacity(rpn.len());
-
-        for token in rpn.iter() {
-            match token {
-                Token::VAR(c) => {
-                    let ndx = inputs.ast_order.get(c).expect("Test").clone();
-                    node_stack.push(create_var_node(ndx))
-                },
-                Token::OP(Operator::NOT) => {
-                    let child = node_stack.pop().expect("No nodes left on stack for NOT");
-                    node_stack.push(create_op_node(Operator::NOT, vec![Some(child)]));
-                },
-                Token::OP(o) => {
-                    let right_child = node_stack.pop().expect("No nodes left on stack for binop");
-                    let left_child = node_stack.pop().expect("No nodes left on stack for binop");
-                    node_stack.push(create_op_node(*o, vec![Some(left_child), Some(right_child)]));
-                }
-                _ => unreachable!("Match encountered non-op or var token in build_ast")
-            }
-        }
-
-        let root = node_stack.pop().expect("No nodes left to assign as root in build_ast");
-        roots.push(root);
-    };
-
+    let mut shared: HashMap<usize, ASTNode> = HashMap::new();
     for expr in inputs.exprs.iter() {
-        build_ast(&expr.rpn);
+        roots.push(build_ast(&expr.shared_rpn, inputs, &[], &mut shared));
     }
+    if let Some(care) = inputs.care.as_ref() {
+        roots.push(build_ast(&care.shared_rpn, inputs, &[], &mut shared));
+    }
+    let care = if inputs.care.is_some() { roots.pop() } else { None };
     res.roots = roots;
     
-    let cases = get_cases(inputs.ast_order.len());
+    // Rows outside the care set are skipped entirely, so they never show up as failures
+    let mut cases = get_cases(inputs.ast_order.len());
+    let fixed: Vec<(usize, bool)> = fixed.iter()
+        .filter_map(|(c, val)| inputs.ast_order.get(c).map(|ndx| (*ndx, *val)))
+        .collect();
+    cases.retain(|case| fixed.iter().all(|(ndx, val)| case[*ndx] == *val));
+    if let Some(care) = care {
+        cases.retain(|case| care.read().unwrap().evaluate(case));
+    }
     res.cases = cases;
 
     let disallow_thread = env::var("NO_THREAD").is_ok();
@@ -113,6 +106,60 @@ pub fn build_ast_session(inputs: &Parser) -> ASTSession {
     }
 }
 
+// The truth-table column of expression ndx alone, over the rows of the full table in the usual order
+pub fn evaluate_column(inputs: &Parser, ndx: usize) -> Vec<bool> {
+    let root = build_ast(&inputs.exprs[ndx].shared_rpn, inputs, &[], &mut HashMap::new());
+    let root = root.read().unwrap();
+    return get_cases(inputs.ast_order.len()).iter().map(|case| root.evaluate(case)).collect();
+}
+
+// Named definitions are built once and their node is shared by every use; a macro call builds the macro's body
+// with its parameters bound to the argument nodes, so arguments are shared rather than copied
+fn build_ast(rpn: &[Token], inputs: &Parser, params: &[ASTNode], shared: &mut HashMap<usize, ASTNode>) -> ASTNode {
+    let mut node_stack: Vec<ASTNode> = Vec::with_capacity(rpn.len());
+
+    for token in rpn.iter() {
+        match token {
+            Token::VAR(c) => {
+                let ndx = *inputs.ast_order.get(c).expect("Test");
+                node_stack.push(create_var_node(ndx))
+            },
+            Token::PARAM(ndx) => node_stack.push(params[*ndx].clone()),
+            Token::DEF(id) => {
+                let def = &inputs.defs[*id];
+                if def.params.is_empty() {
+                    if !shared.contains_key(id) {
+                        let node = build_ast(&def.body.shared_rpn, inputs, &[], shared);
+                        shared.insert(*id, node);
+                    }
+                    node_stack.push(shared[id].clone());
+                } else {
+                    let args = node_stack.split_off(node_stack.len() - def.params.len());
+                    node_stack.push(build_ast(&def.body.shared_rpn, inputs, &args, shared));
+                }
+            },
+            Token::OP(Operator::NOT) => {
+                let child = node_stack.pop().expect("No nodes left on stack for NOT");
+                node_stack.push(create_op_node(Operator::NOT, vec![Some(child)]));
+            },
+            Token::OP(Operator::ITE) => {
+                let else_child = node_stack.pop().expect("No nodes left on stack for ITE");
+                let then_child = node_stack.pop().expect("No nodes left on stack for ITE");
+                let cond_child = node_stack.pop().expect("No nodes left on stack for ITE");
+                node_stack.push(create_op_node(Operator::ITE, vec![Some(cond_child), Some(then_child), Some(else_child)]));
+            },
+            Token::OP(o) => {
+                let right_child = node_stack.pop().expect("No nodes left on stack for binop");
+                let left_child = node_stack.pop().expect("No nodes left on stack for binop");
+                node_stack.push(create_op_node(*o, vec![Some(left_child), Some(right_child)]));
+            }
+            _ => unreachable!("Match encountered non-op or var token in build_ast")
+        }
+    }
+
+    return node_stack.pop().expect("No nodes left to assign as root in build_ast");
+}
+
 fn evaluate_session_seq(session: ASTSession) -> ASTSession {
     let mut results: Vec<Vec<bool>> = Vec::with_capacity(session.cases.len());
     let mut cex: Vec<Vec<bool>> = Vec::with_capacity(session.cases.len() / 2); // Arbitary initial size
@@ -123,7 +170,7 @@ fn evaluate_session_seq(session: ASTSession) -> ASTSession {
             case_res.push(run_res);
         }
 
-        let res = case_res.iter().copied().reduce(|acc, e| acc == e).expect("Reduction failed");
+        let res = case_res.iter().all(|e| *e == case_res[0]);
         if !res {
             cex.push(case_res.clone());
         }
@@ -158,7 +205,7 @@ fn evaluate_session_sync(session: ASTSession) -> ASTSession {
                 case_res.push(root.clone().read().unwrap().evaluate(&case));
             }
 
-            let res = case_res.iter().copied().reduce(|acc, e| acc == e).unwrap();
+            let res = case_res.iter().all(|e| *e == case_res[0]);
             if !res && roots.len() != 1 {
                 failure.append(&mut case.clone());
                 failure.append(&mut case_res.clone());
@@ -203,7 +250,24 @@ fn create_op_node(op: Operator, children: Vec<Option<ASTNode>>) -> ASTNode {
         }
 
 impl Node {
-    pub fn evaluate(&self, values: &Vec<bool>) -> bool {
+    // Converts back into an expression tree; names are indexed like the values passed to evaluate
+    pub fn to_expr(&self, names: &[char]) -> Expr {
+        match &self {
+            Node::VAR(node) => Expr::Var(names[node.val]),
+            Node::OP(node) => {
+                let mut children = node.children.iter()
+                    .map(|child| Box::new(child.as_ref().expect("Unexpected leaf node").read().unwrap().to_expr(names)));
+                let mut next = || children.next().expect("Operator is missing an operand");
+                match node.op {
+                    Operator::NOT => Expr::Not(next()),
+                    Operator::ITE => Expr::Ite(next(), next(), next()),
+                    op => Expr::Bin(op, next(), next())
+                }
+            }
+        }
+    }
+
+    pub fn evaluate(&self, values: &[bool]) -> bool {
         // thread::sleep(time::Duration::from_millis(5));
         match &self {
             Node::VAR(node) => return values[node.val],
@@ -215,12 +279,17 @@ impl Node {
                 Operator::XOR => return node.children[0].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values) ^ 
                 node.children[1].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values),
                 Operator::NOT => return !node.children[0].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values),
+                Operator::ITE => if node.children[0].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values) {
+                    return node.children[1].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values)
+                } else {
+                    return node.children[2].as_ref().expect("Unexpected leaf node").clone().read().unwrap().evaluate(values)
+                },
             }
         }
     }
 }
 
-fn get_cases(n: usize) -> Vec<Vec<bool>> {
+pub(crate) fn get_cases(n: usize) -> Vec<Vec<bool>> {
     if n == 0 {
         return vec![vec![]]
     }
@@ -256,4 +325,68 @@ mod test {
         };
         assert_eq!(expected, res)
     }
+
+    #[test]
+    fn fixed_cases() {
+        let expr = vec![String::from("a & b"), String::from("a")];
+        let session = crate::parser::create_session(expr).unwrap();
+
+        let res = build_ast_session_fixed(&session, &[('b', true)]);
+        assert_eq!(vec![vec![false, true], vec![true, true]], res.cases);
+        assert!(res.all_eq);
+
+        let res = build_ast_session_fixed(&session, &[('b', false)]);
+        assert!(!res.all_eq);
+    }
+
+    #[test]
+    fn ternary_matches_expansion() {
+        let expr = vec![String::from("a ? b ^ c : ~c"), String::from("ite(a, b ^ c, ~c)"), String::from("(a & (b ^ c)) | (~a & ~c)")];
+        let session = crate::parser::create_session(expr).unwrap();
+        let res = build_ast_session(&session);
+        assert!(res.all_eq);
+        assert_eq!(8, res.results.len());
+    }
+
+    #[test]
+    fn care_set_skips_rows() {
+        let expr = vec![String::from("a | b"), String::from("a ^ b")];
+        let mut session = crate::parser::create_session(expr).unwrap();
+        assert!(!build_ast_session(&session).all_eq);
+
+        session.set_care(String::from("~(a & b)")).unwrap();
+        let res = build_ast_session(&session);
+        assert!(res.all_eq);
+        assert_eq!(3, res.cases.len());
+
+        session.set_care(String::from("c | ~c")).unwrap();
+        let res = build_ast_session(&session);
+        assert!(!res.all_eq);
+        assert_eq!(8, res.cases.len());
+    }
+
+    #[test]
+    fn prints_back_to_source() {
+        let expr = vec![String::from("~(a & b) | c ? ~c : b ^ a")];
+        let session = crate::parser::create_session(expr).unwrap();
+        let res = build_ast_session(&session);
+        let names: Vec<char> = session.ast_order.keys().copied().collect();
+        assert_eq!("~(a & b) | c ? ~c : b ^ a", res.roots[0].read().unwrap().to_expr(&names).to_string());
+    }
+
+    #[test]
+    fn definitions_are_shared() {
+        let expr = vec![String::from("both = a & b"), String::from("def mux(s, x, y) = s ? x : y"),
+            String::from("mux(c, both, ~both)"), String::from("both"), String::from("c ? a & b : ~(a & b)")];
+        let session = crate::parser::create_session(expr).unwrap();
+        let res = build_ast_session(&session);
+        assert_eq!(res.results.iter().map(|r| r[0]).collect::<Vec<bool>>(), res.results.iter().map(|r| r[2]).collect::<Vec<bool>>());
+
+        // The root of "both" is the same node used as the macro's then-branch
+        let root = res.roots[0].read().unwrap();
+        match &*root {
+            Node::OP(node) => assert!(Arc::ptr_eq(node.children[1].as_ref().unwrap(), &res.roots[1])),
+            Node::VAR(_) => panic!("Expected the macro body")
+        }
+    }
 }
@@ -0,0 +1,428 @@
+// Saving and loading BDDs
+// Two formats are supported; both store the variable ordering, the vertices reachable from a set of
+// named roots (children always before their parents) and complement edges as negative IDs
+//
+// The text format follows the spirit of DDDMP:
+//     .ver BOOL-EQ-DDDMP-1
+//     .nvars 2
+//     .orderedvarnames a b
+//     .nnodes 3
+//     .nroots 1
+//     .nodes
+//     1 T 0 0
+//     2 b 1 -1
+//     3 a 2 -1
+//     .roots
+//     3 a & b
+//     .end
+// Each node line is "<id> <var> <hi> <lo>"; ID 1 is always the 1-terminal
+//
+// The binary format holds the same fields as LEB128 varints, with signed edges zigzag-encoded
+
+use std::collections::{HashMap, HashSet};
+use crate::areabdd::{self, BDD};
+
+static TEXT_VERSION: &str = "BOOL-EQ-DDDMP-1";
+static BINARY_MAGIC: &[u8; 4] = b"BEQB";
+static BINARY_VERSION: u8 = 1;
+
+// Format-independent contents of a dump; node i is referenced as ID i + 2
+#[derive(Debug, PartialEq)]
+struct Dump {
+    vars: Vec<char>,
+    nodes: Vec<DumpNode>,
+    roots: Vec<(String, i64)>
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct DumpNode {
+    var: usize, // Index into vars
+    hi: i64,
+    lo: i64
+}
+
+pub fn save_text(bdd: &BDD, roots: &[(&str, isize)]) -> String {
+    let dump = collect(bdd, roots);
+    let vars: Vec<String> = dump.vars.iter().map(|c| c.to_string()).collect();
+
+    let mut out = format!(".ver {TEXT_VERSION}\n");
+    out.push_str(&format!(".nvars {}\n", dump.vars.len()));
+    out.push_str(&format!(".orderedvarnames {}\n", vars.join(" ")));
+    out.push_str(&format!(".nnodes {}\n", dump.nodes.len() + 1));
+    out.push_str(&format!(".nroots {}\n", dump.roots.len()));
+    out.push_str(".nodes\n1 T 0 0\n");
+    for (ndx, node) in dump.nodes.iter().enumerate() {
+        out.push_str(&format!("{} {} {} {}\n", ndx + 2, dump.vars[node.var], node.hi, node.lo));
+    }
+
+    out.push_str(".roots\n");
+    for (name, id) in dump.roots.iter() {
+        out.push_str(&format!("{id} {name}\n"));
+    }
+    out.push_str(".end\n");
+    return out;
+}
+
+pub fn save_binary(bdd: &BDD, roots: &[(&str, isize)]) -> Vec<u8> {
+    let dump = collect(bdd, roots);
+
+    let mut out: Vec<u8> = BINARY_MAGIC.to_vec();
+    out.push(BINARY_VERSION);
+    write_varint(&mut out, dump.vars.len() as u64);
+    for var in dump.vars.iter() {
+        write_varint(&mut out, *var as u64);
+    }
+
+    write_varint(&mut out, dump.nodes.len() as u64);
+    for node in dump.nodes.iter() {
+        write_varint(&mut out, node.var as u64);
+        write_varint(&mut out, zigzag(node.hi));
+        write_varint(&mut out, zigzag(node.lo));
+    }
+
+    write_varint(&mut out, dump.roots.len() as u64);
+    for (name, id) in dump.roots.iter() {
+        write_varint(&mut out, zigzag(*id));
+        write_varint(&mut out, name.len() as u64);
+        out.extend_from_slice(name.as_bytes());
+    }
+    return out;
+}
+
+// Loads a dump in either format into bdd, returning the named roots
+// Variables are renamed through remap (missing entries keep their name), which may not merge two of them, and any
+// that bdd does not know yet are placed last in its ordering; the stored ordering does not need to match bdd's
+pub fn load(bdd: &mut BDD, data: &[u8], remap: &HashMap<char, char>) -> Result<Vec<(String, isize)>, String> {
+    let dump = if data.starts_with(BINARY_MAGIC) {
+        parse_binary(data)?
+    } else {
+        let text = std::str::from_utf8(data).map_err(|_| String::from("BDD dump is neither binary nor UTF-8 text"))?;
+        parse_text(text)?
+    };
+
+    validate(&dump)?;
+    let vars: Vec<char> = dump.vars.iter().map(|c| *remap.get(c).unwrap_or(c)).collect();
+    if let Some((ndx, var)) = vars.iter().enumerate().find(|(ndx, var)| vars[..*ndx].contains(var)) {
+        let first = vars.iter().position(|v| v == var).unwrap();
+        return Err(format!("Variables {} and {} of the BDD dump would both become {var}", dump.vars[first], dump.vars[ndx]));
+    }
+    return Ok(rebuild(bdd, &dump, &vars));
+}
+
+// Gathers the vertices reachable from roots so that children are numbered before their parents
+fn collect(bdd: &BDD, roots: &[(&str, isize)]) -> Dump {
+    let mut names: HashMap<isize, char> = HashMap::with_capacity(bdd.ordering().len());
+    for (c, level) in bdd.ordering().iter() {
+        names.insert(*level as isize, *c);
+    }
+
+    let mut ids: HashMap<isize, i64> = HashMap::new();
+    let mut vertices: Vec<(isize, isize, isize)> = Vec::new();
+    let mut used_vars: HashSet<isize> = HashSet::new();
+
+    for (_, root) in roots.iter() {
+        let mut stack: Vec<(isize, bool)> = vec![(root.abs(), false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if id == 1 || ids.contains_key(&id) {
+                continue;
+            }
+
+            let (var, lo, hi) = areabdd::vertex_parts(bdd, id);
+            if expanded {
+                ids.insert(id, vertices.len() as i64 + 2);
+                vertices.push((var, lo, hi));
+                used_vars.insert(var);
+            } else {
+                stack.push((id, true));
+                stack.push((lo.abs(), false));
+                stack.push((hi.abs(), false));
+            }
+        }
+    }
+
+    // Only variables that occur in the dump are stored, still in the BDD's order
+    let mut levels: Vec<isize> = used_vars.into_iter().collect();
+    levels.sort_unstable();
+    let var_ndx: HashMap<isize, usize> = levels.iter().enumerate().map(|(ndx, level)| (*level, ndx)).collect();
+
+    let translate = |edge: isize| -> i64 {
+        let id = if edge.abs() == 1 { 1 } else { *ids.get(&edge.abs()).unwrap() };
+        return if edge < 0 { -id } else { id };
+    };
+
+    let nodes = vertices.iter().map(|(var, lo, hi)| DumpNode {
+        var: *var_ndx.get(var).unwrap(),
+        hi: translate(*hi),
+        lo: translate(*lo)
+    }).collect();
+
+    return Dump {
+        vars: levels.iter().map(|level| *names.get(level).unwrap_or(&'?')).collect(),
+        nodes,
+        roots: roots.iter().map(|(name, root)| (name.to_string(), translate(*root))).collect()
+    };
+}
+
+// Rebuilds every node with ite so the dump can be loaded under any ordering
+// vars holds the name each dump variable takes in bdd
+fn rebuild(bdd: &mut BDD, dump: &Dump, vars: &[char]) -> Vec<(String, isize)> {
+    let var_edges: Vec<isize> = vars.iter().map(|c| areabdd::var_edge(bdd, *c)).collect();
+
+    let mut edges: Vec<isize> = Vec::with_capacity(dump.nodes.len() + 1);
+    edges.push(1);
+    let lookup = |edges: &Vec<isize>, id: i64| -> isize {
+        let edge = edges[id.unsigned_abs() as usize - 1];
+        return if id < 0 { -edge } else { edge };
+    };
+
+    for node in dump.nodes.iter() {
+        let hi = lookup(&edges, node.hi);
+        let lo = lookup(&edges, node.lo);
+        let res = areabdd::ite(bdd, var_edges[node.var], hi, lo);
+        edges.push(res);
+    }
+
+    return dump.roots.iter().map(|(name, id)| (name.clone(), lookup(&edges, *id))).collect();
+}
+
+// Children must come before their parents and every reference must be in range
+fn validate(dump: &Dump) -> Result<(), String> {
+    let mut seen = HashSet::with_capacity(dump.vars.len());
+    for var in dump.vars.iter() {
+        if !var.is_ascii_alphabetic() || !seen.insert(var) {
+            return Err(format!("Invalid or repeated variable {var} in BDD dump"));
+        }
+    }
+
+    for (ndx, node) in dump.nodes.iter().enumerate() {
+        let id = ndx as i64 + 2;
+        if node.var >= dump.vars.len() {
+            return Err(format!("Node {id} refers to unknown variable index {}", node.var));
+        } else if node.hi == 0 || node.lo == 0 || node.hi.unsigned_abs() >= id as u64 || node.lo.unsigned_abs() >= id as u64 {
+            return Err(format!("Node {id} refers to a node that is not defined before it"));
+        }
+    }
+
+    let num_ids = dump.nodes.len() as i64 + 1;
+    for (name, id) in dump.roots.iter() {
+        if *id == 0 || id.unsigned_abs() > num_ids as u64 {
+            return Err(format!("Root {name} refers to undefined node {id}"));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_text(text: &str) -> Result<Dump, String> {
+    let mut lines = text.lines().map(|l| l.trim_end()).filter(|l| !l.is_empty());
+    let mut header: HashMap<&str, &str> = HashMap::new();
+
+    // Header fields up until the node list
+    loop {
+        let line = lines.next().ok_or(String::from("Unexpected end of BDD dump before .nodes"))?;
+        if line == ".nodes" {
+            break;
+        }
+        let (key, val) = line.split_once(' ').unwrap_or((line, ""));
+        header.insert(key, val);
+    }
+
+    match header.get(".ver") {
+        Some(ver) if *ver == TEXT_VERSION => {},
+        ver => return Err(format!("Unsupported BDD dump version {:?}", ver))
+    }
+
+    let count = |key: &str| -> Result<usize, String> {
+        header.get(key).ok_or(format!("Missing {key} in BDD dump"))?
+            .parse::<usize>().map_err(|_| format!("Invalid count for {key} in BDD dump"))
+    };
+    let (num_vars, num_nodes, num_roots) = (count(".nvars")?, count(".nnodes")?, count(".nroots")?);
+
+    let vars: Vec<char> = header.get(".orderedvarnames").unwrap_or(&"").split_whitespace()
+        .map(|v| v.parse::<char>().map_err(|_| format!("Invalid variable name {v} in BDD dump")))
+        .collect::<Result<Vec<char>, String>>()?;
+    if vars.len() != num_vars {
+        return Err(format!("Expected {num_vars} variables but found {}", vars.len()));
+    }
+
+    // Counts come from the file, so they only size the buffers as far as the text could possibly fill them
+    let mut nodes: Vec<DumpNode> = Vec::with_capacity(num_nodes.min(text.len()));
+    for ndx in 0..num_nodes {
+        let line = lines.next().ok_or(String::from("Unexpected end of BDD dump in .nodes"))?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let id = (ndx + 1).to_string();
+        match fields.as_slice() {
+            [i, "T", "0", "0"] if ndx == 0 && *i == id => continue,
+            [i, var, hi, lo] if ndx > 0 && *i == id => {
+                let var = var.parse::<char>().ok().and_then(|c| vars.iter().position(|v| *v == c))
+                    .ok_or(format!("Unknown variable {var} on node {id}"))?;
+                let parse_edge = |e: &str| e.parse::<i64>().map_err(|_| format!("Invalid edge {e} on node {id}"));
+                nodes.push(DumpNode {var, hi: parse_edge(hi)?, lo: parse_edge(lo)?});
+            },
+            _ => return Err(format!("Malformed node line: {line}"))
+        }
+    }
+
+    if lines.next() != Some(".roots") {
+        return Err(String::from("Expected .roots after the node list"));
+    }
+
+    let mut roots: Vec<(String, i64)> = Vec::with_capacity(num_roots.min(text.len()));
+    for _ in 0..num_roots {
+        let line = lines.next().ok_or(String::from("Unexpected end of BDD dump in .roots"))?;
+        let (id, name) = line.split_once(' ').unwrap_or((line, ""));
+        let id = id.parse::<i64>().map_err(|_| format!("Invalid root ID in: {line}"))?;
+        roots.push((name.to_string(), id));
+    }
+
+    if lines.next() != Some(".end") {
+        return Err(String::from("Expected .end after the roots"));
+    }
+
+    Ok(Dump { vars, nodes, roots })
+}
+
+fn parse_binary(data: &[u8]) -> Result<Dump, String> {
+    let mut pos = BINARY_MAGIC.len();
+    match data.get(pos) {
+        Some(ver) if *ver == BINARY_VERSION => pos += 1,
+        ver => return Err(format!("Unsupported binary BDD dump version {:?}", ver))
+    }
+
+    let num_vars = read_varint(data, &mut pos)? as usize;
+    let mut vars: Vec<char> = Vec::with_capacity(num_vars.min(data.len()));
+    for _ in 0..num_vars {
+        let code = read_varint(data, &mut pos)?;
+        vars.push(u32::try_from(code).ok().and_then(char::from_u32).ok_or(format!("Invalid variable code {code}"))?);
+    }
+
+    let num_nodes = read_varint(data, &mut pos)? as usize;
+    let mut nodes: Vec<DumpNode> = Vec::with_capacity(num_nodes.min(data.len()));
+    for _ in 0..num_nodes {
+        let var = read_varint(data, &mut pos)? as usize;
+        let hi = unzigzag(read_varint(data, &mut pos)?);
+        let lo = unzigzag(read_varint(data, &mut pos)?);
+        nodes.push(DumpNode {var, hi, lo});
+    }
+
+    let num_roots = read_varint(data, &mut pos)? as usize;
+    let mut roots: Vec<(String, i64)> = Vec::with_capacity(num_roots.min(data.len()));
+    for _ in 0..num_roots {
+        let id = unzigzag(read_varint(data, &mut pos)?);
+        let len = read_varint(data, &mut pos)? as usize;
+        let name = data.get(pos..pos.saturating_add(len)).ok_or(String::from("Unexpected end of binary BDD dump"))?;
+        let name = String::from_utf8(name.to_vec()).map_err(|_| String::from("Root name is not valid UTF-8"))?;
+        pos += len;
+        roots.push((name, id));
+    }
+
+    if pos != data.len() {
+        return Err(String::from("Trailing bytes after binary BDD dump"));
+    }
+
+    Ok(Dump { vars, nodes, roots })
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: u64) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> Result<u64, String> {
+    let mut val: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *data.get(*pos).ok_or(String::from("Unexpected end of binary BDD dump"))?;
+        *pos += 1;
+        val |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(val);
+        }
+    }
+    Err(String::from("Varint too long in binary BDD dump"))
+}
+
+fn zigzag(val: i64) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+fn unzigzag(val: u64) -> i64 {
+    ((val >> 1) as i64) ^ -((val & 1) as i64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn build_exprs(exprs: &[&str]) -> BDD {
+        let parser = crate::parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+        areabdd::build(BDD::new(), &parser)
+    }
+
+    #[test]
+    fn text_format() {
+        let bdd = build_exprs(&["a & b"]);
+        let expected = ".ver BOOL-EQ-DDDMP-1\n.nvars 2\n.orderedvarnames a b\n.nnodes 3\n.nroots 1\n\
+        .nodes\n1 T 0 0\n2 b 1 -1\n3 a 2 -1\n.roots\n3 a & b\n.end\n";
+        assert_eq!(expected, save_text(&bdd, &[("a & b", bdd.roots()[0])]));
+    }
+
+    #[test]
+    fn round_trip_into_fresh_and_existing() {
+        let exprs = ["(a ^ b) | ~(c & d)", "a ? ~b : d", "~a"];
+        let bdd = build_exprs(&exprs);
+        let roots: Vec<(&str, isize)> = exprs.iter().copied().zip(bdd.roots().iter().copied()).collect();
+
+        for data in [save_text(&bdd, &roots).into_bytes(), save_binary(&bdd, &roots)] {
+            let mut fresh = BDD::new();
+            let loaded = load(&mut fresh, &data, &HashMap::new()).unwrap();
+            assert_eq!(save_text(&bdd, &roots), save_text(&fresh, &loaded.iter().map(|(n, r)| (n.as_str(), *r)).collect::<Vec<_>>()));
+
+            // The existing manager orders the variables differently
+            let mut existing = build_exprs(&["d | c", "(b ^ a) | ~(d & c)"]);
+            let loaded = load(&mut existing, &data, &HashMap::new()).unwrap();
+            assert_eq!(exprs[0], loaded[0].0);
+            assert_eq!(existing.roots()[1], loaded[0].1);
+        }
+    }
+
+    #[test]
+    fn load_with_remap() {
+        let bdd = build_exprs(&["a & ~b"]);
+        let data = save_binary(&bdd, &[("f", bdd.roots()[0])]);
+
+        let mut target = build_exprs(&["x & ~y"]);
+        let remap = HashMap::from([('a', 'x'), ('b', 'y')]);
+        let loaded = load(&mut target, &data, &remap).unwrap();
+        assert_eq!(target.roots()[0], loaded[0].1);
+    }
+
+    #[test]
+    fn load_rejects_bad_dumps() {
+        let bdd = build_exprs(&["a & b"]);
+        let text = save_text(&bdd, &[("f", bdd.roots()[0])]);
+        let binary = save_binary(&bdd, &[("f", bdd.roots()[0])]);
+
+        let forward_ref = text.replace("2 b 1 -1", "2 b 3 -1");
+        assert!(load(&mut BDD::new(), forward_ref.as_bytes(), &HashMap::new()).is_err());
+        assert!(load(&mut BDD::new(), text.replace(".end\n", "").as_bytes(), &HashMap::new()).is_err());
+        assert!(load(&mut BDD::new(), &binary[..binary.len() - 1], &HashMap::new()).is_err());
+
+        // Header counts far past what the file holds are errors rather than allocations
+        for (key, count) in [(".nnodes", "999999999999999999"), (".nroots", "999999999999999999"), (".nnodes", "99999999999")] {
+            let line = text.lines().find(|line| line.starts_with(key)).unwrap();
+            let huge = text.replace(line, &format!("{key} {count}"));
+            assert!(load(&mut BDD::new(), huge.as_bytes(), &HashMap::new()).is_err());
+        }
+
+        let remap = HashMap::from([('a', 'x'), ('b', 'x')]);
+        assert_eq!(Err(String::from("Variables a and b of the BDD dump would both become x")), load(&mut BDD::new(), &binary, &remap));
+        assert!(load(&mut BDD::new(), &binary, &HashMap::from([('a', 'b')])).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+// Fixed-size, lossy computed table for BDD operations
+// Each key hashes to exactly one slot; a colliding insert simply overwrites (evicts) the old entry,
+// which is safe because every entry can be recomputed from scratch
+// Operand normalization happens before lookup (see areabdd::ite), so equivalent calls share a slot
+
+use std::hash::{Hash, Hasher};
+use std::mem;
+
+#[derive(Debug, Clone)]
+pub struct ComputedTable<K> {
+    slots: Vec<Option<(K, isize)>>,
+    mask: usize,
+    stats: CacheStats
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub inserts: usize,
+    pub evictions: usize,
+    pub capacity: usize
+}
+
+impl CacheStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+
+        return self.hits as f64 / lookups as f64;
+    }
+
+    // Combines the statistics of several tables
+    pub fn merge(&self, other: &CacheStats) -> CacheStats {
+        CacheStats {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            inserts: self.inserts + other.inserts,
+            evictions: self.evictions + other.evictions,
+            capacity: self.capacity + other.capacity
+        }
+    }
+}
+
+impl<K: Hash + Eq + Copy> ComputedTable<K> {
+    // Sizes the table to the largest power of two number of slots that fits in budget bytes
+    // At least one slot is always allocated
+    pub fn with_budget(budget: usize) -> Self {
+        let slot_size = mem::size_of::<Option<(K, isize)>>();
+        let mut num_slots: usize = 1;
+        while num_slots * 2 * slot_size <= budget {
+            num_slots *= 2;
+        }
+
+        ComputedTable {
+            slots: vec![None; num_slots],
+            mask: num_slots - 1,
+            stats: CacheStats { capacity: num_slots, ..CacheStats::default() }
+        }
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<isize> {
+        match &self.slots[self.slot(key)] {
+            Some((k, val)) if k == key => {
+                self.stats.hits += 1;
+                Some(*val)
+            },
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: K, val: isize) {
+        let ndx = self.slot(&key);
+        if let Some((k, _)) = &self.slots[ndx] {
+            if *k != key {
+                self.stats.evictions += 1;
+            }
+        }
+
+        self.stats.inserts += 1;
+        self.slots[ndx] = Some((key, val));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        return self.stats;
+    }
+
+    fn slot(&self, key: &K) -> usize {
+        return fx_hash(key) as usize & self.mask;
+    }
+}
+
+pub(crate) fn fx_hash<K: Hash>(key: &K) -> u64 {
+    let mut hasher = FxHasher::default();
+    key.hash(&mut hasher);
+    return hasher.finish();
+}
+
+// Two tables are equal when they hold the same entries; statistics are ignored
+impl<K: PartialEq> PartialEq for ComputedTable<K> {
+    fn eq(&self, other: &Self) -> bool {
+        return self.slots == other.slots;
+    }
+}
+
+// Multiplicative hash in the style of rustc's FxHasher; the keys are a few small integers,
+// so SipHash's DoS resistance is not worth its cost here
+#[derive(Default)]
+struct FxHasher {
+    hash: u64
+}
+
+impl FxHasher {
+    fn add(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+}
+
+impl Hasher for FxHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            self.add(*byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.add(i);
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.add(i as u64);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.add(i as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        // Fold the high bits down, since slots are picked from the low ones
+        return self.hash ^ (self.hash >> 32);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn budget_sizes_table() {
+        let table: ComputedTable<(isize, isize)> = ComputedTable::with_budget(1000);
+        let slot_size = mem::size_of::<Option<((isize, isize), isize)>>();
+        assert!(table.stats().capacity.is_power_of_two());
+        assert!(table.stats().capacity * slot_size <= 1000);
+        assert!(table.stats().capacity * 2 * slot_size > 1000);
+        assert_eq!(1, ComputedTable::<(isize, isize)>::with_budget(0).stats().capacity);
+    }
+
+    #[test]
+    fn lossy_eviction() {
+        let mut table: ComputedTable<isize> = ComputedTable::with_budget(0);
+        assert_eq!(None, table.get(&3));
+        table.insert(3, 30);
+        assert_eq!(Some(30), table.get(&3));
+        table.insert(4, 40);
+        assert_eq!(None, table.get(&3));
+        assert_eq!(Some(40), table.get(&4));
+
+        let stats = table.stats();
+        assert_eq!((2, 2, 2, 1), (stats.hits, stats.misses, stats.inserts, stats.evictions));
+        assert_eq!(0.5, stats.hit_rate());
+    }
+}
@@ -0,0 +1,142 @@
+// Partitions a session's expressions into equivalence classes instead of a single verdict
+// Every expression is built into one shared BDD and restricted to the inputs being compared (the fixed
+// assignments and the care set), so two expressions agree exactly when their restricted roots are the same edge.
+// Each pair of classes gets one representative counterexample, read off the XOR of their representatives.
+
+use std::collections::HashMap;
+use crate::json::{json_list, json_string};
+use crate::parser::{Operator, Parser};
+use crate::sharedbdd::{self, SharedBDD};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classes {
+    pub classes: Vec<Vec<usize>>, // Expression indices, classes ordered by their first member
+    pub class_of: Vec<usize>,
+    pub witnesses: Vec<(usize, usize, Vec<bool>)> // For each pair of classes, input values followed by each expression's result
+}
+
+impl Classes {
+    pub fn agree(&self, i: usize, j: usize) -> bool {
+        return self.class_of[i] == self.class_of[j];
+    }
+
+    // Pairwise agreement between expressions
+    pub fn matrix(&self) -> Vec<Vec<bool>> {
+        let n = self.class_of.len();
+        return (0..n).map(|i| (0..n).map(|j| self.agree(i, j)).collect()).collect();
+    }
+
+    pub fn all_eq(&self) -> bool {
+        return self.classes.len() <= 1;
+    }
+
+    pub fn to_json(&self, parser: &Parser, raw_exprs: &[String]) -> String {
+        let names: Vec<String> = parser.ast_order.keys().map(|c| json_string(&c.to_string())).collect();
+        let exprs: Vec<String> = raw_exprs.iter().map(|e| json_string(e)).collect();
+        let classes: Vec<String> = self.classes.iter().map(|class| json_list(class.iter())).collect();
+        let matrix: Vec<String> = self.matrix().iter().map(|row| json_list(row.iter())).collect();
+
+        let witnesses: Vec<String> = self.witnesses.iter().map(|(i, j, row)| {
+            let (inputs, results) = row.split_at(names.len());
+            let inputs: Vec<String> = names.iter().zip(inputs.iter()).map(|(name, val)| format!("{name}: {val}")).collect();
+            format!("{{\"classes\": [{i}, {j}], \"inputs\": {{{}}}, \"results\": {}}}", inputs.join(", "), json_list(results.iter()))
+        }).collect();
+
+        return format!("{{\"variables\": [{}], \"expressions\": [{}], \"all_eq\": {}, \"classes\": [{}], \"matrix\": [{}], \"witnesses\": [{}]}}",
+            names.join(", "), exprs.join(", "), self.all_eq(), classes.join(", "), matrix.join(", "), witnesses.join(", "));
+    }
+}
+
+// Groups expressions that agree on every input consistent with the fixed (variable, value) pairs and the care set
+pub fn classify(parser: &Parser, fixed: &[(char, bool)]) -> Classes {
+    let bdd = SharedBDD::new();
+    let roots = sharedbdd::build_parallel(&bdd, parser);
+
+    let mut domain = match parser.care.as_ref() {
+        Some(care) => bdd.build_rpn(&care.shared_rpn, &parser.defs),
+        None => 1
+    };
+    for (var, val) in fixed.iter() {
+        let var = bdd.var_edge(*var);
+        domain = bdd.apply(&Operator::AND, domain, if *val { var } else { -var });
+    }
+
+    let mut res = Classes {
+        classes: Vec::new(),
+        class_of: Vec::with_capacity(roots.len()),
+        witnesses: Vec::new()
+    };
+    let mut by_root: HashMap<isize, usize> = HashMap::new();
+    for (ndx, root) in roots.iter().enumerate() {
+        let restricted = bdd.apply(&Operator::AND, *root, domain);
+        let class = *by_root.entry(restricted).or_insert(res.classes.len());
+        if class == res.classes.len() {
+            res.classes.push(Vec::new());
+        }
+        res.classes[class].push(ndx);
+        res.class_of.push(class);
+    }
+
+    for i in 0..res.classes.len() {
+        for j in i + 1..res.classes.len() {
+            let diff = bdd.apply(&Operator::XOR, roots[res.classes[i][0]], roots[res.classes[j][0]]);
+            let diff = bdd.apply(&Operator::AND, diff, domain);
+            let mut row = bdd.pick_one(diff).expect("Distinct classes must differ somewhere");
+            let mut results: Vec<bool> = roots.iter().map(|root| bdd.evaluate(*root, &row)).collect();
+            row.append(&mut results);
+            res.witnesses.push((i, j, row));
+        }
+    }
+
+    return res;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn session(exprs: &[&str]) -> Parser {
+        return parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+    }
+
+    #[test]
+    fn partitions_into_classes() {
+        let parser = session(&["a & (b | c)", "a | b", "(a & b) | (a & c)", "~(~a & ~b)", "a ^ b"]);
+        let res = classify(&parser, &[]);
+        assert_eq!(vec![vec![0, 2], vec![1, 3], vec![4]], res.classes);
+        assert_eq!(vec![0, 1, 0, 1, 2], res.class_of);
+        assert!(res.agree(1, 3) && !res.agree(0, 1));
+        assert!(!res.all_eq());
+
+        // One witness per pair of classes, on which the two representatives differ
+        assert_eq!(3, res.witnesses.len());
+        for (i, j, row) in res.witnesses.iter() {
+            let results = &row[3..];
+            assert_ne!(results[res.classes[*i][0]], results[res.classes[*j][0]]);
+        }
+    }
+
+    #[test]
+    fn fixed_inputs_and_care_merge_classes() {
+        let mut parser = session(&["a & b", "a & c", "a"]);
+        assert_eq!(3, classify(&parser, &[]).classes.len());
+        assert_eq!(vec![vec![0, 1], vec![2]], classify(&parser, &[('b', false), ('c', false)]).classes);
+
+        parser.set_care(String::from("b & c")).unwrap();
+        let res = classify(&parser, &[]);
+        assert!(res.all_eq());
+        assert!(res.witnesses.is_empty());
+    }
+
+    #[test]
+    fn json_output() {
+        let parser = session(&["a", "~~a", "b"]);
+        let res = classify(&parser, &[]);
+        assert_eq!(concat!(
+            "{\"variables\": [\"a\", \"b\"], \"expressions\": [\"a\", \"~~a\", \"b\"], \"all_eq\": false, ",
+            "\"classes\": [[0, 1], [2]], \"matrix\": [[true, true, false], [true, true, false], [false, false, true]], ",
+            "\"witnesses\": [{\"classes\": [0, 1], \"inputs\": {\"a\": true, \"b\": false}, \"results\": [true, true, false]}]}"
+        ), res.to_json(&parser, &[String::from("a"), String::from("~~a"), String::from("b")]));
+    }
+}
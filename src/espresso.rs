@@ -0,0 +1,588 @@
+// Heuristic two-level minimization in the style of Espresso-II
+// Quine-McCluskey has to list every minterm, so beyond a dozen or so variables covers are improved as cube
+// lists instead: EXPAND grows each cube as far as the function allows, IRREDUNDANT drops cubes the rest of the
+// cover already implies and REDUCE shrinks cubes again so the next EXPAND can move them somewhere better.
+// The loop stops once a pass no longer lowers the cost, and the result is near-minimal rather than exact.
+//
+// Cubes are minimize::Cube values, so bit i stands for the i-th variable in ast_order and at most 64
+// variables are supported. Tautology and complement use the unate recursive paradigm.
+
+use std::collections::HashMap;
+use crate::areabdd::{self, BDD};
+use crate::minimize::{self, Cube};
+use crate::parser::{Definition, Operator, Parser, Token};
+
+const UNIVERSE: Cube = Cube {bits: 0, care: 0};
+
+// Largest starting cover espresso_session takes on; each pass of Espresso grows much faster than the cover
+pub const MAX_COVER: usize = 4096;
+
+// Number of products, then number of literals
+fn cost(cover: &[Cube]) -> (usize, u32) {
+    return (cover.len(), cover.iter().map(|cube| cube.literals()).sum());
+}
+
+fn intersects(a: &Cube, b: &Cube) -> bool {
+    return (a.bits ^ b.bits) & a.care & b.care == 0;
+}
+
+// Whether every minterm of a lies in b
+fn contained(a: &Cube, b: &Cube) -> bool {
+    return b.care & !a.care == 0 && (a.bits ^ b.bits) & b.care == 0;
+}
+
+fn intersection(a: &Cube, b: &Cube) -> Cube {
+    return Cube {bits: (a.bits & a.care) | (b.bits & b.care), care: a.care | b.care};
+}
+
+// Smallest cube holding every cube of a non-empty cover
+fn supercube(cover: &[Cube]) -> Cube {
+    let mut res = cover[0];
+    for cube in cover.iter().skip(1) {
+        res.care &= cube.care & !(res.bits ^ cube.bits);
+        res.bits &= res.care;
+    }
+    return res;
+}
+
+// Removes every cube that lies inside another cube of the cover
+fn single_cube_containment(mut cover: Vec<Cube>) -> Vec<Cube> {
+    cover.sort_by_key(|cube| cube.literals());
+    cover.dedup();
+    let mut res: Vec<Cube> = Vec::with_capacity(cover.len());
+    for cube in cover.into_iter() {
+        if !res.iter().any(|big| contained(&cube, big)) {
+            res.push(cube);
+        }
+    }
+    return res;
+}
+
+// Cofactor with respect to a cube: the part of the cover inside it, with the cube's variables dropped
+fn cofactor(cover: &[Cube], by: &Cube) -> Vec<Cube> {
+    return cover.iter()
+        .filter(|cube| intersects(cube, by))
+        .map(|cube| Cube {bits: cube.bits & !by.care, care: cube.care & !by.care})
+        .collect();
+}
+
+// Picks the variable to split on, preferring one that appears in both polarities in the most cubes
+// Returns None when the cover is unate
+fn binate_var(cover: &[Cube]) -> Option<u64> {
+    let (mut pos, mut neg) = (0u64, 0u64);
+    for cube in cover.iter() {
+        pos |= cube.care & cube.bits;
+        neg |= cube.care & !cube.bits;
+    }
+
+    let binate = pos & neg;
+    if binate == 0 {
+        return None;
+    }
+
+    let mut best = (0, 0);
+    let mut vars = binate;
+    while vars != 0 {
+        let bit = vars & vars.wrapping_neg();
+        vars ^= bit;
+        let count = cover.iter().filter(|cube| cube.care & bit != 0).count();
+        if count > best.0 {
+            best = (count, bit);
+        }
+    }
+    return Some(best.1);
+}
+
+// Whether the cover holds every minterm; a unate cover only does so if it holds the universal cube
+pub fn tautology(cover: &[Cube]) -> bool {
+    if cover.iter().any(|cube| cube.care == 0) {
+        return true;
+    }
+
+    // A cube with a unate literal only covers minterms that the rest must cover anyway
+    let (mut pos, mut neg) = (0u64, 0u64);
+    for cube in cover.iter() {
+        pos |= cube.care & cube.bits;
+        neg |= cube.care & !cube.bits;
+    }
+    let unate = pos ^ neg;
+    if unate != 0 {
+        let rest: Vec<Cube> = cover.iter().filter(|cube| cube.care & unate == 0).copied().collect();
+        return !rest.is_empty() && tautology(&rest);
+    }
+
+    match binate_var(cover) {
+        None => false,
+        Some(bit) => tautology(&cofactor(cover, &Cube {bits: bit, care: bit}))
+            && tautology(&cofactor(cover, &Cube {bits: 0, care: bit}))
+    }
+}
+
+// Cover of every minterm outside the given cover
+pub fn complement(cover: &[Cube]) -> Vec<Cube> {
+    if cover.is_empty() {
+        return vec![UNIVERSE];
+    } else if cover.iter().any(|cube| cube.care == 0) {
+        return Vec::new();
+    } else if cover.len() == 1 {
+        // De Morgan: one cube per negated literal
+        let cube = cover[0];
+        let mut res: Vec<Cube> = Vec::new();
+        let mut vars = cube.care;
+        while vars != 0 {
+            let bit = vars & vars.wrapping_neg();
+            vars ^= bit;
+            res.push(Cube {bits: !cube.bits & bit, care: bit});
+        }
+        return res;
+    }
+
+    // Unate covers still need splitting here, so fall back to the most frequent variable
+    let bit = binate_var(cover).unwrap_or_else(|| {
+        let all = cover.iter().fold(0, |acc, cube| acc | cube.care);
+        let mut best = (0, 0);
+        let mut vars = all;
+        while vars != 0 {
+            let bit = vars & vars.wrapping_neg();
+            vars ^= bit;
+            let count = cover.iter().filter(|cube| cube.care & bit != 0).count();
+            if count > best.0 {
+                best = (count, bit);
+            }
+        }
+        best.1
+    });
+
+    let hi = complement(&cofactor(cover, &Cube {bits: bit, care: bit}));
+    let lo = complement(&cofactor(cover, &Cube {bits: 0, care: bit}));
+
+    // Cubes in both halves do not depend on the splitting variable
+    let mut res: Vec<Cube> = Vec::with_capacity(hi.len() + lo.len());
+    for cube in hi.iter() {
+        if lo.contains(cube) {
+            res.push(*cube);
+        } else {
+            res.push(Cube {bits: cube.bits | bit, care: cube.care | bit});
+        }
+    }
+    for cube in lo.iter() {
+        if !hi.contains(cube) {
+            res.push(Cube {bits: cube.bits, care: cube.care | bit});
+        }
+    }
+    return single_cube_containment(res);
+}
+
+// Whether every minterm of the cube lies in the cover
+fn implied(cube: &Cube, cover: &[Cube]) -> bool {
+    return tautology(&cofactor(cover, cube));
+}
+
+// Grows a cube while it stays inside the on-set and don't cares, towards whichever target it can swallow with
+// the fewest raised literals; each step costs a tautology check per target, so this is kept for small lists
+fn expand_towards(mut cube: Cube, targets: &[Cube], care: &[Cube]) -> Cube {
+    loop {
+        let next = targets.iter()
+            .filter(|other| !contained(other, &cube))
+            .map(|other| supercube(&[cube, *other]))
+            .filter(|raised| implied(raised, care))
+            .min_by_key(|raised| raised.literals());
+
+        match next {
+            Some(raised) => cube = raised,
+            None => return cube
+        }
+    }
+}
+
+// Expands every cube into a prime, largest cubes first, dropping the cubes each prime swallows
+// Literals are raised in the order that frees the cube to cover the most other cubes
+fn expand(cover: Vec<Cube>, care: &[Cube]) -> Vec<Cube> {
+    let mut cover = cover;
+    cover.sort_by_key(|cube| cube.literals());
+
+    let mut res: Vec<Cube> = Vec::with_capacity(cover.len());
+    for ndx in 0..cover.len() {
+        let mut cube = cover[ndx];
+        if res.iter().any(|big| contained(&cube, big)) {
+            continue;
+        }
+
+        let mut lits: Vec<(usize, u64)> = Vec::new();
+        let mut vars = cube.care;
+        while vars != 0 {
+            let bit = vars & vars.wrapping_neg();
+            vars ^= bit;
+            let freed = cover.iter().filter(|other| other.care & bit == 0 || (other.bits ^ cube.bits) & bit != 0).count();
+            lits.push((freed, bit));
+        }
+        lits.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+        for (_, bit) in lits.into_iter() {
+            let raised = Cube {bits: cube.bits & !bit, care: cube.care & !bit};
+            if implied(&raised, care) {
+                cube = raised;
+            }
+        }
+
+        res.retain(|small| !contained(small, &cube));
+        res.push(cube);
+    }
+
+    return res;
+}
+
+// Whether the cube at ndx is implied by the other cubes and the don't cares
+fn redundant(cover: &[Cube], ndx: usize, dc: &[Cube]) -> bool {
+    let rest: Vec<Cube> = cover.iter().enumerate()
+        .filter(|(k, _)| *k != ndx)
+        .map(|(_, other)| *other)
+        .chain(dc.iter().copied())
+        .collect();
+    return implied(&cover[ndx], &rest);
+}
+
+// Drops cubes the rest of the cover and the don't cares already imply
+// Relatively essential cubes always stay and cubes they imply always go; of the partially redundant cubes left,
+// each step removes the one that leaves the most others still removable, so cyclic covers do not get stuck
+fn irredundant(cover: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    let (mut essential, mut partial): (Vec<Cube>, Vec<Cube>) = (Vec::new(), Vec::new());
+    for ndx in 0..cover.len() {
+        if redundant(&cover, ndx, dc) {
+            partial.push(cover[ndx]);
+        } else {
+            essential.push(cover[ndx]);
+        }
+    }
+
+    let mut base: Vec<Cube> = essential.clone();
+    base.extend_from_slice(dc);
+    let skip = essential.len();
+    essential.extend(partial.into_iter().filter(|cube| !implied(cube, &base)));
+    let mut cover = essential;
+
+    loop {
+        let removable: Vec<usize> = (skip..cover.len()).filter(|ndx| redundant(&cover, *ndx, dc)).collect();
+        let best = removable.iter().max_by_key(|ndx| {
+            let mut rest = cover.clone();
+            rest.remove(**ndx);
+            let left = removable.iter()
+                .filter(|other| *other != *ndx)
+                .filter(|other| redundant(&rest, if **other > **ndx { **other - 1 } else { **other }, dc))
+                .count();
+            (left, cover[**ndx].literals())
+        });
+
+        match best {
+            Some(ndx) => { cover.remove(*ndx); },
+            None => return cover
+        }
+    }
+}
+
+// Shrinks each cube to the smallest cube holding the minterms only it covers, largest cubes first
+fn reduce(cover: Vec<Cube>, dc: &[Cube]) -> Vec<Cube> {
+    let mut cover = cover;
+    cover.sort_by_key(|cube| cube.literals());
+
+    let mut ndx = 0;
+    while ndx < cover.len() {
+        let cube = cover[ndx];
+        let rest: Vec<Cube> = cover.iter().enumerate()
+            .filter(|(k, _)| *k != ndx)
+            .map(|(_, other)| *other)
+            .chain(dc.iter().copied())
+            .collect();
+
+        let uncovered = complement(&cofactor(&rest, &cube));
+        if uncovered.is_empty() {
+            cover.remove(ndx);
+        } else {
+            cover[ndx] = intersection(&cube, &supercube(&uncovered));
+            ndx += 1;
+        }
+    }
+
+    return cover;
+}
+
+// Reduces every cube on its own against the rest of the cover, then looks for primes that hold two or more of
+// the reduced cubes; this escapes covers that REDUCE and EXPAND keep rebuilding
+fn last_gasp(cover: &[Cube], dc: &[Cube], care: &[Cube]) -> Vec<Cube> {
+    let mut reduced: Vec<Cube> = Vec::with_capacity(cover.len());
+    for (ndx, cube) in cover.iter().enumerate() {
+        let rest: Vec<Cube> = cover.iter().enumerate()
+            .filter(|(k, _)| *k != ndx)
+            .map(|(_, other)| *other)
+            .chain(dc.iter().copied())
+            .collect();
+
+        let uncovered = complement(&cofactor(&rest, cube));
+        if !uncovered.is_empty() {
+            reduced.push(intersection(cube, &supercube(&uncovered)));
+        }
+    }
+
+    let mut res = cover.to_vec();
+    for cube in reduced.iter() {
+        let prime = expand(vec![expand_towards(*cube, &reduced, care)], care)[0];
+        if reduced.iter().filter(|other| contained(other, &prime)).count() > 1 && !res.contains(&prime) {
+            res.push(prime);
+        }
+    }
+
+    return irredundant(res, dc);
+}
+
+// Minimizes the function given by its on-set and don't-care covers
+pub fn espresso(on: &[Cube], dc: &[Cube]) -> Vec<Cube> {
+    let mut care: Vec<Cube> = on.to_vec();
+    care.extend_from_slice(dc);
+
+    let mut best = irredundant(expand(single_cube_containment(on.to_vec()), &care), dc);
+    loop {
+        let mut next = irredundant(expand(reduce(best.clone(), dc), &care), dc);
+        if cost(&next) >= cost(&best) {
+            next = last_gasp(&best, dc, &care);
+            if cost(&next) >= cost(&best) {
+                break;
+            }
+        }
+        best = next;
+    }
+
+    best.sort();
+    return best;
+}
+
+// Number of cubes bdd_cover returns for an edge, saturating at usize::MAX, found without listing them
+pub fn path_count(bdd: &BDD, edge: isize) -> usize {
+    return path_count_helper(bdd, edge, &mut HashMap::new());
+}
+
+fn path_count_helper(bdd: &BDD, edge: isize, memo: &mut HashMap<isize, usize>) -> usize {
+    if edge.abs() == 1 {
+        return (edge == 1) as usize;
+    } else if let Some(count) = memo.get(&edge) {
+        return *count;
+    }
+
+    let (_, lo, hi) = areabdd::vertex_parts(bdd, edge);
+    let (lo, hi) = if edge < 0 { (-lo, -hi) } else { (lo, hi) };
+    let count = path_count_helper(bdd, lo, memo).saturating_add(path_count_helper(bdd, hi, memo));
+    memo.insert(edge, count);
+    return count;
+}
+
+// Disjoint cover read off the paths of a BDD to the 1-terminal
+pub fn bdd_cover(bdd: &BDD, root: isize) -> Vec<Cube> {
+    let mut res: Vec<Cube> = Vec::new();
+    bdd_cover_helper(bdd, root, UNIVERSE, &mut res);
+    return res;
+}
+
+fn bdd_cover_helper(bdd: &BDD, edge: isize, path: Cube, res: &mut Vec<Cube>) {
+    if edge == -1 {
+        return;
+    } else if edge == 1 {
+        res.push(path);
+        return;
+    }
+
+    let (var, lo, hi) = areabdd::vertex_parts(bdd, edge);
+    let (lo, hi) = if edge < 0 { (-lo, -hi) } else { (lo, hi) };
+    let bit = 1 << (var - 1);
+    bdd_cover_helper(bdd, lo, Cube {bits: path.bits, care: path.care | bit}, res);
+    bdd_cover_helper(bdd, hi, Cube {bits: path.bits | bit, care: path.care | bit}, res);
+}
+
+// Cover of a parsed expression built directly from its operators
+// order gives each variable's bit, like ast_order
+pub fn rpn_cover(rpn: &[Token], defs: &[Definition], order: &HashMap<char, usize>) -> Vec<Cube> {
+    return rpn_cover_helper(rpn, defs, order, &[], &mut HashMap::new());
+}
+
+// Definitions are covered once per distinct list of argument covers
+fn rpn_cover_helper(rpn: &[Token], defs: &[Definition], order: &HashMap<char, usize>, params: &[Vec<Cube>],
+    shared: &mut HashMap<(usize, Vec<Vec<Cube>>), Vec<Cube>>) -> Vec<Cube> {
+    let and = |lhs: &[Cube], rhs: &[Cube]| -> Vec<Cube> {
+        let mut res: Vec<Cube> = Vec::new();
+        for a in lhs.iter() {
+            for b in rhs.iter().filter(|b| intersects(a, b)) {
+                res.push(intersection(a, b));
+            }
+        }
+        single_cube_containment(res)
+    };
+    let or = |lhs: &[Cube], rhs: &[Cube]| -> Vec<Cube> {
+        single_cube_containment(lhs.iter().chain(rhs.iter()).copied().collect())
+    };
+
+    let mut op_stack: Vec<Vec<Cube>> = Vec::with_capacity(rpn.len());
+    for t in rpn.iter() {
+        match t {
+            Token::VAR(c) => {
+                let bit = 1 << order[c];
+                op_stack.push(vec![Cube {bits: bit, care: bit}]);
+            },
+            Token::PARAM(ndx) => op_stack.push(params[*ndx].clone()),
+            Token::DEF(id) => {
+                let args = op_stack.split_off(op_stack.len() - defs[*id].params.len());
+                let cover = match shared.get(&(*id, args.clone())) {
+                    Some(cover) => cover.clone(),
+                    None => {
+                        let cover = rpn_cover_helper(&defs[*id].body.shared_rpn, defs, order, &args, shared);
+                        shared.insert((*id, args), cover.clone());
+                        cover
+                    }
+                };
+                op_stack.push(cover);
+            },
+            Token::OP(Operator::NOT) => {
+                let top = op_stack.pop().unwrap();
+                op_stack.push(complement(&top));
+            },
+            Token::OP(Operator::ITE) => {
+                let h = op_stack.pop().unwrap();
+                let g = op_stack.pop().unwrap();
+                let f = op_stack.pop().unwrap();
+                op_stack.push(or(&and(&f, &g), &and(&complement(&f), &h)));
+            },
+            Token::OP(op) => {
+                let rhs = op_stack.pop().unwrap();
+                let lhs = op_stack.pop().unwrap();
+                op_stack.push(match op {
+                    Operator::AND => and(&lhs, &rhs),
+                    Operator::OR => or(&lhs, &rhs),
+                    Operator::XOR => or(&and(&lhs, &complement(&rhs)), &and(&complement(&lhs), &rhs)),
+                    _ => panic!("Unexpected operator {:?} while building cover", op)
+                });
+            },
+            _ => panic!("Unexpected token while building cover")
+        }
+    }
+
+    return op_stack.pop().expect("No cover left on the stack");
+}
+
+// Builds a cover into the BDD so it can be compared against the original function
+pub fn cover_to_bdd(bdd: &mut BDD, cover: &[Cube], names: &[char]) -> isize {
+    let mut res = -1;
+    for cube in cover.iter() {
+        let mut product = 1;
+        for (i, name) in names.iter().enumerate().filter(|(i, _)| cube.care & (1 << i) != 0) {
+            let var = areabdd::var_edge(bdd, *name);
+            let lit = if cube.bits & (1 << i) != 0 { var } else { -var };
+            product = areabdd::apply(bdd, &Operator::AND, product, lit);
+        }
+        res = areabdd::apply(bdd, &Operator::OR, res, product);
+    }
+    return res;
+}
+
+// Heuristic SOP of every expression in the session; each result is checked against its BDD before it is returned
+pub fn espresso_session(parser: &Parser) -> Result<Vec<String>, String> {
+    let mut bdd = areabdd::build(BDD::new(), parser);
+    let names: Vec<char> = parser.ast_order.keys().copied().collect();
+    if names.len() > 64 {
+        return Err(String::from("Espresso supports at most 64 variables"));
+    }
+
+    let mut res: Vec<String> = Vec::with_capacity(parser.exprs.len());
+    for root in bdd.roots().to_vec().into_iter() {
+        if path_count(&bdd, root) > MAX_COVER {
+            return Err(format!("Espresso is limited to functions whose BDD has at most {MAX_COVER} paths to 1"));
+        }
+        let cover = espresso(&bdd_cover(&bdd, root), &[]);
+        if cover_to_bdd(&mut bdd, &cover, &names) != root {
+            return Err(format!("Minimized cover {} is not equivalent to its expression", minimize::to_expr(&cover, &names)));
+        }
+        res.push(minimize::to_expr(&cover, &names));
+    }
+
+    return Ok(res);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn cube(lits: &str) -> Cube {
+        // One character per variable: 1, 0 or - for a variable that does not appear
+        let mut res = UNIVERSE;
+        for (i, c) in lits.chars().enumerate() {
+            match c {
+                '1' => res = Cube {bits: res.bits | 1 << i, care: res.care | 1 << i},
+                '0' => res.care |= 1 << i,
+                _ => ()
+            }
+        }
+        return res;
+    }
+
+    #[test]
+    fn tautology_and_complement() {
+        assert!(tautology(&[cube("1-"), cube("0-")]));
+        assert!(tautology(&[cube("11"), cube("0-"), cube("-0")]));
+        assert!(!tautology(&[cube("11"), cube("0-")]));
+
+        let cover = vec![cube("11-"), cube("0-1")];
+        let off = complement(&cover);
+        for m in 0..8u64 {
+            let on = cover.iter().any(|c| c.covers(m));
+            assert_ne!(on, off.iter().any(|c| c.covers(m)));
+        }
+    }
+
+    #[test]
+    fn matches_exact_minimization() {
+        let on: Vec<u64> = vec![0b000, 0b001, 0b011, 0b111, 0b110, 0b100];
+        let minterms: Vec<Cube> = on.iter().map(|m| Cube::minterm(*m, 3)).collect();
+        assert_eq!(3, espresso(&minterms, &[]).len());
+
+        // Don't cares let a & ~b grow into a
+        let res = espresso(&[cube("10")], &[cube("11")]);
+        assert_eq!(vec![cube("1-")], res);
+    }
+
+    #[test]
+    fn wide_functions() {
+        // Thirty inputs; the redundant products vanish and the consensus term merges away
+        let expr = "(a & b) | (c & d) | (a & b & e) | (f & g & h) | (f & g & ~h) | (i ^ j) | (k & l & m & n & o & p & q & r & s & t) | (u & v) | (w & x) | (y & z) | (A & B & C & D)";
+        let parser = parser::create_session(vec![String::from(expr)]).unwrap();
+        let res = espresso_session(&parser).unwrap();
+        assert_eq!("(a & b) | (c & d) | (f & g) | (i & ~j) | (~i & j) | (k & l & m & n & o & p & q & r & s & t) | (u & v) | (w & x) | (y & z) | (A & B & C & D)", res[0]);
+
+        let order: HashMap<char, usize> = parser.ast_order.iter().map(|(c, level)| (*c, *level)).collect();
+        let from_expr = rpn_cover(&parser.exprs[0].shared_rpn, &parser.defs, &order);
+        assert_eq!(10, espresso(&from_expr, &[]).len());
+    }
+
+    #[test]
+    fn covers_through_definitions() {
+        let parser = parser::create_session(vec![String::from("maj(x, y, z) = (x & y) | (x & z) | (y & z)"),
+            String::from("half = a ^ b"), String::from("maj(half, c, ~half) | maj(a, a, d)")]).unwrap();
+        let order: HashMap<char, usize> = parser.ast_order.iter().map(|(c, level)| (*c, *level)).collect();
+        let names: Vec<char> = parser.ast_order.keys().copied().collect();
+
+        // maj(h, c, ~h) is just c, and maj(a, a, d) is a
+        let mut bdd = areabdd::build(BDD::new(), &parser);
+        let cover = rpn_cover(&parser.exprs[0].shared_rpn, &parser.defs, &order);
+        let root = bdd.roots()[0];
+        assert_eq!(root, cover_to_bdd(&mut bdd, &cover, &names));
+        assert_eq!(2, espresso(&cover, &[]).len());
+    }
+
+    #[test]
+    fn large_covers_are_refused() {
+        // Parity of 13 inputs has 2^12 products, as many as Espresso takes; one more input doubles that
+        let names: Vec<String> = ('a'..='n').map(|c| c.to_string()).collect();
+        let parser = parser::create_session(vec![names[..13].join(" ^ "), names.join(" ^ ")]).unwrap();
+        let bdd = areabdd::build(BDD::new(), &parser);
+        assert_eq!(vec![MAX_COVER, 2 * MAX_COVER], bdd.roots().iter().map(|root| path_count(&bdd, *root)).collect::<Vec<usize>>());
+
+        let parser = parser::create_session(vec![names.join(" ^ ")]).unwrap();
+        assert!(espresso_session(&parser).unwrap_err().contains("at most 4096 paths"));
+    }
+}
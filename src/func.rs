@@ -0,0 +1,259 @@
+// Reference-counted handles to functions in a BDD
+// A Func holds one reference on its root for as long as it lives: cloning takes another and dropping releases it,
+// so vertex reference counts always match the handles that are alive
+
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+use std::rc::Rc;
+use crate::areabdd::{self, BDD};
+use crate::parser::Operator;
+
+pub type Manager = Rc<RefCell<BDD>>;
+
+pub struct Func {
+    bdd: Manager,
+    edge: isize
+}
+
+// Wraps a BDD so that handles can share it
+pub fn manager(bdd: BDD) -> Manager {
+    return Rc::new(RefCell::new(bdd));
+}
+
+impl Func {
+    // Takes a new reference on an edge of the managed BDD
+    pub fn from_edge(bdd: &Manager, edge: isize) -> Func {
+        areabdd::inc_ref(&mut bdd.borrow_mut(), &edge);
+        return Func {bdd: Rc::clone(bdd), edge};
+    }
+
+    pub fn constant(bdd: &Manager, val: bool) -> Func {
+        return Func::from_edge(bdd, if val { 1 } else { -1 });
+    }
+
+    // The function of a single variable, which is placed last in the ordering if it is new
+    pub fn var(bdd: &Manager, var: char) -> Func {
+        let edge = areabdd::var_edge(&mut bdd.borrow_mut(), var);
+        return Func::from_edge(bdd, edge);
+    }
+
+    // Handles to every root built into the BDD, in build order
+    pub fn roots(bdd: &Manager) -> Vec<Func> {
+        let edges = bdd.borrow().roots().to_vec();
+        return edges.into_iter().map(|edge| Func::from_edge(bdd, edge)).collect();
+    }
+
+    pub fn edge(&self) -> isize {
+        return self.edge;
+    }
+
+    pub fn manager(&self) -> &Manager {
+        return &self.bdd;
+    }
+
+    pub fn is_true(&self) -> bool {
+        return self.edge == 1;
+    }
+
+    pub fn is_false(&self) -> bool {
+        return self.edge == -1;
+    }
+
+    // If self then g else h
+    pub fn ite(&self, g: &Func, h: &Func) -> Func {
+        self.check_manager(g);
+        self.check_manager(h);
+        let edge = areabdd::ite(&mut self.bdd.borrow_mut(), self.edge, g.edge, h.edge);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn apply(&self, op: &Operator, rhs: &Func) -> Func {
+        self.check_manager(rhs);
+        let edge = areabdd::apply(&mut self.bdd.borrow_mut(), op, self.edge, rhs.edge);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn exists(&self, vars: &[char]) -> Func {
+        let edge = areabdd::exists(&mut self.bdd.borrow_mut(), vars, self.edge);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn forall(&self, vars: &[char]) -> Func {
+        let edge = areabdd::forall(&mut self.bdd.borrow_mut(), vars, self.edge);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn restrict(&self, var: char, value: bool) -> Func {
+        let edge = areabdd::restrict(&mut self.bdd.borrow_mut(), self.edge, var, value);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn compose(&self, var: char, g: &Func) -> Func {
+        self.check_manager(g);
+        let edge = areabdd::compose(&mut self.bdd.borrow_mut(), self.edge, var, g.edge);
+        return Func::from_edge(&self.bdd, edge);
+    }
+
+    pub fn evaluate(&self, values: &[bool]) -> bool {
+        return areabdd::evaluate(&self.bdd.borrow(), self.edge, values);
+    }
+
+    pub fn satisfy_count(&self) -> usize {
+        return areabdd::satisfy_count(&self.bdd.borrow(), self.edge);
+    }
+
+    // Edges from two different BDDs mean nothing to each other
+    fn check_manager(&self, other: &Func) {
+        assert!(Rc::ptr_eq(&self.bdd, &other.bdd), "Combined functions from different BDDs");
+    }
+}
+
+impl Clone for Func {
+    fn clone(&self) -> Self {
+        return Func::from_edge(&self.bdd, self.edge);
+    }
+}
+
+impl Drop for Func {
+    fn drop(&mut self) {
+        areabdd::dec_ref(&mut self.bdd.borrow_mut(), &self.edge);
+    }
+}
+
+// BDDs are canonical, so handles to the same function always hold the same edge
+impl PartialEq for Func {
+    fn eq(&self, other: &Self) -> bool {
+        return Rc::ptr_eq(&self.bdd, &other.bdd) && self.edge == other.edge;
+    }
+}
+
+impl Eq for Func {}
+
+impl fmt::Debug for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return write!(f, "Func({})", self.edge);
+    }
+}
+
+impl Not for &Func {
+    type Output = Func;
+
+    fn not(self) -> Func {
+        // Complement edges make negation free
+        return Func::from_edge(&self.bdd, -self.edge);
+    }
+}
+
+impl Not for Func {
+    type Output = Func;
+
+    fn not(self) -> Func {
+        return !&self;
+    }
+}
+
+// Binary operators take either side by value or by reference
+impl<R: std::borrow::Borrow<Func>> BitAnd<R> for &Func {
+    type Output = Func;
+
+    fn bitand(self, rhs: R) -> Func {
+        return self.apply(&Operator::AND, std::borrow::Borrow::borrow(&rhs));
+    }
+}
+
+impl<R: std::borrow::Borrow<Func>> BitAnd<R> for Func {
+    type Output = Func;
+
+    fn bitand(self, rhs: R) -> Func {
+        return &self & rhs;
+    }
+}
+
+impl<R: std::borrow::Borrow<Func>> BitOr<R> for &Func {
+    type Output = Func;
+
+    fn bitor(self, rhs: R) -> Func {
+        return self.apply(&Operator::OR, std::borrow::Borrow::borrow(&rhs));
+    }
+}
+
+impl<R: std::borrow::Borrow<Func>> BitOr<R> for Func {
+    type Output = Func;
+
+    fn bitor(self, rhs: R) -> Func {
+        return &self | rhs;
+    }
+}
+
+impl<R: std::borrow::Borrow<Func>> BitXor<R> for &Func {
+    type Output = Func;
+
+    fn bitxor(self, rhs: R) -> Func {
+        return self.apply(&Operator::XOR, std::borrow::Borrow::borrow(&rhs));
+    }
+}
+
+impl<R: std::borrow::Borrow<Func>> BitXor<R> for Func {
+    type Output = Func;
+
+    fn bitxor(self, rhs: R) -> Func {
+        return &self ^ rhs;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn operators_are_canonical() {
+        let bdd = manager(BDD::new());
+        let (a, b, c) = (Func::var(&bdd, 'a'), Func::var(&bdd, 'b'), Func::var(&bdd, 'c'));
+
+        assert_eq!(&a & &b, &b & &a);
+        assert_eq!(!(&a | &b), !&a & !&b);
+        assert_eq!(&a ^ &b, (&a & !&b) | (!&a & &b));
+        assert_eq!(a.ite(&b, &c), (&a & &b) | (!&a & &c));
+        assert!((&a | !&a).is_true());
+        assert!((&a & !&a).is_false());
+        assert_eq!(6, (&a | &b).satisfy_count());
+    }
+
+    #[test]
+    fn dropping_handles_frees_vertices() {
+        let bdd = manager(BDD::new());
+        let a = Func::var(&bdd, 'a');
+        let b = Func::var(&bdd, 'b');
+        assert_eq!(0, bdd.borrow().dead_count());
+
+        let f = &a & &b;
+        let g = f.clone();
+        assert_eq!(2, bdd.borrow().ref_count(f.edge()));
+        drop(f);
+        assert_eq!(1, bdd.borrow().ref_count(g.edge()));
+        assert_eq!(0, bdd.borrow().dead_count());
+
+        drop(g);
+        assert_eq!(1, bdd.borrow().dead_count());
+        drop(a);
+        drop(b);
+        assert_eq!(bdd.borrow().vertex_count() - 1, bdd.borrow().dead_count());
+    }
+
+    #[test]
+    fn handles_leave_no_references_behind() {
+        let bdd = manager(BDD::new());
+        let vars: Vec<Func> = "abcd".chars().map(|c| Func::var(&bdd, c)).collect();
+        let f = (&vars[0] & &vars[1]) | (&vars[2] ^ &vars[3]);
+        let g = f.exists(&['a']).compose('c', &!&vars[1]);
+        assert_eq!(1, bdd.borrow().ref_count(f.edge()));
+
+        drop(vars);
+        drop(f);
+        drop(g);
+        let bdd = bdd.borrow();
+        assert!((2..=bdd.vertex_count() as isize).all(|id| bdd.ref_count(id) == 0));
+        assert_eq!(bdd.vertex_count() - 1, bdd.dead_count());
+    }
+}
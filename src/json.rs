@@ -0,0 +1,249 @@
+// Just enough JSON for the command line's output and the server's requests
+// Output is written directly with json_string and json_list; input is read into a Value tree. Objects keep their
+// keys in order, and nesting is capped so a hostile request cannot exhaust the stack.
+
+const MAX_DEPTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>)
+}
+
+impl Value {
+    // The value of a key in an object; the last one wins if a key repeats
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(members) => members.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None
+        }
+    }
+}
+
+pub fn json_list<T: ToString>(items: impl Iterator<Item = T>) -> String {
+    return format!("[{}]", items.map(|item| item.to_string()).collect::<Vec<String>>().join(", "));
+}
+
+pub fn json_string(s: &str) -> String {
+    let mut res = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => res.push_str(&format!("\\u{:04x}", c as u32)),
+            c => res.push(c)
+        }
+    }
+    res.push('"');
+    return res;
+}
+
+pub fn parse(input: &str) -> Result<Value, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut pos = 0;
+    let value = parse_value(&chars, &mut pos, 0)?;
+    skip_whitespace(&chars, &mut pos);
+    if pos != chars.len() {
+        return Err(format!("Unexpected {} after the JSON value", chars[pos]));
+    }
+    return Ok(value);
+}
+
+fn skip_whitespace(chars: &[char], pos: &mut usize) {
+    while *pos < chars.len() && matches!(chars[*pos], ' ' | '\t' | '\n' | '\r') {
+        *pos += 1;
+    }
+}
+
+fn expect(chars: &[char], pos: &mut usize, c: char) -> Result<(), String> {
+    skip_whitespace(chars, pos);
+    match chars.get(*pos) {
+        Some(next) if *next == c => {
+            *pos += 1;
+            return Ok(());
+        },
+        Some(next) => return Err(format!("Expected {c} but found {next}")),
+        None => return Err(format!("Expected {c} but the JSON ended"))
+    }
+}
+
+fn parse_value(chars: &[char], pos: &mut usize, depth: usize) -> Result<Value, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("JSON is nested more than {MAX_DEPTH} levels deep"));
+    }
+
+    skip_whitespace(chars, pos);
+    match chars.get(*pos) {
+        None => return Err(String::from("Expected a value but the JSON ended")),
+        Some('{') => {
+            *pos += 1;
+            let mut members = Vec::new();
+            skip_whitespace(chars, pos);
+            if chars.get(*pos) == Some(&'}') {
+                *pos += 1;
+                return Ok(Value::Object(members));
+            }
+            loop {
+                skip_whitespace(chars, pos);
+                if chars.get(*pos) != Some(&'"') {
+                    return Err(String::from("Expected a string key in a JSON object"));
+                }
+                let key = parse_string(chars, pos)?;
+                expect(chars, pos, ':')?;
+                members.push((key, parse_value(chars, pos, depth + 1)?));
+
+                skip_whitespace(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some('}') => {
+                        *pos += 1;
+                        return Ok(Value::Object(members));
+                    },
+                    _ => return Err(String::from("Expected , or } in a JSON object"))
+                }
+            }
+        },
+        Some('[') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            skip_whitespace(chars, pos);
+            if chars.get(*pos) == Some(&']') {
+                *pos += 1;
+                return Ok(Value::Array(items));
+            }
+            loop {
+                items.push(parse_value(chars, pos, depth + 1)?);
+                skip_whitespace(chars, pos);
+                match chars.get(*pos) {
+                    Some(',') => *pos += 1,
+                    Some(']') => {
+                        *pos += 1;
+                        return Ok(Value::Array(items));
+                    },
+                    _ => return Err(String::from("Expected , or ] in a JSON array"))
+                }
+            }
+        },
+        Some('"') => return Ok(Value::String(parse_string(chars, pos)?)),
+        Some(c) if *c == '-' || c.is_ascii_digit() => {
+            let start = *pos;
+            while *pos < chars.len() && matches!(chars[*pos], '0'..='9' | '-' | '+' | '.' | 'e' | 'E') {
+                *pos += 1;
+            }
+            let number: String = chars[start..*pos].iter().collect();
+            return number.parse().map(Value::Number).map_err(|_| format!("Invalid JSON number {number}"));
+        },
+        Some(_) => {
+            for (word, value) in [("true", Value::Bool(true)), ("false", Value::Bool(false)), ("null", Value::Null)] {
+                if chars[*pos..].iter().take(word.len()).copied().eq(word.chars()) {
+                    *pos += word.len();
+                    return Ok(value);
+                }
+            }
+            return Err(format!("Unexpected {} in JSON", chars[*pos]));
+        }
+    }
+}
+
+// Starts on the opening quote
+fn parse_string(chars: &[char], pos: &mut usize) -> Result<String, String> {
+    *pos += 1;
+    let mut res = String::new();
+    loop {
+        let c = *chars.get(*pos).ok_or("Unterminated JSON string")?;
+        *pos += 1;
+        match c {
+            '"' => return Ok(res),
+            '\\' => {
+                let escape = *chars.get(*pos).ok_or("Unterminated JSON string")?;
+                *pos += 1;
+                match escape {
+                    '"' | '\\' | '/' => res.push(escape),
+                    'n' => res.push('\n'),
+                    't' => res.push('\t'),
+                    'r' => res.push('\r'),
+                    'b' => res.push('\u{8}'),
+                    'f' => res.push('\u{c}'),
+                    'u' => {
+                        let mut code = parse_hex(chars, pos)?;
+                        // A high surrogate must be followed by an escaped low one
+                        if (0xd800..0xdc00).contains(&code) && chars.get(*pos) == Some(&'\\') && chars.get(*pos + 1) == Some(&'u') {
+                            *pos += 2;
+                            let low = parse_hex(chars, pos)?;
+                            code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        res.push(char::from_u32(code).ok_or("Invalid \\u escape in JSON string")?);
+                    },
+                    e => return Err(format!("Invalid escape \\{e} in JSON string"))
+                }
+            },
+            c if (c as u32) < 0x20 => return Err(String::from("Unescaped control character in JSON string")),
+            c => res.push(c)
+        }
+    }
+}
+
+fn parse_hex(chars: &[char], pos: &mut usize) -> Result<u32, String> {
+    let digits: String = chars.iter().skip(*pos).take(4).collect();
+    *pos += 4;
+    return u32::from_str_radix(&digits, 16).map_err(|_| format!("Invalid \\u escape {digits} in JSON string"));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_values() {
+        let value = parse(" {\"exprs\": [\"a & b\", \"say \\\"hi\\\"\\u00e9\\ud83d\\ude00\"], \"n\": -1.5e2, \"ok\": true, \"none\": null, \"empty\": {}} ").unwrap();
+        let exprs = value.get("exprs").and_then(|e| e.as_array()).unwrap();
+        assert_eq!(Some("a & b"), exprs[0].as_str());
+        assert_eq!(Some("say \"hi\"\u{e9}\u{1f600}"), exprs[1].as_str());
+        assert_eq!(Some(&Value::Number(-150.0)), value.get("n"));
+        assert_eq!(Some(true), value.get("ok").and_then(|v| v.as_bool()));
+        assert_eq!(Some(&Value::Null), value.get("none"));
+        assert_eq!(Some(&Value::Object(Vec::new())), value.get("empty"));
+        assert_eq!(None, value.get("missing"));
+
+        // Strings written out read back in
+        assert_eq!("\"say \\\"hi\\\"\\\\\\n\"", json_string("say \"hi\"\\\n"));
+        let s = "tab\t, newline\n, quote \" and \u{1}";
+        assert_eq!(Value::String(String::from(s)), parse(&json_string(s)).unwrap());
+    }
+
+    #[test]
+    fn rejects_malformed_json() {
+        for input in ["", "{", "[1, 2", "{\"a\" 1}", "{a: 1}", "[1] 2", "\"unterminated", "tru", "\"\\x\"", "[01x]"] {
+            assert!(parse(input).is_err(), "{input} should not parse");
+        }
+        assert!(parse(&"[".repeat(MAX_DEPTH + 2)).is_err());
+        assert!(parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_ok());
+    }
+}
@@ -0,0 +1,26 @@
+// Functions here end with an explicit return, as the original modules were written, so clippy's needless_return
+// is allowed for the whole library rather than rewriting every function
+#![allow(clippy::needless_return)]
+
+pub mod parser;
+pub mod ast;
+// pub mod bdd;
+pub mod areabdd;
+pub mod cache;
+pub mod bddio;
+pub mod func;
+pub mod zdd;
+pub mod sat;
+pub mod miter;
+pub mod aig;
+pub mod sharedbdd;
+pub mod minimize;
+pub mod espresso;
+pub mod normalform;
+pub mod anf;
+pub mod pretty;
+pub mod classes;
+pub mod query;
+pub mod bus;
+pub mod session;
+pub mod json;
@@ -1,35 +1,471 @@
 use std::io::*;
+use std::{env, process};
 use tabled::{builder::Builder, ModifyObject, object::Rows, Alignment, Style};
+use std::collections::HashMap;
+use bool_eq::{parser, ast, aig, anf, areabdd, bddio, bus, classes, espresso, miter, minimize, normalform, pretty, query, sharedbdd};
+use bool_eq::parser::Operator;
 
-mod parser;
-mod ast;
-// mod bdd;
-mod areabdd;
+mod repl;
+mod serve;
+
+// Which method decides equivalence; only the ast engine prints the full truth table
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Engine {
+    Ast,
+    Bdd,
+    Sat
+}
+
+const MAX_EXACT_VARS: usize = 12;
+
+// The first argument may name a command; without one the expressions are checked for equivalence
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Check,
+    Minimize,
+    Convert(normalform::Form),
+    Anf,
+    Fmt,
+    Classes,
+    Query(query::Query),
+    Implies,
+    Repl,
+    Serve
+}
+
+struct Options {
+    command: Command,
+    shared: bool,
+    heuristic: bool,
+    limit: Option<usize>,
+    dialect: Option<pretty::Dialect>,
+    json: bool,
+    paths: Vec<String>,
+    engine: Engine,
+    fixed: Vec<(char, bool)>,
+    assume: Option<String>,
+    dot_path: Option<String>,
+    save_path: Option<String>,
+    load_paths: Vec<String>,
+    port: Option<u16>,
+    max_body: Option<usize>,
+    timeout: Option<std::time::Duration>,
+    workers: Option<usize>
+}
 
 fn main() {
+    let options = parse_args(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
 
-    let raw_inputs = get_user_input();
-    let input = parser::create_session(raw_inputs.clone()).unwrap();
-    let ast_session = ast::build_ast_session(&input);
+    let dialect = options.dialect.unwrap_or(pretty::Dialect::Ascii);
+    if options.command == Command::Fmt && !options.paths.is_empty() {
+        format_files(&options.paths, dialect);
+        return;
+    } else if options.command == Command::Repl {
+        repl::run(options);
+        return;
+    } else if options.command == Command::Serve {
+        serve::run(&options);
+        return;
+    }
+
+    let mut input = parser::create_session(get_user_input()).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+    let raw_inputs = input.raw_exprs().to_vec(); // Definitions are not expressions to compare
+    if let Some(care) = options.assume.clone() {
+        input.set_care(care).unwrap_or_else(|e| {
+            eprintln!("Invalid assumption given to --assume: {e}");
+            process::exit(1);
+        });
+    }
+
+    for (var, _) in options.fixed.iter() {
+        if !input.ast_order.contains_key(var) {
+            eprintln!("Variable {var} given to --fix does not appear in any expression");
+            process::exit(1);
+        }
+    }
+
+    if options.command == Command::Minimize {
+        let minimized = minimize_session(&input, options.shared, options.heuristic).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            process::exit(1);
+        });
+        for (raw, minimized) in raw_inputs.iter().zip(minimized) {
+            println!("{raw} => {minimized}");
+        }
+        return;
+    }
+
+    if let Command::Convert(form) = options.command {
+        let converted = normalform::convert_session(&input, form, options.limit.unwrap_or(normalform::DEFAULT_LIMIT))
+            .unwrap_or_else(|e| {
+                eprintln!("{e}");
+                process::exit(1);
+            });
+        for (raw, converted) in raw_inputs.iter().zip(converted) {
+            println!("{raw} => {converted}");
+        }
+        return;
+    }
+
+    if options.command == Command::Fmt {
+        for line in session_lines(&input, dialect) {
+            println!("{line}");
+        }
+        return;
+    }
+
+    if options.command == Command::Anf {
+        print_anf(&input, &raw_inputs);
+        return;
+    }
+
+    if options.command == Command::Classes {
+        let classes = classes::classify(&input, &options.fixed);
+        if options.json {
+            println!("{}", classes.to_json(&input, &raw_inputs));
+        } else {
+            print_classes(&input, &raw_inputs, &classes);
+        }
+        return;
+    }
+
+    if let Command::Query(query) = options.command {
+        print_query(&input, &raw_inputs, query, &mut searcher(&input, options.engine, &options.fixed));
+        return;
+    }
+
+    if options.command == Command::Implies {
+        print_implications(&input, &raw_inputs, &mut searcher(&input, options.engine, &options.fixed));
+        return;
+    }
+
+    if !input.buses.is_empty() {
+        check_buses(&input, &raw_inputs, &mut searcher(&input, options.engine, &options.fixed)).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            process::exit(1);
+        });
+    } else {
+        check_exprs(&input, &raw_inputs, &options);
+    }
+
+    if options.dot_path.is_some() || options.save_path.is_some() || !options.load_paths.is_empty() {
+        let mut bdd = areabdd::build(areabdd::BDD::new(), &input);
+        if let Some(path) = options.dot_path.as_ref() {
+            write_dot(&bdd, &raw_inputs, path);
+        }
+        if let Some(path) = options.save_path.as_ref() {
+            save_bdd(&bdd, &raw_inputs, path);
+        }
+        let care = areabdd::build_care(&mut bdd, &input);
+        for path in options.load_paths.iter() {
+            compare_reference(&mut bdd, care, &raw_inputs, path);
+        }
+    }
+}
+
+// Quine-McCluskey lists every minterm, so larger sessions fall back to the Espresso heuristic
+fn minimize_session(input: &parser::Parser, shared: bool, heuristic: bool) -> std::result::Result<Vec<String>, String> {
+    if !heuristic && input.ast_order.len() <= MAX_EXACT_VARS {
+        return Ok(minimize::minimize_session(input, shared));
+    } else if shared {
+        return Err(format!("--shared needs exact minimization, which is limited to {MAX_EXACT_VARS} variables"));
+    }
+
+    espresso::espresso_session(input)
+}
+
+// The ANF is canonical, so matching polynomials also settle equivalence
+fn print_anf(input: &parser::Parser, raw_inputs: &[String]) {
+    let names: Vec<char> = input.ast_order.keys().copied().collect();
+    let anfs = anf::anf_session(input);
+    for (raw, anf) in raw_inputs.iter().zip(anfs.iter()) {
+        println!("{raw} => {} (degree {})", anf::to_expr(anf, &names), anf.degree);
+    }
+
+    if anfs.len() > 1 && anfs.windows(2).all(|pair| pair[0] == pair[1]) {
+        println!("All expressions share one ANF, so they are logically equivalent");
+    } else if anfs.len() > 1 {
+        println!("The expressions do not all share one ANF, so they are not all logically equivalent");
+    }
+}
+
+// Lists the classes, then the pairwise agreement matrix and one failure case for each pair of classes
+fn print_classes(input: &parser::Parser, raw_inputs: &[String], classes: &classes::Classes) {
+    for (ndx, class) in classes.classes.iter().enumerate() {
+        let members: Vec<&str> = class.iter().map(|e| raw_inputs[*e].as_str()).collect();
+        println!("Class {}: {}", ndx + 1, members.join(", "));
+    }
+
+    let mut matrix = Builder::default();
+    matrix.set_columns(std::iter::once(String::new()).chain(raw_inputs.iter().cloned()));
+    for (raw, row) in raw_inputs.iter().zip(classes.matrix()) {
+        matrix.add_record(std::iter::once(raw.clone()).chain(row.iter().map(|b| usize::from(*b).to_string())));
+    }
+    println!("{}", matrix.build().with(Style::rounded()).with(Rows::new(1..).modify().with(Alignment::center())));
+
+    if classes.all_eq() {
+        println!("Congrats! All expressions are logically equivalent");
+        return;
+    }
 
-    let mut truth_table = Builder::default();
     let mut cex_table = Builder::default();
+    cex_table.set_columns(std::iter::once(String::from("classes"))
+        .chain(input.ast_order.keys().map(|c| c.to_string()))
+        .chain(raw_inputs.iter().cloned()));
+    for (i, j, row) in classes.witnesses.iter() {
+        cex_table.add_record(std::iter::once(format!("{} vs {}", i + 1, j + 1))
+            .chain(row.iter().map(|b| usize::from(*b).to_string())));
+    }
+    println!("Failure cases between classes are as follows: ");
+    println!("{}", cex_table.build().with(Style::rounded()).with(Rows::new(1..).modify().with(Alignment::center())));
+}
 
-    truth_table.set_columns(input.ast_order.keys().map(|c| c.to_string()).chain(raw_inputs.clone().into_iter()));
-    cex_table.set_columns(input.ast_order.keys().map(|c| c.to_string()).chain(raw_inputs.clone().into_iter()));
+fn searcher(input: &parser::Parser, engine: Engine, fixed: &[(char, bool)]) -> query::Searcher {
+    match engine {
+        Engine::Ast => query::Searcher::ast(input, fixed),
+        Engine::Bdd => query::Searcher::bdd(input, fixed),
+        Engine::Sat => query::Searcher::sat(input, fixed)
+    }
+}
 
-    for (case, res) in ast_session.cases.iter().zip(ast_session.results.iter()) {
-        let mut case = case.clone();
-        case.append(&mut res.clone());
-        let table_row: Vec<usize> = case.iter().map(|b| b.clone().into()).collect();
-        let table_row: Vec<String> = table_row.iter().map(|e| e.to_string()).collect();
-        truth_table.add_record(table_row);
+// One verdict per expression, followed by the witnesses: falsifying inputs for tautology, satisfying ones otherwise
+fn print_query(input: &parser::Parser, raw_inputs: &[String], query: query::Query, searcher: &mut query::Searcher) {
+    let (holds, fails, witnesses) = match query {
+        query::Query::Tautology => ("is a tautology", "is not a tautology", "Falsifying"),
+        query::Query::Sat => ("is satisfiable", "is unsatisfiable", "Satisfying"),
+        query::Query::Unsat => ("is unsatisfiable", "is satisfiable", "Satisfying")
+    };
+
+    let mut rows: Vec<(String, Vec<bool>)> = Vec::new();
+    for (ndx, raw) in raw_inputs.iter().enumerate() {
+        let verdict = query::check(searcher, query, ndx);
+        println!("{raw} {}", if verdict.holds { holds } else { fails });
+        if let Some(witness) = verdict.witness {
+            rows.push((raw.clone(), witness));
+        }
     }
 
-    for cex in ast_session.cex.iter() {
-        let table_row: Vec<usize> = cex.iter().map(|b| b.clone().into()).collect();
-        let table_row: Vec<String> = table_row.iter().map(|e| e.to_string()).collect(); 
-        cex_table.add_record(table_row);
+    if !rows.is_empty() {
+        println!("{witnesses} assignments are as follows: ");
+        print_witnesses(input, raw_inputs, &rows);
+    }
+}
+
+// Prints which expressions imply which, the Hasse diagram of that order, and a failure case for every non-implication
+fn print_implications(input: &parser::Parser, raw_inputs: &[String], searcher: &mut query::Searcher) {
+    let lattice = query::lattice(searcher, raw_inputs.len());
+    if raw_inputs.len() == 2 {
+        let verb = if lattice[0][1].holds { "implies" } else { "does not imply" };
+        println!("{} {verb} {}", raw_inputs[0], raw_inputs[1]);
+    }
+
+    let mut matrix = Builder::default();
+    matrix.set_columns(std::iter::once(String::from("implies")).chain(raw_inputs.iter().cloned()));
+    for (raw, row) in raw_inputs.iter().zip(lattice.iter()) {
+        matrix.add_record(std::iter::once(raw.clone()).chain(row.iter().map(|v| usize::from(v.holds).to_string())));
+    }
+    println!("{}", matrix.build().with(Style::rounded()).with(Rows::new(1..).modify().with(Alignment::center())));
+
+    println!("Implication lattice: ");
+    for (i, row) in lattice.iter().enumerate() {
+        for (j, verdict) in row.iter().enumerate().skip(i + 1) {
+            if verdict.holds && lattice[j][i].holds {
+                println!("  {} <=> {}", raw_inputs[i], raw_inputs[j]);
+            }
+        }
+    }
+    for (i, j) in query::covers(&lattice) {
+        println!("  {} => {}", raw_inputs[i], raw_inputs[j]);
+    }
+
+    let rows: Vec<(String, Vec<bool>)> = lattice.iter().enumerate()
+        .flat_map(|(i, row)| row.iter().enumerate().filter_map(move |(j, v)| v.witness.clone().map(|w| (i, j, w))))
+        .map(|(i, j, witness)| (format!("{} =/> {}", raw_inputs[i], raw_inputs[j]), witness))
+        .collect();
+    if !rows.is_empty() {
+        println!("Failure cases are as follows: ");
+        print_witnesses(input, raw_inputs, &rows);
+    }
+}
+
+fn print_witnesses(input: &parser::Parser, raw_inputs: &[String], rows: &[(String, Vec<bool>)]) {
+    let mut table = Builder::default();
+    table.set_columns(std::iter::once(String::new())
+        .chain(input.ast_order.keys().map(|c| c.to_string()))
+        .chain(raw_inputs.iter().cloned()));
+    for (label, row) in rows.iter() {
+        table.add_record(std::iter::once(label.clone()).chain(row.iter().map(|b| usize::from(*b).to_string())));
+    }
+    println!("{}", table.build().with(Style::rounded()).with(Rows::new(1..).modify().with(Alignment::center())));
+}
+
+// The session's definitions, then its expressions, in a form that reads back in; bus bits are written one per line
+fn session_lines(input: &parser::Parser, dialect: pretty::Dialect) -> Vec<String> {
+    let mut lines: Vec<String> = input.defs.iter().map(|def| pretty::definition(def, &input.defs, dialect)).collect();
+    let bits: Vec<usize> = input.buses.iter().flat_map(|bus| bus.bits.values().copied()).collect();
+    for (ndx, (raw, expr)) in input.raw_exprs().iter().zip(input.exprs.iter()).enumerate() {
+        let expr = pretty::Expr::from_shared_rpn(&expr.shared_rpn, &input.defs, &[]);
+        if bits.contains(&ndx) {
+            lines.push(format!("{raw} = {}", expr.display(dialect)));
+        } else {
+            lines.push(expr.display(dialect).to_string());
+        }
+    }
+    lines
+}
+
+// Rewrites each file with one expression per line in canonical spacing and spelling, keeping blank lines
+// Only ASCII output reads back in, so other dialects are printed instead of written over the file
+fn format_files(paths: &[String], dialect: pretty::Dialect) {
+    let mut failed = false;
+    for path in paths.iter() {
+        // Only a file with errors of its own is left untouched; the others are still formatted
+        let mut file_failed = false;
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) => {
+                eprintln!("Failed to read {path}: {e}");
+                failed = true;
+                continue;
+            }
+        };
+
+        // Lines share one session so that expressions can use the definitions above them
+        let mut session = parser::create_session(Vec::new()).unwrap();
+        let mut lines: Vec<String> = Vec::new();
+        for (ndx, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                lines.push(String::new());
+                continue;
+            }
+
+            let (num_defs, num_exprs) = (session.defs.len(), session.exprs.len());
+            match session.add_expr(vec![line.to_string()]) {
+                Ok(()) if session.defs.len() > num_defs => {
+                    lines.push(pretty::definition(session.defs.last().unwrap(), &session.defs, dialect));
+                },
+                Ok(()) if line.split_once('=').is_some() => {
+                    // Bus bits keep their range as written
+                    let lhs: String = line.split_once('=').unwrap().0.chars().filter(|c| !c.is_whitespace()).collect();
+                    let bits: Vec<String> = session.exprs[num_exprs..].iter()
+                        .map(|expr| pretty::Expr::from_shared_rpn(&expr.shared_rpn, &session.defs, &[]).display(dialect).to_string())
+                        .collect();
+                    lines.push(format!("{lhs} = {}", bits.join(", ")));
+                },
+                Ok(()) => {
+                    let expr = session.exprs.last().unwrap();
+                    lines.push(pretty::Expr::from_shared_rpn(&expr.shared_rpn, &session.defs, &[]).display(dialect).to_string());
+                },
+                Err(e) => {
+                    eprintln!("{path}:{}: {e}", ndx + 1);
+                    file_failed = true;
+                }
+            }
+        }
+
+        let formatted = lines.join("\n") + "\n";
+        if file_failed {
+            failed = true;
+            continue;
+        } else if dialect != pretty::Dialect::Ascii {
+            print!("{formatted}");
+        } else if formatted != contents {
+            match std::fs::write(path, formatted) {
+                Ok(()) => println!("Formatted {path}"),
+                Err(e) => {
+                    eprintln!("Failed to write {path}: {e}");
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+}
+
+fn check_exprs(input: &parser::Parser, raw_inputs: &[String], options: &Options) {
+    if options.engine == Engine::Ast {
+        check_ast(input, raw_inputs, &options.fixed);
+    } else {
+        let (all_eq, cex) = compare_exprs(input, options.engine, &options.fixed);
+        print_verdict(input, raw_inputs, all_eq, &cex);
+    }
+}
+
+// The verdict of the chosen engine along with its failure cases, each the input values followed by every result
+fn compare_exprs(input: &parser::Parser, engine: Engine, fixed: &[(char, bool)]) -> (bool, Vec<Vec<bool>>) {
+    match engine {
+        Engine::Ast => {
+            let session = ast::build_ast_session_fixed(input, fixed);
+            (session.all_eq, session.cex)
+        },
+        Engine::Bdd | Engine::Sat if fraig_proves_equal(input, fixed) => (true, Vec::new()),
+        Engine::Bdd => check_bdd(input, fixed),
+        Engine::Sat => {
+            let session = miter::check_session(input, fixed, 1);
+            (session.all_eq, session.cex)
+        }
+    }
+}
+
+// Compares every bus against the first one bit by bit, with a verdict for each bit and a failure case naming it
+fn check_buses(input: &parser::Parser, raw_inputs: &[String], searcher: &mut query::Searcher) -> std::result::Result<(), String> {
+    let num_bits: usize = input.buses.iter().map(|bus| bus.bits.len()).sum();
+    if num_bits != input.exprs.len() {
+        return Err(String::from("Sessions with buses can only compare buses; assign every expression to a bit"));
+    }
+    let verdicts = bus::compare(input, searcher)?;
+
+    let first = &input.buses[0];
+    let others = &input.buses[1..];
+    let mut table = Builder::default();
+    table.set_columns(std::iter::once(String::from("bit")).chain(others.iter().map(|bus| format!("{} vs {}", first.name, bus.name))));
+    for (bit, row) in first.bits.keys().zip(verdicts.chunks(others.len().max(1))) {
+        let row = row.iter().map(|v| String::from(if v.equal { "equal" } else { "differs" }));
+        table.add_record(std::iter::once(bit.to_string()).chain(row));
+    }
+    println!("{}", table.build().with(Style::rounded()).with(Rows::new(1..).modify().with(Alignment::center())));
+
+    let rows: Vec<(String, Vec<bool>)> = verdicts.iter()
+        .filter_map(|v| v.witness.clone().map(|w| (format!("{0}[{2}] vs {1}[{2}]", first.name, input.buses[v.bus].name, v.bit), w)))
+        .collect();
+    if rows.is_empty() {
+        println!("Congrats! All buses are logically equivalent bit by bit");
+    } else {
+        println!("Not all buses are logically equivalent");
+        println!("Failure cases are as follows: ");
+        print_witnesses(input, raw_inputs, &rows);
+    }
+    Ok(())
+}
+
+// Prints the full truth table, then the failing rows
+fn check_ast(input: &parser::Parser, raw_inputs: &[String], fixed: &[(char, bool)]) {
+    let ast_session = ast::build_ast_session_fixed(input, fixed);
+    let rows: Vec<Vec<bool>> = ast_session.cases.iter().zip(ast_session.results.iter())
+        .map(|(case, res)| case.iter().chain(res.iter()).copied().collect())
+        .collect();
+    print_truth_table(input, raw_inputs, &rows);
+    print_verdict(input, raw_inputs, ast_session.all_eq, &ast_session.cex);
+}
+
+// Each row holds the input values followed by every expression's result
+fn print_truth_table(input: &parser::Parser, raw_inputs: &[String], rows: &[Vec<bool>]) {
+    let mut truth_table = Builder::default();
+    truth_table.set_columns(input.ast_order.keys().map(|c| c.to_string()).chain(raw_inputs.iter().cloned()));
+
+    for case in rows.iter() {
+        let table_row: Vec<usize> = case.iter().map(|b| (*b).into()).collect();
+        let table_row: Vec<String> = table_row.iter().map(|e| e.to_string()).collect();
+        truth_table.add_record(table_row);
     }
 
     let table = truth_table.build()
@@ -38,8 +474,56 @@ fn main() {
         .to_string();
 
     println!("{}", table);
+}
+
+// Fraiging merges the outputs of near-identical expressions without running either full engine
+// Outputs left apart may still be equivalent, so only a proof of equality is trusted
+fn fraig_proves_equal(input: &parser::Parser, fixed: &[(char, bool)]) -> bool {
+    let (fraiged, _) = aig::fraig(&aig::build(input, fixed));
+    fraiged.outputs().windows(2).all(|pair| pair[0] == pair[1])
+}
+
+// The bdd engine builds every expression into one shared graph, so equivalent expressions share a root
+// A single failure case is read off the miter, the OR of each root XOR the first, restricted to the care set
+fn check_bdd(input: &parser::Parser, fixed: &[(char, bool)]) -> (bool, Vec<Vec<bool>>) {
+    let bdd = sharedbdd::SharedBDD::new();
+    let roots = sharedbdd::build_parallel(&bdd, input);
+
+    let mut miter = -1;
+    for root in roots.iter().skip(1) {
+        let diff = bdd.apply(&Operator::XOR, roots[0], *root);
+        miter = bdd.apply(&Operator::OR, miter, diff);
+    }
+    for (var, val) in fixed.iter() {
+        let var = bdd.var_edge(*var);
+        miter = bdd.apply(&Operator::AND, miter, if *val { var } else { -var });
+    }
+    if let Some(care) = input.care.as_ref() {
+        miter = bdd.apply(&Operator::AND, miter, bdd.build_rpn(&care.shared_rpn, &input.defs));
+    }
+
+    match bdd.pick_one(miter) {
+        None => (true, Vec::new()),
+        Some(mut case) => {
+            let mut res: Vec<bool> = roots.iter().map(|root| bdd.evaluate(*root, &case)).collect();
+            case.append(&mut res);
+            (false, vec![case])
+        }
+    }
+}
 
-    if ast_session.all_eq {
+// Each counterexample row holds the input values followed by every expression's result
+fn print_verdict(input: &parser::Parser, raw_inputs: &[String], all_eq: bool, cex: &[Vec<bool>]) {
+    let mut cex_table = Builder::default();
+    cex_table.set_columns(input.ast_order.keys().map(|c| c.to_string()).chain(raw_inputs.iter().cloned()));
+
+    for cex in cex.iter() {
+        let table_row: Vec<usize> = cex.iter().map(|b| (*b).into()).collect();
+        let table_row: Vec<String> = table_row.iter().map(|e| e.to_string()).collect(); 
+        cex_table.add_record(table_row);
+    }
+
+    if all_eq {
         println!("Congrats! All expressions are logically equivalent");
     } else {
         println!("Not all expressions are logically equivalent");
@@ -54,6 +538,248 @@ fn main() {
     }
 }
 
+fn parse_args(mut args: impl Iterator<Item = String>) -> std::result::Result<Options, String> {
+    let mut options = Options {
+        command: Command::Check,
+        shared: false,
+        heuristic: false,
+        limit: None,
+        dialect: None,
+        json: false,
+        paths: Vec::new(),
+        engine: Engine::Ast,
+        fixed: Vec::new(),
+        assume: None,
+        dot_path: None,
+        save_path: None,
+        load_paths: Vec::new(),
+        port: None,
+        max_body: None,
+        timeout: None,
+        workers: None
+    };
+
+    let mut first = true;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "minimize" if first => {
+                options.command = Command::Minimize;
+            },
+            "nnf" if first => {
+                options.command = Command::Convert(normalform::Form::Nnf);
+            },
+            "dnf" if first => {
+                options.command = Command::Convert(normalform::Form::Dnf);
+            },
+            "cnf" if first => {
+                options.command = Command::Convert(normalform::Form::Cnf);
+            },
+            "tseitin" if first => {
+                options.command = Command::Convert(normalform::Form::Tseitin);
+            },
+            "anf" if first => {
+                options.command = Command::Anf;
+            },
+            "fmt" if first => {
+                options.command = Command::Fmt;
+            },
+            "repl" if first => {
+                options.command = Command::Repl;
+            },
+            "serve" if first => {
+                options.command = Command::Serve;
+            },
+            "classes" if first => {
+                options.command = Command::Classes;
+            },
+            "check" if first => {
+                options.command = match args.next().as_deref() {
+                    Some("implies") => Command::Implies,
+                    Some("tautology") => Command::Query(query::Query::Tautology),
+                    Some("unsat") => Command::Query(query::Query::Unsat),
+                    Some("sat") => Command::Query(query::Query::Sat),
+                    _ => return Err(String::from("check expects one of implies, tautology, unsat or sat"))
+                };
+            },
+            "--json" => {
+                options.json = true;
+            },
+            "--dialect" => {
+                options.dialect = Some(match args.next().as_deref() {
+                    Some("ascii") => pretty::Dialect::Ascii,
+                    Some("c") => pretty::Dialect::C,
+                    Some("unicode") => pretty::Dialect::Unicode,
+                    _ => return Err(String::from("--dialect expects one of ascii, c or unicode"))
+                });
+            },
+            "--limit" => {
+                let limit = args.next().and_then(|limit| limit.parse().ok()).ok_or("--limit expects a number of terms")?;
+                options.limit = Some(limit);
+            },
+            "--shared" => {
+                options.shared = true;
+            },
+            "--heuristic" => {
+                options.heuristic = true;
+            },
+            "--engine" => {
+                options.engine = match args.next().as_deref() {
+                    Some("ast") => Engine::Ast,
+                    Some("bdd") => Engine::Bdd,
+                    Some("sat") => Engine::Sat,
+                    _ => return Err(String::from("--engine expects one of ast, bdd or sat"))
+                };
+            },
+            "--fix" => {
+                let assignments = args.next().ok_or("--fix expects assignments such as a=1,b=0")?;
+                options.fixed.append(&mut parser::parse_assignments(&assignments)?);
+            },
+            "--assume" => {
+                options.assume = Some(args.next().ok_or("--assume expects an expression")?);
+            },
+            "--dot" => {
+                options.dot_path = Some(args.next().ok_or("--dot expects an output file path")?);
+            },
+            "--save" => {
+                options.save_path = Some(args.next().ok_or("--save expects an output file path")?);
+            },
+            "--load" => {
+                options.load_paths.push(args.next().ok_or("--load expects a BDD file path")?);
+            },
+            "--port" => {
+                options.port = Some(args.next().and_then(|port| port.parse().ok()).ok_or("--port expects a port number")?);
+            },
+            "--max-body" => {
+                options.max_body = Some(args.next().and_then(|size| size.parse().ok()).ok_or("--max-body expects a number of bytes")?);
+            },
+            "--timeout" => {
+                let secs = args.next().and_then(|secs| secs.parse::<f64>().ok()).filter(|secs| *secs > 0.0 && secs.is_finite())
+                    .ok_or("--timeout expects a positive number of seconds")?;
+                options.timeout = Some(std::time::Duration::from_secs_f64(secs));
+            },
+            "--workers" => {
+                options.workers = Some(args.next().and_then(|workers| workers.parse().ok()).filter(|workers| *workers > 0)
+                    .ok_or("--workers expects a positive number of requests")?);
+            },
+            path if options.command == Command::Fmt && !path.starts_with("--") => {
+                options.paths.push(path.to_string());
+            },
+            a => return Err(format!("Unrecognized argument {a}"))
+        }
+        first = false;
+    }
+
+    // Repeated --fix options are merged; a variable may only be given one value across all of them
+    let mut fixed: Vec<(char, bool)> = Vec::new();
+    for (var, val) in options.fixed.drain(..) {
+        match fixed.iter().find(|(c, _)| *c == var) {
+            Some((_, v)) if *v != val => return Err(format!("Conflicting values given for variable {var}")),
+            Some(_) => (),
+            None => fixed.push((var, val))
+        }
+    }
+    options.fixed = fixed;
+
+    let check_only = options.engine != Engine::Ast || !options.fixed.is_empty() || options.assume.is_some() || options.dot_path.is_some()
+        || options.save_path.is_some() || !options.load_paths.is_empty();
+    let minimize_only = options.shared || options.heuristic;
+    match options.command {
+        Command::Check if minimize_only || options.limit.is_some() => {
+            return Err(String::from("--shared, --heuristic and --limit need a command such as minimize or cnf"));
+        },
+        Command::Minimize if check_only || options.limit.is_some() => {
+            return Err(String::from("minimize only accepts --shared and --heuristic"));
+        },
+        Command::Convert(_) if check_only || minimize_only => {
+            return Err(String::from("Normal form commands only accept --limit"));
+        },
+        Command::Anf if check_only || minimize_only || options.limit.is_some() => {
+            return Err(String::from("anf takes no options"));
+        },
+        Command::Fmt if check_only || minimize_only || options.limit.is_some() => {
+            return Err(String::from("fmt only accepts --dialect and file paths"));
+        },
+        Command::Classes if options.engine != Engine::Ast || options.dot_path.is_some() || options.save_path.is_some()
+            || !options.load_paths.is_empty() || minimize_only || options.limit.is_some() => {
+            return Err(String::from("classes only accepts --fix, --assume and --json"));
+        },
+        Command::Query(_) | Command::Implies if options.dot_path.is_some() || options.save_path.is_some()
+            || !options.load_paths.is_empty() || minimize_only || options.limit.is_some() => {
+            return Err(String::from("check modes only accept --engine, --fix and --assume"));
+        },
+        Command::Repl if !options.fixed.is_empty() || options.assume.is_some() || options.dot_path.is_some() || options.save_path.is_some()
+            || !options.load_paths.is_empty() || minimize_only || options.limit.is_some() => {
+            return Err(String::from("repl only accepts --engine"));
+        },
+        Command::Serve if check_only || minimize_only || options.limit.is_some() => {
+            return Err(String::from("serve only accepts --port, --max-body, --timeout and --workers"));
+        },
+        _ if options.command != Command::Serve && (options.port.is_some() || options.max_body.is_some() || options.timeout.is_some()
+            || options.workers.is_some()) => {
+            return Err(String::from("--port, --max-body, --timeout and --workers can only be used with serve"));
+        },
+        _ if options.command != Command::Classes && options.json => {
+            return Err(String::from("--json can only be used with classes"));
+        },
+        _ if options.command != Command::Fmt && options.dialect.is_some() => {
+            return Err(String::from("--dialect can only be used with fmt"));
+        },
+        _ => ()
+    }
+
+    Ok(options)
+}
+
+fn named_roots<'a>(bdd: &areabdd::BDD, raw_inputs: &'a [String]) -> Vec<(&'a str, isize)> {
+    raw_inputs.iter().map(|e| e.as_str()).zip(bdd.roots().iter().copied()).collect()
+}
+
+// Writes the shared BDD of the expressions out as a Graphviz graph
+fn write_dot(bdd: &areabdd::BDD, raw_inputs: &[String], path: &str) {
+    if let Err(e) = std::fs::write(path, areabdd::to_dot(bdd, &named_roots(bdd, raw_inputs))) {
+        eprintln!("Failed to write {path}: {e}");
+        process::exit(1);
+    }
+}
+
+// Files ending in .dddmp are written in the text format; anything else is binary
+fn save_bdd(bdd: &areabdd::BDD, raw_inputs: &[String], path: &str) {
+    let roots = named_roots(bdd, raw_inputs);
+    let data = if path.ends_with(".dddmp") {
+        bddio::save_text(bdd, &roots).into_bytes()
+    } else {
+        bddio::save_binary(bdd, &roots)
+    };
+
+    if let Err(e) = std::fs::write(path, data) {
+        eprintln!("Failed to write {path}: {e}");
+        process::exit(1);
+    }
+}
+
+// Loads the reference functions saved at path and reports which expressions match each of them on the care set
+fn compare_reference(bdd: &mut areabdd::BDD, care: isize, raw_inputs: &[String], path: &str) {
+    let references = std::fs::read(path).map_err(|e| e.to_string())
+        .and_then(|data| bddio::load(bdd, &data, &HashMap::new()))
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to load {path}: {e}");
+            process::exit(1);
+        });
+
+    for (name, root) in references.iter() {
+        let matches: Vec<&str> = named_roots(bdd, raw_inputs).into_iter()
+            .filter(|(_, r)| areabdd::equal_under(bdd, *r, *root, care))
+            .map(|(e, _)| e)
+            .collect();
+
+        if matches.is_empty() {
+            println!("Reference {name} from {path} matches none of the expressions");
+        } else {
+            println!("Reference {name} from {path} is equivalent to: {}", matches.join(", "));
+        }
+    }
+}
+
 fn get_user_input() -> Vec<String> {
     let mut inputs: Vec<String> = Vec::with_capacity(5); // Arbitrary
 
@@ -79,4 +805,21 @@ fn read_input() -> String {
     input.pop();
 
     input
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(|arg| arg.to_string())
+    }
+
+    #[test]
+    fn repeated_fixes_are_merged() {
+        let options = parse_args(args("--fix a=1,b=0 --fix a=1 --fix c=1")).ok().unwrap();
+        assert_eq!(vec![('a', true), ('b', false), ('c', true)], options.fixed);
+
+        assert_eq!(Some(String::from("Conflicting values given for variable a")), parse_args(args("--fix a=1 --fix a=0")).err());
+        assert_eq!(Some(String::from("Conflicting values given for variable b")), parse_args(args("--fix a=1,b=1 --fix b=0")).err());
+    }
+}
@@ -0,0 +1,322 @@
+// Exact two-level minimization: Quine-McCluskey generates the prime implicants and Petrick's method picks
+// a cover with the fewest products, breaking ties on the number of literals
+//
+// Minterms are bitmasks with bit i holding the value of the i-th variable in ast_order.
+// Several functions can be minimized together so that they share product terms: every cube carries a tag
+// of the outputs it implies, and the cover then counts each distinct product once across all outputs
+
+use std::collections::{BTreeSet, HashMap};
+use crate::areabdd::{self, BDD};
+use crate::parser::Parser;
+
+// A product of literals; variables whose care bit is clear do not appear
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Cube {
+    pub bits: u64,
+    pub care: u64
+}
+
+impl Cube {
+    pub fn minterm(bits: u64, num_vars: usize) -> Self {
+        let care = if num_vars == 64 { !0 } else { (1 << num_vars) - 1 };
+        return Cube {bits, care};
+    }
+
+    pub fn literals(&self) -> u32 {
+        return self.care.count_ones();
+    }
+
+    pub fn covers(&self, minterm: u64) -> bool {
+        return (minterm ^ self.bits) & self.care == 0;
+    }
+}
+
+// Prime implicants of the function that is true on on and free on dc
+pub fn prime_implicants(on: &[u64], dc: &[u64], num_vars: usize) -> Vec<Cube> {
+    let primes = tagged_primes(&[on.to_vec()], dc, num_vars);
+    return primes.into_iter().map(|(cube, _)| cube).collect();
+}
+
+// Minimum sum of products of a single function
+pub fn minimize(on: &[u64], dc: &[u64], num_vars: usize) -> Vec<Cube> {
+    return minimize_shared(&[on.to_vec()], dc, num_vars).pop().unwrap();
+}
+
+// Minimizes several functions at once, sharing products between them; returns a cover per function
+pub fn minimize_shared(ons: &[Vec<u64>], dc: &[u64], num_vars: usize) -> Vec<Vec<Cube>> {
+    assert!(ons.len() <= 64, "At most 64 functions can be minimized together");
+    let primes = tagged_primes(ons, dc, num_vars);
+
+    // Every (output, minterm) pair must be covered by a prime whose tag holds that output
+    let rows: Vec<BTreeSet<usize>> = ons.iter().enumerate()
+        .flat_map(|(out, on)| on.iter().map(move |m| (out, *m)))
+        .map(|(out, m)| (0..primes.len())
+            .filter(|p| primes[*p].1 & (1 << out) != 0 && primes[*p].0.covers(m))
+            .collect())
+        .collect();
+    let chosen = petrick(&rows, |p| primes[p].0.literals());
+
+    let mut covers: Vec<Vec<Cube>> = Vec::with_capacity(ons.len());
+    for (out, on) in ons.iter().enumerate() {
+        let mut cover: Vec<Cube> = chosen.iter()
+            .filter(|p| primes[**p].1 & (1 << out) != 0)
+            .map(|p| primes[*p].0)
+            .collect();
+
+        // A shared product may be redundant for some of the outputs it implies
+        let mut ndx = cover.len();
+        while ndx > 0 {
+            ndx -= 1;
+            let needed = on.iter().any(|m| cover[ndx].covers(*m) &&
+                cover.iter().enumerate().all(|(k, other)| k == ndx || !other.covers(*m)));
+            if !needed {
+                cover.remove(ndx);
+            }
+        }
+
+        cover.sort();
+        covers.push(cover);
+    }
+
+    return covers;
+}
+
+// Quine-McCluskey over tagged cubes; a cube stops being prime for its tag once merging keeps the whole tag
+fn tagged_primes(ons: &[Vec<u64>], dc: &[u64], num_vars: usize) -> Vec<(Cube, u64)> {
+    let all_outputs: u64 = if ons.len() == 64 { !0 } else { (1 << ons.len()) - 1 };
+    let mut level: HashMap<Cube, u64> = HashMap::new();
+    for (out, on) in ons.iter().enumerate() {
+        for m in on.iter() {
+            *level.entry(Cube::minterm(*m, num_vars)).or_insert(0) |= 1 << out;
+        }
+    }
+    for m in dc.iter() {
+        level.insert(Cube::minterm(*m, num_vars), all_outputs);
+    }
+
+    let mut primes: Vec<(Cube, u64)> = Vec::new();
+    while !level.is_empty() {
+        let mut next: HashMap<Cube, u64> = HashMap::new();
+        let mut covered: HashMap<Cube, bool> = level.keys().map(|cube| (*cube, false)).collect();
+
+        // Cubes can only merge with a cube that differs in exactly one of the same care bits
+        for (cube, tag) in level.iter() {
+            let mut free = cube.care;
+            while free != 0 {
+                let bit = free & free.wrapping_neg();
+                free ^= bit;
+                if cube.bits & bit != 0 {
+                    continue; // Each pair is visited from its lower half
+                }
+
+                let partner = Cube {bits: cube.bits | bit, care: cube.care};
+                if let Some(partner_tag) = level.get(&partner) {
+                    let merged_tag = tag & partner_tag;
+                    if merged_tag == 0 {
+                        continue;
+                    }
+
+                    *next.entry(Cube {bits: cube.bits, care: cube.care ^ bit}).or_insert(0) |= merged_tag;
+                    if merged_tag == *tag {
+                        covered.insert(*cube, true);
+                    }
+                    if merged_tag == *partner_tag {
+                        covered.insert(partner, true);
+                    }
+                }
+            }
+        }
+
+        primes.extend(level.iter().filter(|(cube, _)| !covered[*cube]).map(|(cube, tag)| (*cube, *tag)));
+        level = next;
+    }
+
+    primes.sort();
+    return primes;
+}
+
+// Picks the cheapest set of columns that hits every row: essential columns first, then Petrick's method
+// on whatever is left. Fewer columns always win, and cost breaks ties
+fn petrick(rows: &[BTreeSet<usize>], cost: impl Fn(usize) -> u32) -> Vec<usize> {
+    let mut chosen: BTreeSet<usize> = BTreeSet::new();
+    for row in rows.iter() {
+        if row.len() == 1 {
+            chosen.insert(*row.iter().next().unwrap());
+        }
+    }
+
+    // Drop rows already hit, and rows that another remaining row implies
+    let mut remaining: Vec<&BTreeSet<usize>> = rows.iter().filter(|row| row.is_disjoint(&chosen)).collect();
+    remaining.sort_by_key(|row| row.len());
+    remaining.dedup();
+    let mut reduced: Vec<&BTreeSet<usize>> = Vec::new();
+    for row in remaining.into_iter() {
+        if !reduced.iter().any(|smaller| smaller.is_subset(row)) {
+            reduced.push(row);
+        }
+    }
+
+    // Multiply out the product of sums, absorbing any term that contains another
+    let mut products: Vec<BTreeSet<usize>> = vec![BTreeSet::new()];
+    for row in reduced.iter() {
+        let mut next: Vec<BTreeSet<usize>> = Vec::new();
+        for product in products.iter() {
+            if !product.is_disjoint(row) {
+                next.push(product.clone());
+                continue;
+            }
+            for col in row.iter() {
+                let mut term = product.clone();
+                term.insert(*col);
+                next.push(term);
+            }
+        }
+
+        next.sort_by_key(|term| term.len());
+        next.dedup();
+        products.clear();
+        for term in next.into_iter() {
+            if !products.iter().any(|smaller| smaller.is_subset(&term)) {
+                products.push(term);
+            }
+        }
+    }
+
+    let best = products.into_iter()
+        .min_by_key(|term| (term.len(), term.iter().map(|col| cost(*col)).sum::<u32>()))
+        .unwrap();
+    chosen.extend(best);
+    return chosen.into_iter().collect();
+}
+
+// Lists the minterms of a BDD function over the first num_vars levels
+pub fn minterms(bdd: &BDD, root: isize, num_vars: usize) -> Vec<u64> {
+    let mut res: Vec<u64> = Vec::new();
+    minterms_helper(bdd, root, 1, num_vars, 0, &mut res);
+    return res;
+}
+
+fn minterms_helper(bdd: &BDD, edge: isize, level: usize, num_vars: usize, bits: u64, res: &mut Vec<u64>) {
+    if edge == -1 {
+        return;
+    } else if level > num_vars {
+        res.push(bits);
+        return;
+    }
+
+    let (lo, hi) = if edge.abs() == 1 {
+        (edge, edge)
+    } else {
+        let (var, lo, hi) = areabdd::vertex_parts(bdd, edge);
+        if var as usize != level {
+            (edge, edge)
+        } else if edge < 0 {
+            (-lo, -hi)
+        } else {
+            (lo, hi)
+        }
+    };
+
+    minterms_helper(bdd, lo, level + 1, num_vars, bits, res);
+    minterms_helper(bdd, hi, level + 1, num_vars, bits | 1 << (level - 1), res);
+}
+
+// Writes a cover in the parser's syntax; every binary operator has the same precedence,
+// so products are parenthesized whenever there is more than one term
+pub fn to_expr(cover: &[Cube], names: &[char]) -> String {
+    if cover.is_empty() {
+        return format!("{0} & ~{0}", names[0]);
+    } else if cover.iter().any(|cube| cube.care == 0) {
+        return format!("{0} | ~{0}", names[0]);
+    }
+
+    let terms: Vec<String> = cover.iter().map(|cube| {
+        let lits: Vec<String> = names.iter().enumerate()
+            .filter(|(i, _)| cube.care & (1 << i) != 0)
+            .map(|(i, name)| if cube.bits & (1 << i) != 0 { name.to_string() } else { format!("~{name}") })
+            .collect();
+        if lits.len() > 1 && cover.len() > 1 {
+            format!("({})", lits.join(" & "))
+        } else {
+            lits.join(" & ")
+        }
+    }).collect();
+
+    return terms.join(" | ");
+}
+
+// Minimum SOP of every expression in the session, optionally sharing products between them
+pub fn minimize_session(parser: &Parser, shared: bool) -> Vec<String> {
+    let bdd = areabdd::build(BDD::new(), parser);
+    let names: Vec<char> = parser.ast_order.keys().copied().collect();
+    let ons: Vec<Vec<u64>> = bdd.roots().iter().map(|root| minterms(&bdd, *root, names.len())).collect();
+
+    let covers = if shared {
+        minimize_shared(&ons, &[], names.len())
+    } else {
+        ons.iter().map(|on| minimize(on, &[], names.len())).collect()
+    };
+    return covers.iter().map(|cover| to_expr(cover, &names)).collect();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    #[test]
+    fn textbook_primes_and_cover() {
+        // f(a, b, c, d) = sum of minterms 4, 8, 10, 11, 12, 15 with don't cares 9, 14, where a is the high bit
+        let rev = |m: u64| (0..4).fold(0, |acc, i| acc | ((m >> (3 - i)) & 1) << i);
+        let on: Vec<u64> = [4, 8, 10, 11, 12, 15].iter().map(|m| rev(*m)).collect();
+        let dc: Vec<u64> = [9, 14].iter().map(|m| rev(*m)).collect();
+
+        assert_eq!(4, prime_implicants(&on, &dc, 4).len());
+        let cover = minimize(&on, &dc, 4);
+        assert_eq!(3, cover.len());
+        for m in on.iter() {
+            assert!(cover.iter().any(|cube| cube.covers(*m)));
+        }
+        assert_eq!("(a & ~b) | (b & ~c & ~d) | (a & c)", to_expr(&cover, &['a', 'b', 'c', 'd']));
+    }
+
+    #[test]
+    fn cyclic_cover_needs_petrick() {
+        // Every minterm is covered by exactly two primes, so nothing is essential
+        let on: Vec<u64> = vec![0b000, 0b001, 0b011, 0b111, 0b110, 0b100];
+        let cover = minimize(&on, &[], 3);
+        assert_eq!(6, prime_implicants(&on, &[], 3).len());
+        assert_eq!(3, cover.len());
+        assert!(cover.iter().all(|cube| cube.literals() == 2));
+    }
+
+    #[test]
+    fn session_results_are_equivalent() {
+        let exprs = vec![String::from("(a & b) | (a & ~b) | (b & c)"), String::from("a ^ a"), String::from("a | ~a | b")];
+        let parser = parser::create_session(exprs).unwrap();
+        let minimized = minimize_session(&parser, false);
+        assert_eq!(vec!["a | (b & c)", "a & ~a", "a | ~a"], minimized);
+
+        let check = parser::create_session(vec![String::from("(a & b) | (a & ~b) | (b & c)"), minimized[0].clone()]).unwrap();
+        let bdd = areabdd::build(BDD::new(), &check);
+        assert_eq!(bdd.roots()[0], bdd.roots()[1]);
+    }
+
+    #[test]
+    fn shared_products() {
+        // Alone, f's cheapest cover is ~a & b | b & c and g needs a & b & c | a & ~b & ~c: four distinct products.
+        // Together, f can reuse g's a & b & c instead of b & c, which leaves three
+        let parser = parser::create_session(vec![String::from("(~a & b) | (a & b & c)"), String::from("(a & b & c) | (a & ~b & ~c)")]).unwrap();
+        assert_eq!(vec!["(~a & b) | (b & c)", "(a & ~b & ~c) | (a & b & c)"], minimize_session(&parser, false));
+        assert_eq!(vec!["(~a & b) | (a & b & c)", "(a & ~b & ~c) | (a & b & c)"], minimize_session(&parser, true));
+
+        let on_f: Vec<u64> = vec![0b010, 0b011, 0b111];
+        let on_g: Vec<u64> = vec![0b100, 0b111];
+        let separate = [minimize(&on_f, &[], 3), minimize(&on_g, &[], 3)];
+        let shared = minimize_shared(&[on_f, on_g], &[], 3);
+        let distinct = |covers: &[Vec<Cube>]| covers.iter().flatten().collect::<BTreeSet<_>>().len();
+        assert_eq!(4, distinct(&separate));
+        assert_eq!(3, distinct(&shared));
+        assert!(shared[0].iter().any(|cube| shared[1].contains(cube)));
+    }
+}
@@ -0,0 +1,350 @@
+// Equivalence checking by SAT: the expressions are Tseitin-encoded into CNF and joined into a miter,
+// the OR of (first XOR other) over every other expression, which is satisfiable exactly when they disagree
+//
+// Gates are hashed structurally, so subexpressions shared between the expressions get a single output literal,
+// and negation costs nothing since it only flips the sign of a literal
+
+use linked_hash_map::LinkedHashMap;
+use std::collections::HashMap;
+use crate::parser::{Definition, Operator, Parser, Token};
+use crate::sat::{Lit, Solver, SolverStats};
+
+#[derive(Debug)]
+pub struct SatSession {
+    pub cex: Vec<Vec<bool>>, // Input values followed by each expression's result, like ASTSession::cex
+    pub all_eq: bool,
+    pub stats: SolverStats
+}
+
+// Gate inputs are kept in a normal form so that equivalent gates hash the same
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+enum Gate {
+    And(Lit, Lit),
+    Xor(Lit, Lit),
+    Ite(Lit, Lit, Lit)
+}
+
+#[derive(Debug, Default)]
+pub struct Tseitin {
+    gates: HashMap<Gate, Lit>,
+    true_lit: Option<Lit>
+}
+
+impl Tseitin {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    // A literal with a fixed value; the variable behind it is created on first use
+    pub fn constant(&mut self, solver: &mut Solver, val: bool) -> Lit {
+        let lit = match self.true_lit {
+            Some(lit) => lit,
+            None => {
+                let lit = solver.new_var();
+                solver.add_clause(&[lit]);
+                self.true_lit = Some(lit);
+                lit
+            }
+        };
+
+        return if val { lit } else { -lit };
+    }
+
+    fn as_const(&self, lit: Lit) -> Option<bool> {
+        match self.true_lit {
+            Some(t) if t == lit => Some(true),
+            Some(t) if t == -lit => Some(false),
+            _ => None
+        }
+    }
+
+    pub fn and(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        match (self.as_const(a), self.as_const(b)) {
+            (Some(false), _) | (_, Some(false)) => return self.constant(solver, false),
+            (Some(true), _) => return b,
+            (_, Some(true)) => return a,
+            _ => ()
+        }
+        if a == b {
+            return a;
+        } else if a == -b {
+            return self.constant(solver, false);
+        }
+
+        let gate = Gate::And(a.min(b), a.max(b));
+        if let Some(out) = self.gates.get(&gate) {
+            return *out;
+        }
+
+        let out = solver.new_var();
+        solver.add_clause(&[-out, a]);
+        solver.add_clause(&[-out, b]);
+        solver.add_clause(&[out, -a, -b]);
+        self.gates.insert(gate, out);
+        return out;
+    }
+
+    pub fn or(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        return -self.and(solver, -a, -b);
+    }
+
+    pub fn xor(&mut self, solver: &mut Solver, a: Lit, b: Lit) -> Lit {
+        match (self.as_const(a), self.as_const(b)) {
+            (Some(val), _) => return if val { -b } else { b },
+            (_, Some(val)) => return if val { -a } else { a },
+            _ => ()
+        }
+        if a == b {
+            return self.constant(solver, false);
+        } else if a == -b {
+            return self.constant(solver, true);
+        }
+
+        // Negated inputs come out as a negated output
+        let sign = if (a < 0) ^ (b < 0) { -1 } else { 1 };
+        let (a, b) = (a.abs().min(b.abs()), a.abs().max(b.abs()));
+        let gate = Gate::Xor(a, b);
+        if let Some(out) = self.gates.get(&gate) {
+            return *out * sign;
+        }
+
+        let out = solver.new_var();
+        solver.add_clause(&[-out, a, b]);
+        solver.add_clause(&[-out, -a, -b]);
+        solver.add_clause(&[out, -a, b]);
+        solver.add_clause(&[out, a, -b]);
+        self.gates.insert(gate, out);
+        return out * sign;
+    }
+
+    pub fn ite(&mut self, solver: &mut Solver, c: Lit, t: Lit, e: Lit) -> Lit {
+        if let Some(val) = self.as_const(c) {
+            return if val { t } else { e };
+        } else if t == e {
+            return t;
+        } else if t == -e {
+            return -self.xor(solver, c, t);
+        } else if t == c || self.as_const(t) == Some(true) {
+            return self.or(solver, c, e);
+        } else if t == -c || self.as_const(t) == Some(false) {
+            return self.and(solver, -c, e);
+        } else if e == c || self.as_const(e) == Some(false) {
+            return self.and(solver, c, t);
+        } else if e == -c || self.as_const(e) == Some(true) {
+            return self.or(solver, -c, t);
+        }
+
+        // Positive condition and then-branch: ite(~c, t, e) = ite(c, e, t) and ite(c, ~t, ~e) = ~ite(c, t, e)
+        let (c, t, e) = if c < 0 { (-c, e, t) } else { (c, t, e) };
+        let (t, e, sign) = if t < 0 { (-t, -e, -1) } else { (t, e, 1) };
+        let gate = Gate::Ite(c, t, e);
+        if let Some(out) = self.gates.get(&gate) {
+            return *out * sign;
+        }
+
+        let out = solver.new_var();
+        solver.add_clause(&[-c, -t, out]);
+        solver.add_clause(&[-c, t, -out]);
+        solver.add_clause(&[c, -e, out]);
+        solver.add_clause(&[c, e, -out]);
+        // Redundant, but they let propagation settle the output when both branches agree
+        solver.add_clause(&[-t, -e, out]);
+        solver.add_clause(&[t, e, -out]);
+        self.gates.insert(gate, out);
+        return out * sign;
+    }
+
+    // Encodes an expression in RPN, returning the literal that is true exactly when the expression is
+    // Each definition is encoded once per distinct argument list, however often the expression uses it
+    pub fn encode(&mut self, solver: &mut Solver, rpn: &[Token], defs: &[Definition], vars: &HashMap<char, Lit>) -> Lit {
+        return self.encode_helper(solver, rpn, defs, vars, &[], &mut HashMap::new());
+    }
+
+    fn encode_helper(&mut self, solver: &mut Solver, rpn: &[Token], defs: &[Definition], vars: &HashMap<char, Lit>,
+        params: &[Lit], shared: &mut HashMap<(usize, Vec<Lit>), Lit>) -> Lit {
+        let mut lit_stack: Vec<Lit> = Vec::with_capacity(rpn.len());
+
+        for t in rpn.iter() {
+            match t {
+                Token::VAR(c) => lit_stack.push(*vars.get(c).expect("Variable missing from the encoding")),
+                Token::PARAM(ndx) => lit_stack.push(params[*ndx]),
+                Token::DEF(id) => {
+                    let args = lit_stack.split_off(lit_stack.len() - defs[*id].params.len());
+                    let out = match shared.get(&(*id, args.clone())) {
+                        Some(out) => *out,
+                        None => {
+                            let out = self.encode_helper(solver, &defs[*id].body.shared_rpn, defs, vars, &args, shared);
+                            shared.insert((*id, args), out);
+                            out
+                        }
+                    };
+                    lit_stack.push(out);
+                },
+                Token::VAL(b) => {
+                    let lit = self.constant(solver, *b);
+                    lit_stack.push(lit);
+                },
+                Token::OP(Operator::NOT) => {
+                    let top = lit_stack.last_mut().unwrap();
+                    *top = -(*top);
+                },
+                Token::OP(Operator::ITE) => {
+                    let e = lit_stack.pop().unwrap();
+                    let t = lit_stack.pop().unwrap();
+                    let c = lit_stack.pop().unwrap();
+                    let out = self.ite(solver, c, t, e);
+                    lit_stack.push(out);
+                },
+                Token::OP(op) => {
+                    let rhs = lit_stack.pop().unwrap();
+                    let lhs = lit_stack.pop().unwrap();
+                    let out = match op {
+                        Operator::AND => self.and(solver, lhs, rhs),
+                        Operator::OR => self.or(solver, lhs, rhs),
+                        Operator::XOR => self.xor(solver, lhs, rhs),
+                        _ => unreachable!()
+                    };
+                    lit_stack.push(out);
+                },
+                _ => panic!("Unexpected token while encoding expression")
+            }
+        }
+
+        return lit_stack.pop().expect("No literal left to assign as output");
+    }
+}
+
+// Creates one solver variable per input, in ast_order
+pub fn input_vars(solver: &mut Solver, order: &LinkedHashMap<char, usize>) -> HashMap<char, Lit> {
+    return order.keys().map(|c| (*c, solver.new_var())).collect();
+}
+
+// Checks whether every expression agrees on all inputs consistent with the fixed (variable, value) pairs
+// Up to limit distinct counterexamples are collected, each blocked in turn so the next one differs in its inputs
+pub fn check_session(parser: &Parser, fixed: &[(char, bool)], limit: usize) -> SatSession {
+    let mut solver = Solver::new();
+    let mut res = SatSession {
+        cex: Vec::new(),
+        all_eq: true,
+        stats: SolverStats::default()
+    };
+    if parser.exprs.len() < 2 {
+        return res;
+    }
+
+    let vars = input_vars(&mut solver, &parser.ast_order);
+    let inputs: Vec<Lit> = parser.ast_order.keys().map(|c| vars[c]).collect();
+    let mut tseitin = Tseitin::new();
+    let outputs: Vec<Lit> = parser.exprs.iter()
+        .map(|expr| tseitin.encode(&mut solver, &expr.shared_rpn, &parser.defs, &vars))
+        .collect();
+
+    let miter: Vec<Lit> = outputs[1..].iter()
+        .map(|out| tseitin.xor(&mut solver, outputs[0], *out))
+        .collect();
+    solver.add_clause(&miter);
+    for (c, val) in fixed.iter() {
+        if let Some(lit) = vars.get(c) {
+            solver.add_clause(&[if *val { *lit } else { -*lit }]);
+        }
+    }
+    if let Some(care) = parser.care.as_ref() {
+        let care = tseitin.encode(&mut solver, &care.shared_rpn, &parser.defs, &vars);
+        solver.add_clause(&[care]);
+    }
+
+    while res.cex.len() < limit && solver.solve() {
+        let row: Vec<bool> = inputs.iter().chain(outputs.iter()).map(|lit| solver.model_value(*lit)).collect();
+        let block: Vec<Lit> = inputs.iter().map(|lit| if solver.model_value(*lit) { -*lit } else { *lit }).collect();
+        res.cex.push(row);
+        solver.add_clause(&block);
+    }
+
+    res.all_eq = res.cex.is_empty();
+    res.stats = solver.stats();
+    return res;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{ast, parser};
+
+    fn session(exprs: &[&str]) -> Parser {
+        return parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+    }
+
+    #[test]
+    fn structural_hashing_shares_gates() {
+        let mut solver = Solver::new();
+        let mut tseitin = Tseitin::new();
+        let (a, b, c) = (solver.new_var(), solver.new_var(), solver.new_var());
+
+        assert_eq!(tseitin.and(&mut solver, a, b), tseitin.and(&mut solver, b, a));
+        assert_eq!(tseitin.xor(&mut solver, -a, b), -tseitin.xor(&mut solver, b, a));
+        assert_eq!(tseitin.ite(&mut solver, -c, a, b), tseitin.ite(&mut solver, c, b, a));
+        assert_eq!(-tseitin.and(&mut solver, -a, -b), tseitin.or(&mut solver, a, b));
+        assert_eq!(3 + 4, solver.num_vars());
+    }
+
+    #[test]
+    fn equivalent_expressions() {
+        let res = check_session(&session(&["a & (b | c)", "(a & b) | (a & c)", "a ? b | c : a"]), &[], usize::MAX);
+        assert!(res.all_eq);
+        assert!(res.cex.is_empty());
+    }
+
+    #[test]
+    fn counterexamples_match_truth_table() {
+        let parser = session(&["a & (b | c)", "(a & b) | c", "a ^ c ^ ~b"]);
+        let res = check_session(&parser, &[], usize::MAX);
+        assert!(!res.all_eq);
+
+        // Every failing truth table row is found, in some order, with the same shape as ASTSession::cex
+        let mut expected = ast::build_ast_session(&parser).cex;
+        let mut actual = res.cex;
+        expected.sort();
+        actual.sort();
+        assert_eq!(expected, actual);
+
+        let one = check_session(&parser, &[], 1);
+        assert_eq!(1, one.cex.len());
+        assert_eq!(3 + 3, one.cex[0].len());
+    }
+
+    #[test]
+    fn fixed_inputs_restrict_the_search() {
+        let parser = session(&["a & b", "a & c"]);
+        assert!(!check_session(&parser, &[], usize::MAX).all_eq);
+        assert!(check_session(&parser, &[('a', false)], usize::MAX).all_eq);
+
+        let res = check_session(&parser, &[('a', true), ('b', true)], usize::MAX);
+        assert_eq!(vec![vec![true, true, false, true, false]], res.cex);
+    }
+
+    #[test]
+    fn counterexamples_come_from_the_care_set() {
+        let mut parser = session(&["a & b", "a & c"]);
+        parser.set_care(String::from("b ? c : ~a")).unwrap();
+        assert!(check_session(&parser, &[], usize::MAX).all_eq);
+
+        parser.set_care(String::from("b | c")).unwrap();
+        let res = check_session(&parser, &[], usize::MAX);
+        assert_eq!(2, res.cex.len());
+        assert!(res.cex.iter().all(|row| row[0] && row[1] != row[2]));
+    }
+
+    #[test]
+    fn definition_chains() {
+        let mut inputs = parser::definition_chain(40);
+        inputs.push(String::from("(a ^ b) & ~c"));
+        let mut parser = parser::create_session(inputs).unwrap();
+        assert!(check_session(&parser, &[], usize::MAX).all_eq);
+
+        // Only the inputs with c set and a ^ b tell the chain apart from a ^ b
+        parser.replace_expr(1, String::from("a ^ b")).unwrap();
+        let res = check_session(&parser, &[], usize::MAX);
+        assert_eq!(2, res.cex.len());
+        assert!(res.cex.iter().all(|row| row[0] != row[1] && row[2]));
+    }
+}
@@ -0,0 +1,307 @@
+// Rewrites expressions into normal forms written in the parser's own syntax
+// NNF pushes negations down to the variables, DNF and CNF are read off the paths of the BDD, so they are
+// canonical for the variable ordering, and Tseitin CNF names every gate with a fresh variable.
+// Each form can grow exponentially except Tseitin, so conversions stop with an error past a size limit.
+// Every result is parsed back and compared against the original on a BDD before it is returned; the Tseitin
+// form is only equisatisfiable, so its fresh variables are quantified out first.
+
+use std::collections::HashMap;
+use crate::areabdd::{self, BDD};
+use crate::espresso;
+use crate::parser::{self, Definition, Operator, Parser, Token};
+
+// Largest number of literals (NNF) or products/clauses (DNF, CNF) a conversion may produce
+pub const DEFAULT_LIMIT: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Form {
+    Nnf,
+    Dnf,
+    Cnf,
+    Tseitin
+}
+
+type Literal = (char, bool);
+
+// Subexpressions keep track of whether they need parentheses when used as an operand
+struct Printed {
+    text: String,
+    compound: bool
+}
+
+impl Printed {
+    fn operand(&self) -> String {
+        if self.compound {
+            return format!("({})", self.text);
+        }
+        return self.text.clone();
+    }
+}
+
+fn literal((var, val): Literal) -> String {
+    if val {
+        return var.to_string();
+    }
+    return format!("~{var}");
+}
+
+fn join(op: &str, lhs: &Printed, rhs: &Printed) -> Printed {
+    return Printed {text: format!("{} {op} {}", lhs.operand(), rhs.operand()), compound: true};
+}
+
+// Operands of each operator in the RPN, found once so they can be revisited with either polarity
+// Definitions are turned into nodes once per distinct argument list, so the nodes form a DAG
+enum Node {
+    Var(char),
+    Not(usize),
+    Bin(Operator, usize, usize),
+    Ite(usize, usize, usize)
+}
+
+fn tree(rpn: &[Token], defs: &[Definition]) -> (Vec<Node>, usize) {
+    let mut nodes: Vec<Node> = Vec::with_capacity(rpn.len());
+    let root = tree_helper(rpn, defs, &[], &mut nodes, &mut HashMap::new());
+    return (nodes, root);
+}
+
+fn tree_helper(rpn: &[Token], defs: &[Definition], params: &[usize], nodes: &mut Vec<Node>,
+    shared: &mut HashMap<(usize, Vec<usize>), usize>) -> usize {
+    let mut op_stack: Vec<usize> = Vec::with_capacity(rpn.len());
+
+    for t in rpn.iter() {
+        let node = match t {
+            Token::PARAM(ndx) => {
+                op_stack.push(params[*ndx]);
+                continue;
+            },
+            Token::DEF(id) => {
+                let args = op_stack.split_off(op_stack.len() - defs[*id].params.len());
+                let ndx = match shared.get(&(*id, args.clone())) {
+                    Some(ndx) => *ndx,
+                    None => {
+                        let ndx = tree_helper(&defs[*id].body.shared_rpn, defs, &args, nodes, shared);
+                        shared.insert((*id, args), ndx);
+                        ndx
+                    }
+                };
+                op_stack.push(ndx);
+                continue;
+            },
+            Token::VAR(c) => Node::Var(*c),
+            Token::OP(Operator::NOT) => Node::Not(op_stack.pop().unwrap()),
+            Token::OP(Operator::ITE) => {
+                let h = op_stack.pop().unwrap();
+                let g = op_stack.pop().unwrap();
+                Node::Ite(op_stack.pop().unwrap(), g, h)
+            },
+            Token::OP(op) => {
+                let rhs = op_stack.pop().unwrap();
+                Node::Bin(*op, op_stack.pop().unwrap(), rhs)
+            },
+            _ => panic!("Unexpected token while converting expression")
+        };
+        op_stack.push(nodes.len());
+        nodes.push(node);
+    }
+
+    return op_stack.pop().expect("No node left to assign as root");
+}
+
+// Negation normal form: only variables are negated, and xor and ite are expanded into and/or
+pub fn nnf(rpn: &[Token], defs: &[Definition], limit: usize) -> Result<String, String> {
+    let (nodes, root) = tree(rpn, defs);
+    let mut budget = limit;
+    return Ok(nnf_helper(&nodes, root, true, &mut budget)?.text);
+}
+
+fn nnf_helper(nodes: &[Node], ndx: usize, positive: bool, budget: &mut usize) -> Result<Printed, String> {
+    match nodes[ndx] {
+        Node::Var(c) => {
+            if *budget == 0 {
+                return Err(String::from("NNF exceeds the literal limit"));
+            }
+            *budget -= 1;
+            Ok(Printed {text: literal((c, positive)), compound: false})
+        },
+        Node::Not(child) => nnf_helper(nodes, child, !positive, budget),
+        Node::Bin(op @ (Operator::AND | Operator::OR), lhs, rhs) => {
+            // De Morgan swaps the operator under a negation
+            let lhs = nnf_helper(nodes, lhs, positive, budget)?;
+            let rhs = nnf_helper(nodes, rhs, positive, budget)?;
+            Ok(join(if (op == Operator::AND) == positive { "&" } else { "|" }, &lhs, &rhs))
+        },
+        Node::Bin(Operator::XOR, lhs, rhs) => {
+            // a ^ b is (a & ~b) | (~a & b), and its negation (a & b) | (~a & ~b)
+            let first = join("&", &nnf_helper(nodes, lhs, true, budget)?, &nnf_helper(nodes, rhs, !positive, budget)?);
+            let second = join("&", &nnf_helper(nodes, lhs, false, budget)?, &nnf_helper(nodes, rhs, positive, budget)?);
+            Ok(join("|", &first, &second))
+        },
+        Node::Ite(f, g, h) => {
+            let first = join("&", &nnf_helper(nodes, f, true, budget)?, &nnf_helper(nodes, g, positive, budget)?);
+            let second = join("&", &nnf_helper(nodes, f, false, budget)?, &nnf_helper(nodes, h, positive, budget)?);
+            Ok(join("|", &first, &second))
+        },
+        Node::Bin(op, _, _) => panic!("Unexpected operator {:?} while converting expression", op)
+    }
+}
+
+// The BDD paths to 1 as products, or with complement set the paths to 0 as clauses
+fn paths(bdd: &BDD, root: isize, names: &[char], complement: bool, limit: usize) -> Result<Vec<Vec<Literal>>, String> {
+    let edge = if complement { -root } else { root };
+    if espresso::path_count(bdd, edge) > limit {
+        return Err(format!("{} has more than {limit} terms", if complement { "CNF" } else { "DNF" }));
+    }
+
+    let cover = espresso::bdd_cover(bdd, edge);
+    return Ok(cover.iter().map(|cube| names.iter().enumerate()
+        .filter(|(i, _)| cube.care & (1 << i) != 0)
+        .map(|(i, name)| (*name, (cube.bits & (1 << i) != 0) != complement))
+        .collect())
+        .collect());
+}
+
+// Joins terms of literals with inner, then the terms with outer
+// There are no constant tokens, so constants are written with the first variable: no clauses or an empty product
+// is true, and no products or an empty clause is false
+fn print_terms(terms: &[Vec<Literal>], inner: &str, outer: &str, names: &[char]) -> String {
+    if terms.is_empty() || terms.iter().any(|term| term.is_empty()) {
+        let is_true = terms.is_empty() == (outer == "&");
+        return format!("{0} {1} ~{0}", names[0], if is_true { "|" } else { "&" });
+    }
+
+    let printed: Vec<String> = terms.iter().map(|term| {
+        let lits: Vec<String> = term.iter().map(|lit| literal(*lit)).collect();
+        if lits.len() > 1 && terms.len() > 1 {
+            format!("({})", lits.join(&format!(" {inner} ")))
+        } else {
+            lits.join(&format!(" {inner} "))
+        }
+    }).collect();
+    return printed.join(&format!(" {outer} "));
+}
+
+pub fn dnf(bdd: &BDD, root: isize, names: &[char], limit: usize) -> Result<String, String> {
+    return Ok(print_terms(&paths(bdd, root, names, false, limit)?, "&", "|", names));
+}
+
+pub fn cnf(bdd: &BDD, root: isize, names: &[char], limit: usize) -> Result<String, String> {
+    return Ok(print_terms(&paths(bdd, root, names, true, limit)?, "|", "&", names));
+}
+
+// Equisatisfiable CNF with one fresh variable per gate, taken from the letters no expression uses
+// Returns the formula along with the fresh variables
+pub fn tseitin(rpn: &[Token], defs: &[Definition], used: &[char]) -> Result<(String, Vec<char>), String> {
+    let (nodes, root) = tree(rpn, defs);
+    let mut unused = ('a'..='z').chain('A'..='Z').filter(|c| !used.contains(c));
+    let mut fresh: Vec<char> = Vec::new();
+    let mut clauses: Vec<Vec<Literal>> = Vec::new();
+    let mut lits: Vec<Literal> = Vec::with_capacity(nodes.len());
+
+    for node in nodes.iter() {
+        let lit = match node {
+            Node::Var(c) => (*c, true),
+            Node::Not(child) => (lits[*child].0, !lits[*child].1),
+            _ => {
+                let x = unused.next().ok_or("Tseitin encoding ran out of unused variable names")?;
+                fresh.push(x);
+                let neg = |(var, val): Literal| (var, !val);
+                let (pos_x, neg_x) = ((x, true), (x, false));
+                match node {
+                    Node::Bin(Operator::AND, a, b) => {
+                        let (a, b) = (lits[*a], lits[*b]);
+                        clauses.extend([vec![neg_x, a], vec![neg_x, b], vec![pos_x, neg(a), neg(b)]]);
+                    },
+                    Node::Bin(Operator::OR, a, b) => {
+                        let (a, b) = (lits[*a], lits[*b]);
+                        clauses.extend([vec![pos_x, neg(a)], vec![pos_x, neg(b)], vec![neg_x, a, b]]);
+                    },
+                    Node::Bin(Operator::XOR, a, b) => {
+                        let (a, b) = (lits[*a], lits[*b]);
+                        clauses.extend([vec![neg_x, a, b], vec![neg_x, neg(a), neg(b)], vec![pos_x, neg(a), b], vec![pos_x, a, neg(b)]]);
+                    },
+                    Node::Ite(c, t, e) => {
+                        let (c, t, e) = (lits[*c], lits[*t], lits[*e]);
+                        clauses.extend([vec![neg_x, neg(c), t], vec![neg_x, c, e], vec![pos_x, neg(c), neg(t)], vec![pos_x, c, neg(e)]]);
+                    },
+                    _ => panic!("Unexpected operator while converting expression")
+                }
+                pos_x
+            }
+        };
+        lits.push(lit);
+    }
+
+    clauses.push(vec![lits[root]]);
+    return Ok((print_terms(&clauses, "|", "&", used), fresh));
+}
+
+// Converts every expression of the session, checking each result against the original function
+pub fn convert_session(parser: &Parser, form: Form, limit: usize) -> Result<Vec<String>, String> {
+    let mut bdd = areabdd::build(BDD::new(), parser);
+    let names: Vec<char> = parser.ast_order.keys().copied().collect();
+    let roots = bdd.roots().to_vec();
+
+    let mut res: Vec<String> = Vec::with_capacity(roots.len());
+    for (expr, root) in parser.exprs.iter().zip(roots) {
+        let (converted, fresh) = match form {
+            Form::Nnf => (nnf(&expr.shared_rpn, &parser.defs, limit)?, Vec::new()),
+            Form::Dnf => (dnf(&bdd, root, &names, limit)?, Vec::new()),
+            Form::Cnf => (cnf(&bdd, root, &names, limit)?, Vec::new()),
+            Form::Tseitin => tseitin(&expr.shared_rpn, &parser.defs, &names)?
+        };
+
+        bdd = areabdd::build(bdd, &parser::create_session(vec![converted.clone()])?);
+        let mut check = *bdd.roots().last().unwrap();
+        if !fresh.is_empty() {
+            check = areabdd::exists(&mut bdd, &fresh, check);
+        }
+        if check != root {
+            return Err(format!("Converted form {converted} is not equivalent to its expression"));
+        }
+
+        res.push(converted);
+    }
+
+    return Ok(res);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn convert(exprs: &[&str], form: Form) -> Vec<String> {
+        let parser = parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+        return convert_session(&parser, form, DEFAULT_LIMIT).unwrap();
+    }
+
+    #[test]
+    fn negation_normal_form() {
+        let res = convert(&["~(a & (b | ~c))", "~(a ^ b)", "~(a ? b : c)"], Form::Nnf);
+        assert_eq!(vec!["~a | (~b & c)", "(a & b) | (~a & ~b)", "(a & ~b) | (~a & ~c)"], res);
+    }
+
+    #[test]
+    fn path_forms() {
+        assert_eq!(vec!["(~a & c) | (a & b)"], convert(&["a ? b : c"], Form::Dnf));
+        assert_eq!(vec!["(a | c) & (~a | b)"], convert(&["a ? b : c"], Form::Cnf));
+        assert_eq!(vec!["a | ~a", "a & ~a"], convert(&["a | ~a", "a & ~a"], Form::Cnf));
+        assert_eq!(vec!["a | ~a", "a & ~a"], convert(&["a | ~a", "a & ~a"], Form::Dnf));
+    }
+
+    #[test]
+    fn tseitin_is_equisatisfiable() {
+        let res = convert(&["(a & b) | ~c", "a ^ b"], Form::Tseitin);
+        assert_eq!("(~d | a) & (~d | b) & (d | ~a | ~b) & (e | ~d) & (e | c) & (~e | d | ~c) & e", res[0]);
+        assert_eq!(4, res[1].matches('&').count());
+    }
+
+    #[test]
+    fn blow_up_guard() {
+        // Parity has 2^(n-1) products in any sum of products
+        let parser = parser::create_session(vec![String::from("a ^ b ^ c ^ d ^ e ^ f ^ g ^ h")]).unwrap();
+        assert!(convert_session(&parser, Form::Dnf, 100).is_err());
+        assert!(convert_session(&parser, Form::Dnf, 128).is_ok());
+        assert!(convert_session(&parser, Form::Nnf, 100).is_err());
+        assert!(convert_session(&parser, Form::Tseitin, 100).is_ok());
+    }
+}
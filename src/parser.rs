@@ -1,14 +1,21 @@
 use linked_hash_map::LinkedHashMap;
+use std::collections::BTreeMap;
 
-static OPERATORS: [char; 7] = ['&', '|', '^', '!', '~', '(', ')'];
+// Widest range a single "name[hi..lo] = ..." line may assign
+pub const MAX_BUS_WIDTH: usize = 4096;
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Token {
     VAR(char),
     LParen,
     RParen,
+    Comma,
+    QMark,
+    Colon,
     OP(Operator),
-    VAL(bool)
+    VAL(bool),
+    DEF(usize), // Reference to the session's definition with this index; a macro pops its arguments like ITE
+    PARAM(usize) // Parameter of the macro being defined, by position
 }
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone, Hash)]
@@ -16,51 +23,222 @@ pub enum Operator {
     AND,
     OR,
     XOR,
-    NOT
+    NOT,
+    ITE
 }
 
 pub struct Parser {
     raw_exprs: Vec<String>,
     pub exprs: Vec<Tokenized>,
+    pub defs: Vec<Definition>,
+    pub buses: Vec<Bus>,
     pub ast_order: LinkedHashMap<char, usize>,
+    // Assumption restricting comparisons to the input combinations where it holds
+    pub care: Option<Tokenized>,
     // pub bdd_order: HashMap<char, isize>
     // pub bdd_order: LinkedHashMap<char, usize>
 }
 
+// shared_rpn keeps the DEF references, letting builders construct each definition once and share it
+// Definitions are never expanded in place, since chained definitions would grow the RPN exponentially
+// uses holds the variables, and the parameters of a definition's body, in the order they would first appear
+// with every definition expanded
+#[derive(Debug, Clone)]
 pub struct Tokenized {
-    pub rpn: Vec<Token>,
+    pub shared_rpn: Vec<Token>,
+    pub uses: Vec<Token>,
     pub tokens: Vec<Token>
 }
 
+// A named subformula such as "carry = a & b | c & (a ^ b)", or a macro such as "def mux(s, x, y) = s & x | ~s & y"
+// Names are at least two letters long so they never clash with variables
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub params: Vec<char>,
+    pub body: Tokenized
+}
+
+// A multi-output function such as "sum[3..0] = ..., ..., ..., ...", whose bits may also be given one at a time
+// Every bit is an expression of the session, labelled like "sum[2]"; bits maps bit numbers to indices in exprs
+#[derive(Debug, Clone)]
+pub struct Bus {
+    pub name: String,
+    pub bits: BTreeMap<usize, usize>
+}
+
+enum Line {
+    Def(Definition),
+    Bits(String, Vec<(usize, Tokenized)>),
+    Expr(Tokenized)
+}
+
 impl Parser {
-    pub fn add_expr(&mut self, mut raw_inputs: Vec<String>) -> Result<(), String> {
-        let mut update_ast_map = |raw: &String| {
-            for c in raw.chars() {
-                if c == ' ' || OPERATORS.contains(&c) || self.ast_order.contains_key(&c) {
-                    continue;
-                } else if c.is_ascii_alphabetic() {
-                    self.ast_order.insert(c, self.ast_order.len());
-                }
+    // Each input is either an expression or a definition that later inputs may use
+    pub fn add_expr(&mut self, raw_inputs: Vec<String>) -> Result<(), String> {
+        // Parse everything up front so a bad input leaves the session untouched
+        let mut defs = self.defs.clone();
+        let mut buses = self.buses.clone();
+        let mut parsed: Vec<(String, Tokenized)> = Vec::with_capacity(raw_inputs.len());
+        for raw in raw_inputs.into_iter() {
+            match parse_line(&raw, &defs)? {
+                Line::Def(def) => defs.push(def),
+                Line::Bits(name, bits) => {
+                    let bus = match buses.iter().position(|bus| bus.name == name) {
+                        Some(ndx) => &mut buses[ndx],
+                        None => {
+                            buses.push(Bus {name: name.clone(), bits: BTreeMap::new()});
+                            buses.last_mut().unwrap()
+                        }
+                    };
+                    for (bit, tokenized) in bits.into_iter() {
+                        if bus.bits.insert(bit, self.exprs.len() + parsed.len()).is_some() {
+                            return Err(format!("Bit {name}[{bit}] is assigned twice"));
+                        }
+                        parsed.push((format!("{name}[{bit}]"), tokenized));
+                    }
+                },
+                Line::Expr(tokenized) => parsed.push((raw, tokenized))
             }
-        };
+        }
 
-        for input in raw_inputs.iter() {
-            let tokenized = parse_expr(input)?;
+        self.defs = defs;
+        self.buses = buses;
+        for (raw, tokenized) in parsed.into_iter() {
+            self.update_ast_map(&tokenized);
             self.exprs.push(tokenized);
-            update_ast_map(input);
+            self.raw_exprs.push(raw);
         };
 
-        self.raw_exprs.append(&mut raw_inputs);
+        Ok(())
+    }
+
+    // Only input combinations satisfying the care expression are compared; its variables become inputs too
+    pub fn set_care(&mut self, raw_care: String) -> Result<(), String> {
+        let tokenized = parse_expr(&raw_care, &self.defs, &[])?;
+        self.update_ast_map(&tokenized);
+        self.care = Some(tokenized);
 
         Ok(())
     }
 
+    // Variables used inside definitions count too
+    fn update_ast_map(&mut self, tokenized: &Tokenized) {
+        for token in tokenized.uses.iter() {
+            if let Token::VAR(c) = token {
+                if !self.ast_order.contains_key(c) {
+                    self.ast_order.insert(*c, self.ast_order.len());
+                }
+            }
+        }
+    }
+
+    // The expressions as entered, without the definitions; bus bits are labelled by name and bit instead
+    pub fn raw_exprs(&self) -> &[String] {
+        return &self.raw_exprs;
+    }
+
+    // Numbered from 1, the way remove_expr is addressed from the command line
     pub fn list_expr(&self) {
-        for expr in self.raw_exprs.iter() {
-            println!("{}", expr);
+        for (ndx, expr) in self.raw_exprs.iter().enumerate() {
+            println!("{}: {}", ndx + 1, expr);
         }
     }
 
+    // Removes the expression at ndx, along with its bus bit; variables no other input uses leave the ordering
+    pub fn remove_expr(&mut self, ndx: usize) -> Result<String, String> {
+        if ndx >= self.exprs.len() {
+            return Err(format!("There is no expression {}", ndx + 1));
+        }
+
+        self.exprs.remove(ndx);
+        let raw = self.raw_exprs.remove(ndx);
+        for bus in self.buses.iter_mut() {
+            bus.bits.retain(|_, e| *e != ndx);
+            for e in bus.bits.values_mut() {
+                if *e > ndx {
+                    *e -= 1;
+                }
+            }
+        }
+        self.buses.retain(|bus| !bus.bits.is_empty());
+        self.prune_order();
+
+        Ok(raw)
+    }
+
+    // Swaps the expression at ndx for another, returning the one it replaces; a bus bit keeps its label
+    pub fn replace_expr(&mut self, ndx: usize, raw_input: String) -> Result<String, String> {
+        if ndx >= self.exprs.len() {
+            return Err(format!("There is no expression {}", ndx + 1));
+        }
+        let tokenized = match parse_line(&raw_input, &self.defs)? {
+            Line::Expr(tokenized) => tokenized,
+            _ => return Err(format!("Expression {} can only be replaced by another expression", ndx + 1))
+        };
+
+        self.update_ast_map(&tokenized);
+        self.exprs[ndx] = tokenized;
+        let old = if self.buses.iter().any(|bus| bus.bits.values().any(|e| *e == ndx)) {
+            self.raw_exprs[ndx].clone()
+        } else {
+            std::mem::replace(&mut self.raw_exprs[ndx], raw_input)
+        };
+        self.prune_order();
+
+        Ok(old)
+    }
+
+    // Variables no expression uses any more leave the ordering; the rest keep their relative order
+    fn prune_order(&mut self) {
+        let used: Vec<char> = self.exprs.iter().chain(self.care.iter())
+            .flat_map(|expr| expr.uses.iter())
+            .filter_map(|t| if let Token::VAR(c) = t { Some(*c) } else { None })
+            .collect();
+        let order: Vec<char> = self.ast_order.keys().copied().filter(|c| used.contains(c)).collect();
+        self.ast_order = order.into_iter().enumerate().map(|(ndx, c)| (c, ndx)).collect();
+    }
+
+    // Number of tokens in the longest expression or assumption once every definition is written out, saturating at
+    // usize::MAX; a chain of definitions each using the one before twice doubles it with every link
+    pub fn expanded_size(&self) -> usize {
+        let mut sizes: Vec<LinearSize> = Vec::with_capacity(self.defs.len());
+        for def in self.defs.iter() {
+            let size = linear_size(&def.body.shared_rpn, &sizes, def.params.len());
+            sizes.push(size);
+        }
+        return self.exprs.iter().chain(self.care.iter())
+            .map(|expr| linear_size(&expr.shared_rpn, &sizes, 0).0)
+            .max()
+            .unwrap_or(0);
+    }
+
+    // Length of the longest chain of definitions that each use the next, so builders recurse at most this deep
+    pub fn definition_depth(&self) -> usize {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.defs.len());
+        for def in self.defs.iter() {
+            let deepest = def.body.shared_rpn.iter()
+                .filter_map(|t| if let Token::DEF(id) = t { Some(depths[*id]) } else { None })
+                .max()
+                .unwrap_or(0);
+            depths.push(deepest + 1);
+        }
+        return depths.into_iter().max().unwrap_or(0);
+    }
+
+    // Moves the given variables to the front of the ordering, in the order given; the rest keep their order
+    pub fn set_order(&mut self, vars: &[char]) -> Result<(), String> {
+        if let Some(c) = vars.iter().find(|c| !self.ast_order.contains_key(c)) {
+            return Err(format!("Variable {c} does not appear in any expression"));
+        } else if let Some(c) = vars.iter().enumerate().find(|(ndx, c)| vars[..*ndx].contains(c)).map(|(_, c)| c) {
+            return Err(format!("Variable {c} is listed twice"));
+        }
+
+        let rest: Vec<char> = self.ast_order.keys().copied().filter(|c| !vars.contains(c)).collect();
+        self.ast_order = vars.iter().copied().chain(rest).enumerate().map(|(ndx, c)| (c, ndx)).collect();
+
+        Ok(())
+    }
 }
 
 pub fn create_session(raw_inputs: Vec<String>) -> Result<Parser, String> {
@@ -71,38 +249,283 @@ pub fn create_session(raw_inputs: Vec<String>) -> Result<Parser, String> {
     let mut res = Parser {
         raw_exprs: Vec::with_capacity(raw_inputs.len()),
         exprs, 
-        ast_order
+        defs: Vec::new(),
+        buses: Vec::new(),
+        ast_order,
+        care: None
     };
 
     res.add_expr(raw_inputs)?;
     Ok(res)
 }
 
-fn parse_expr(input: &String) -> Result<Tokenized, String> {
-    let tokens = tokenize(&input)?;
-    let rpn = convert_rpn(&tokens)?;
+// Parses a partial assignment such as "a=1,b=0" into (variable, value) pairs
+// Assignments may be separated by commas or whitespace
+pub fn parse_assignments(input: &str) -> Result<Vec<(char, bool)>, String> {
+    let mut res: Vec<(char, bool)> = Vec::new();
 
+    for assignment in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|a| !a.is_empty()) {
+        let (var, val) = assignment.split_once('=').ok_or(format!("Expected var=value in assignment {assignment}"))?;
+        let mut chars = var.trim().chars();
+        let var = match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => c,
+            _ => return Err(format!("Invalid variable name {var} in assignment {assignment}"))
+        };
+
+        let val = match val.trim() {
+            "1" | "true" | "T" => true,
+            "0" | "false" | "F" => false,
+            v => return Err(format!("Invalid value {v} in assignment {assignment}"))
+        };
+
+        if res.iter().any(|(c, v)| *c == var && *v != val) {
+            return Err(format!("Conflicting values given for variable {var}"));
+        }
+        res.push((var, val));
+    }
+
+    Ok(res)
+}
+
+// A line containing = is a definition, "name = expr" or "def name(x, y) = expr", or assigns bus bits
+fn parse_line(input: &str, defs: &[Definition]) -> Result<Line, String> {
+    let (lhs, body) = match input.split_once('=') {
+        Some(parts) => parts,
+        None => return Ok(Line::Expr(parse_expr(input, defs, &[])?))
+    };
+    if lhs.contains('[') {
+        return parse_bits(lhs.trim(), body, defs);
+    }
+
+    let lhs = lhs.trim();
+    let lhs = lhs.strip_prefix("def ").unwrap_or(lhs).trim();
+    let (name, params) = match lhs.split_once('(') {
+        Some((name, params)) => {
+            let params = params.trim_end().strip_suffix(')').ok_or(format!("Expected ) after the parameters of {}", name.trim()))?;
+            let params = params.split(',').map(|param| {
+                let mut chars = param.trim().chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphabetic() => Ok(c),
+                    _ => Err(format!("Invalid parameter {} of {}; parameters are single letters", param.trim(), name.trim()))
+                }
+            }).collect::<Result<Vec<char>, String>>()?;
+            (name.trim(), params)
+        },
+        None => (lhs, Vec::new())
+    };
+
+    if name.len() < 2 || !name.chars().all(|c| c.is_ascii_alphabetic()) || name == "ite" || name == "def" {
+        return Err(format!("Invalid definition name {name}; names are two or more letters and not ite or def"));
+    } else if defs.iter().any(|def| def.name == name) {
+        return Err(format!("{name} is already defined"));
+    } else if let Some(param) = params.iter().enumerate().find(|(ndx, p)| params[..*ndx].contains(p)).map(|(_, p)| p) {
+        return Err(format!("Parameter {param} of {name} is repeated"));
+    }
+
+    let body = parse_expr(body, defs, &params)?;
+    Ok(Line::Def(Definition {name: name.to_string(), params, body}))
+}
+
+// "name[i] = expr" assigns one bit; "name[hi..lo] = e_hi, ..., e_lo" assigns a range, listed in the order written
+fn parse_bits(lhs: &str, body: &str, defs: &[Definition]) -> Result<Line, String> {
+    let (name, range) = lhs.strip_suffix(']').and_then(|lhs| lhs.split_once('['))
+        .ok_or(format!("Expected name[bits] before = in {lhs}"))?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("Invalid bus name {name}; bus names are letters"));
+    }
+
+    // The width is checked against the expressions before the range is built, so a huge range costs nothing
+    let parse_bit = |bit: &str| bit.trim().parse::<usize>().map_err(|_| format!("Invalid bit number {} in {lhs}", bit.trim()));
+    let (first, last) = match range.split_once("..") {
+        Some((first, last)) => (parse_bit(first)?, parse_bit(last)?),
+        None => {
+            let bit = parse_bit(range)?;
+            (bit, bit)
+        }
+    };
+    let width = first.abs_diff(last).saturating_add(1);
+    if width > MAX_BUS_WIDTH {
+        return Err(format!("{lhs} is {width} bits wide; buses are limited to {MAX_BUS_WIDTH} bits"));
+    }
+
+    let exprs = split_arguments(body);
+    if exprs.len() != width {
+        return Err(format!("{lhs} has {width} bits but {} expressions were given", exprs.len()));
+    }
+    let bits: Vec<usize> = if first <= last { (first..=last).collect() } else { (last..=first).rev().collect() };
+    let exprs = exprs.iter().map(|expr| parse_expr(expr, defs, &[])).collect::<Result<Vec<Tokenized>, String>>()?;
+    Ok(Line::Bits(name.to_string(), bits.into_iter().zip(exprs).collect()))
+}
+
+// Splits on the commas that are not inside parentheses, which belong to ite and macro calls
+fn split_arguments(input: &str) -> Vec<&str> {
+    let mut res = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (ndx, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                res.push(&input[start..ndx]);
+                start = ndx + 1;
+            },
+            _ => ()
+        }
+    }
+    res.push(&input[start..]);
+    return res;
+}
+
+// params are the letters that stand for a macro's parameters rather than variables
+fn parse_expr(input: &str, defs: &[Definition], params: &[char]) -> Result<Tokenized, String> {
+    let mut tokens = tokenize(&input.to_string(), defs)?;
+    for token in tokens.iter_mut() {
+        if let Token::VAR(c) = token {
+            if let Some(ndx) = params.iter().position(|p| p == c) {
+                *token = Token::PARAM(ndx);
+            }
+        }
+    }
+
+    let shared_rpn = convert_rpn(&tokens, defs)?;
+    if operand_count(&shared_rpn, defs) != Some(1) {
+        return Err(format!("Missing operator or operand in {input}; names longer than one letter must be defined before use"));
+    }
+    let uses = first_uses(&shared_rpn, defs);
     Ok(Tokenized {
-        rpn,
+        shared_rpn,
+        uses,
         tokens
     })
 }
 
+// The expanded size of an RPN as a constant plus a multiple of the size of each parameter's argument
+type LinearSize = (usize, Vec<usize>);
+
+// sizes holds the LinearSize of every definition, so calls are sized without writing out their bodies
+fn linear_size(rpn: &[Token], sizes: &[LinearSize], num_params: usize) -> LinearSize {
+    // Adds times copies of a size to a sum
+    let add = |(base, mut coeffs): LinearSize, (arg_base, arg_coeffs): &LinearSize, times: usize| {
+        coeffs.iter_mut().zip(arg_coeffs.iter()).for_each(|(c, a)| *c = c.saturating_add(a.saturating_mul(times)));
+        (base.saturating_add(arg_base.saturating_mul(times)), coeffs)
+    };
+
+    let mut stack: Vec<LinearSize> = Vec::with_capacity(rpn.len());
+    for token in rpn.iter() {
+        let size = match token {
+            Token::PARAM(ndx) => {
+                let mut coeffs = vec![0; num_params];
+                coeffs[*ndx] = 1;
+                (0, coeffs)
+            },
+            Token::DEF(id) => {
+                let (base, times) = &sizes[*id];
+                let args = stack.split_off(stack.len() - times.len());
+                args.iter().zip(times.iter()).fold((*base, vec![0; num_params]), |sum, (arg, times)| add(sum, arg, *times))
+            },
+            Token::OP(op) => {
+                let arity = match op {
+                    Operator::NOT => 1,
+                    Operator::ITE => 3,
+                    _ => 2
+                };
+                let args = stack.split_off(stack.len() - arity);
+                args.iter().fold((1, vec![0; num_params]), |sum, arg| add(sum, arg, 1))
+            },
+            _ => (1, vec![0; num_params])
+        };
+        stack.push(size);
+    }
+
+    return stack.pop().unwrap_or((0, vec![0; num_params]));
+}
+
+// How many values an RPN leaves on the stack, or None if an operator runs out of operands
+fn operand_count(rpn: &[Token], defs: &[Definition]) -> Option<usize> {
+    let mut depth: usize = 0;
+    for token in rpn.iter() {
+        let (pops, pushes) = match token {
+            Token::OP(Operator::NOT) => (1, 1),
+            Token::OP(Operator::ITE) => (3, 1),
+            Token::OP(_) => (2, 1),
+            Token::DEF(id) => (defs[*id].params.len(), 1),
+            _ => (0, 1)
+        };
+        depth = depth.checked_sub(pops)? + pushes;
+    }
+    return Some(depth);
+}
+
+// The variables and parameters of an RPN in the order they first appear once every definition is expanded
+// A definition's own list stands in for its body, so nothing is ever expanded
+fn first_uses(shared_rpn: &[Token], defs: &[Definition]) -> Vec<Token> {
+    let mut operands: Vec<Vec<Token>> = Vec::with_capacity(shared_rpn.len());
+    for token in shared_rpn.iter() {
+        let arity = match token {
+            Token::OP(Operator::NOT) => 1,
+            Token::OP(Operator::ITE) => 3,
+            Token::OP(_) => 2,
+            Token::DEF(id) => defs[*id].params.len(),
+            _ => 0
+        };
+        let args = operands.split_off(operands.len() - arity);
 
-fn tokenize(input: &String) -> Result<Vec<Token>, String> {
+        let mut res: Vec<Token> = Vec::new();
+        let mut push = |t: &Token| if !res.contains(t) { res.push(*t) };
+        match token {
+            Token::DEF(id) => {
+                for t in defs[*id].body.uses.iter() {
+                    match t {
+                        Token::PARAM(ndx) => args[*ndx].iter().for_each(&mut push),
+                        t => push(t)
+                    }
+                }
+            },
+            Token::VAR(_) | Token::PARAM(_) => push(token),
+            _ => args.iter().flatten().for_each(&mut push)
+        }
+        operands.push(res);
+    }
+
+    return operands.concat();
+}
+
+// A run of letters naming a definition becomes a DEF token; any other letters are single-letter variables
+fn tokenize(input: &String, defs: &[Definition]) -> Result<Vec<Token>, String> {
     let mut tokens: Vec<Token> = Vec::with_capacity(input.len());
+    let chars: Vec<char> = input.chars().collect();
+
+    let mut ndx = 0;
+    while ndx < chars.len() {
+        let c = chars[ndx];
+        ndx += 1;
+        let word_len = chars[ndx - 1..].iter().take_while(|c| c.is_ascii_alphabetic()).count();
+        if word_len > 1 {
+            let word: String = chars[ndx - 1..ndx - 1 + word_len].iter().collect();
+            if let Some(id) = defs.iter().position(|def| def.name == word) {
+                tokens.push(Token::DEF(id));
+                ndx += word_len - 1;
+                continue;
+            }
+        }
 
-    for c in input.chars() {
         match c {
-            ' ' =>          continue,
+            c if c.is_whitespace() => continue,
             '&' =>          tokens.push(Token::OP(Operator::AND)),
             '|' =>          tokens.push(Token::OP(Operator::OR)),
             '^' =>          tokens.push(Token::OP(Operator::XOR)),
             '!' | '~' =>    tokens.push(Token::OP(Operator::NOT)),
             '(' =>          tokens.push(Token::LParen),
             ')' =>          tokens.push(Token::RParen),
+            ',' =>          tokens.push(Token::Comma),
+            '?' =>          tokens.push(Token::QMark),
+            ':' =>          tokens.push(Token::Colon),
             c => {
-                if c.is_ascii_alphabetic() {
+                if let Some(len) = match_ite(&chars[ndx - 1..]) {
+                    tokens.push(Token::OP(Operator::ITE));
+                    ndx += len - 1;
+                } else if c.is_ascii_alphabetic() {
                     tokens.push(Token::VAR(c));
                 } else {
                     return Err(format!("Non-alphabetical variable name {c} encountered in input string {input}"))
@@ -113,23 +536,96 @@ fn tokenize(input: &String) -> Result<Vec<Token>, String> {
     Ok(tokens)
 }
 
-fn convert_rpn(tokens: &Vec<Token>) -> Result<Vec<Token>, String> {
+// Variables are single letters, so "ite" is only treated as the ite function when it is called
+// Returns the length of the keyword, not including the opening paren
+fn match_ite(chars: &[char]) -> Option<usize> {
+    if !chars.starts_with(&['i', 't', 'e']) {
+        return None;
+    }
+
+    match chars[3..].iter().find(|c| !c.is_whitespace()) {
+        Some('(') => Some(3),
+        _ => None
+    }
+}
+
+// The conditional operator binds looser than every binary operator and associates to the right
+// Macro calls are parsed like ite(...), checking the argument count against the definition
+fn convert_rpn(tokens: &[Token], defs: &[Definition]) -> Result<Vec<Token>, String> {
     let mut rpn: Vec<Token> = Vec::with_capacity(tokens.len());
     let mut op_stack: Vec<&Token> = Vec::with_capacity(tokens.len());
+    // Tracks each open paren; Some(n) counts the arguments seen so far in an ite call
+    let mut paren_args: Vec<Option<usize>> = Vec::new();
 
-    for token in tokens.iter() {
+    // Pops operators until reaching a paren (or a ?, if stop_at_qmark); completed conditionals become ITE
+    let pop_until_marker = |op_stack: &mut Vec<&Token>, rpn: &mut Vec<Token>, stop_at_qmark: bool| -> Result<(), String> {
+        while let Some(top) = op_stack.last() {
+            match top {
+                Token::OP(o) => rpn.push(Token::OP(*o)),
+                Token::Colon => rpn.push(Token::OP(Operator::ITE)),
+                Token::QMark if stop_at_qmark => break,
+                Token::QMark => return Err(String::from("Conditional ? is missing its :")),
+                _ => break
+            }
+            op_stack.pop();
+        }
+        Ok(())
+    };
+
+    for (ndx, token) in tokens.iter().enumerate() {
         match token {
-            Token::LParen | Token::OP(Operator::NOT) => op_stack.push(token),
-            Token::VAR(c) => rpn.push(Token::VAR(*c)),
+            Token::LParen => {
+                let is_call = match ndx.checked_sub(1).map(|prev| tokens[prev]) {
+                    Some(Token::DEF(id)) if defs[id].params.is_empty() => return Err(format!("{} takes no arguments", defs[id].name)),
+                    Some(Token::OP(Operator::ITE)) | Some(Token::DEF(_)) => true,
+                    _ => false
+                };
+                paren_args.push(if is_call { Some(1) } else { None });
+                op_stack.push(token);
+            },
+            Token::DEF(id) if defs[*id].params.is_empty() => rpn.push(Token::DEF(*id)),
+            Token::DEF(id) => {
+                if tokens.get(ndx + 1) != Some(&Token::LParen) {
+                    return Err(format!("{} expects {} arguments", defs[*id].name, defs[*id].params.len()));
+                }
+                op_stack.push(token);
+            },
+            Token::OP(Operator::NOT) | Token::OP(Operator::ITE) => op_stack.push(token),
+            Token::VAR(_) | Token::PARAM(_) => rpn.push(*token),
             Token::RParen => {
-                loop {
-                    let top = op_stack.pop().ok_or(String::from("Unclosed right paren"))?;
-                    if let Token::OP(o) = top {
-                        // dbg!("Pushed {} to rpn", *o);
-                        rpn.push(Token::OP(*o));
-                    } else {
-                        break;
+                pop_until_marker(&mut op_stack, &mut rpn, false)?;
+                op_stack.pop().ok_or(String::from("Unclosed right paren"))?;
+                if let Some(Some(args)) = paren_args.pop() {
+                    let call = *op_stack.pop().ok_or(String::from("Argument list without a call"))?;
+                    let (name, arity) = match call {
+                        Token::DEF(id) => (defs[id].name.as_str(), defs[id].params.len()),
+                        _ => ("ite", 3)
+                    };
+                    if args != arity {
+                        return Err(format!("{name} expects {arity} arguments but {args} were given"));
                     }
+                    rpn.push(call);
+                }
+            },
+            Token::Comma => {
+                pop_until_marker(&mut op_stack, &mut rpn, false)?;
+                match paren_args.last_mut() {
+                    Some(Some(args)) => *args += 1,
+                    _ => return Err(String::from("Argument separator , used outside of ite(...) or a macro call"))
+                }
+            },
+            Token::QMark => {
+                while let Some(Token::OP(o)) = op_stack.last() {
+                    rpn.push(Token::OP(*o));
+                    op_stack.pop();
+                }
+                op_stack.push(token);
+            },
+            Token::Colon => {
+                pop_until_marker(&mut op_stack, &mut rpn, true)?;
+                match op_stack.pop() {
+                    Some(Token::QMark) => op_stack.push(token),
+                    _ => return Err(String::from("Conditional : without a matching ?"))
                 }
             },
             t => {
@@ -144,10 +640,11 @@ fn convert_rpn(tokens: &Vec<Token>) -> Result<Vec<Token>, String> {
 
     if !op_stack.is_empty() {
         for op in op_stack.into_iter().rev() {
-            if *op == Token::LParen { 
-                return Err(String::from("Unclosed left paren"))
-            } else {
-                rpn.push(*op);
+            match op {
+                Token::LParen => return Err(String::from("Unclosed left paren")),
+                Token::QMark => return Err(String::from("Conditional ? is missing its :")),
+                Token::Colon => rpn.push(Token::OP(Operator::ITE)),
+                op => rpn.push(*op)
             }
         }
     }
@@ -155,6 +652,17 @@ fn convert_rpn(tokens: &Vec<Token>) -> Result<Vec<Token>, String> {
     Ok(rpn)
 }
 
+// Definitions d0 = a ^ b, then each dn = dn-1 ^ (dn-1 & c), followed by the last one as an expression
+// Every link uses the one before it twice, so written out the expression holds 2^len copies of a ^ b
+#[cfg(test)]
+pub(crate) fn definition_chain(len: usize) -> Vec<String> {
+    let name = |n: usize| format!("d{}{}", (b'a' + (n / 26) as u8) as char, (b'a' + (n % 26) as u8) as char);
+    let mut inputs = vec![format!("{} = a ^ b", name(0))];
+    inputs.extend((1..=len).map(|n| format!("{} = {} ^ ({} & c)", name(n), name(n - 1), name(n - 1))));
+    inputs.push(name(len));
+    return inputs;
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -162,20 +670,26 @@ mod test {
     #[test]
     fn token_simple() {
         let expected: Result<Vec<Token>, String> = Ok(vec![Token::VAR('a'), Token::OP(Operator::AND), Token::VAR('b')]);
-        assert_eq!(expected, tokenize(&String::from("a & b")))
+        assert_eq!(expected, tokenize(&String::from("a & b"), &[]))
 
     }
 
+    #[test]
+    fn token_whitespace() {
+        let expected: Result<Vec<Token>, String> = Ok(vec![Token::VAR('a'), Token::OP(Operator::AND), Token::VAR('b')]);
+        assert_eq!(expected, tokenize(&String::from("\ta &\tb\r"), &[]))
+    }
+
     #[test]
     fn token_fail() {
-       assert!(tokenize(&String::from("@ ^ $")).is_err())
+       assert!(tokenize(&String::from("@ ^ $"), &[]).is_err())
     }
 
     #[test]
     fn rpn_not() {
         let expected: Vec<Token> = vec![Token::VAR('a'), Token::OP(Operator::NOT), Token::VAR('b'), Token::OP(Operator::AND)];
-        let input = tokenize(&String::from("~a & b")).expect("tokenize step failed");
-        assert_eq!(Ok(expected), convert_rpn(&input))
+        let input = tokenize(&String::from("~a & b"), &[]).expect("tokenize step failed");
+        assert_eq!(Ok(expected), convert_rpn(&input, &[]))
     }
 
     #[test]
@@ -183,19 +697,171 @@ mod test {
         let expected: Vec<Token> = vec![Token::VAR('a'), Token::VAR('b'), Token::OP(Operator::AND),
         Token::VAR('c'), Token::VAR('d'), Token::VAR('e'), Token::OP(Operator::AND), Token::OP(Operator::XOR),
         Token::OP(Operator::NOT), Token::OP(Operator::OR)];
-        let input = tokenize(&String::from("(a & b) | ~(c ^ (d & e))")).unwrap();
-        assert_eq!(Ok(expected), convert_rpn(&input))
+        let input = tokenize(&String::from("(a & b) | ~(c ^ (d & e))"), &[]).unwrap();
+        assert_eq!(Ok(expected), convert_rpn(&input, &[]))
     }
 
     #[test]
     fn rpn_left_paren_unclosed() {
-        let input = tokenize(&String::from("(a & b")).expect("tokenize step failed");
-        assert!(convert_rpn(&input).is_err())
+        let input = tokenize(&String::from("(a & b"), &[]).expect("tokenize step failed");
+        assert!(convert_rpn(&input, &[]).is_err())
     }
 
     #[test]
     fn rpn_right_paren_unclosed() {
-        let input = tokenize(&String::from("a & b)")).expect("tokenize step failed");
-        assert!(convert_rpn(&input).is_err())
+        let input = tokenize(&String::from("a & b)"), &[]).expect("tokenize step failed");
+        assert!(convert_rpn(&input, &[]).is_err())
+    }
+
+    #[test]
+    fn assignments() {
+        assert_eq!(Ok(vec![('a', true), ('b', false)]), parse_assignments("a=1,b=0"));
+        assert_eq!(Ok(vec![('a', true), ('b', false)]), parse_assignments("a=1 b=0"));
+        assert!(parse_assignments("a=2").is_err());
+        assert!(parse_assignments("ab=1").is_err());
+        assert!(parse_assignments("a=1,a=0").is_err());
+    }
+
+    #[test]
+    fn token_ite() {
+        let expected = vec![Token::OP(Operator::ITE), Token::LParen, Token::VAR('a'), Token::Comma,
+        Token::VAR('t'), Token::Comma, Token::VAR('e'), Token::RParen, Token::OP(Operator::AND), Token::VAR('i')];
+        assert_eq!(Ok(expected.clone()), tokenize(&String::from("ite (a, t, e) & i"), &[]));
+        assert_eq!(Ok(expected), tokenize(&String::from("ite\t(a, t, e) & i"), &[]));
+        assert_eq!(Ok(vec![Token::VAR('i'), Token::VAR('t'), Token::VAR('e')]), tokenize(&String::from("ite"), &[]));
+    }
+
+    #[test]
+    fn rpn_ternary() {
+        let expected: Vec<Token> = vec![Token::VAR('a'), Token::VAR('b'), Token::VAR('c'), Token::VAR('d'),
+        Token::OP(Operator::AND), Token::VAR('e'), Token::VAR('e'), Token::OP(Operator::NOT),
+        Token::OP(Operator::ITE), Token::OP(Operator::ITE)];
+        let input = tokenize(&String::from("a ? b : c & d ? e : ~e"), &[]).unwrap();
+        assert_eq!(Ok(expected), convert_rpn(&input, &[]))
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rpn_ite_call_matches_ternary() {
+        let call = tokenize(&String::from("~ite(a & b, c, d ? a : b)"), &[]).unwrap();
+        let ternary = tokenize(&String::from("~(a & b ? c : (d ? a : b))"), &[]).unwrap();
+        assert_eq!(convert_rpn(&ternary, &[]), convert_rpn(&call, &[]));
+    }
+
+    #[test]
+    fn rpn_ternary_errors() {
+        for input in ["a ? b", "a : b", "(a ? b) : c", "ite(a, b)", "a, b", "ite(a, b, c, d)"] {
+            let tokens = tokenize(&String::from(input), &[]).expect("tokenize step failed");
+            assert!(convert_rpn(&tokens, &[]).is_err(), "{input} should not parse");
+        }
+    }
+    #[test]
+    fn definitions_stay_shared() {
+        let inputs = ["carry = a & b", "def mux(s, x, y) = s ? x : y", "mux(carry, c, ~ carry)", "~mux(c,a,b) ^ carry"];
+        let session = create_session(inputs.iter().map(|i| i.to_string()).collect()).unwrap();
+        assert_eq!(2, session.defs.len());
+        assert_eq!(vec![String::from("mux(carry, c, ~ carry)"), String::from("~mux(c,a,b) ^ carry")], session.raw_exprs());
+        assert_eq!(vec![Token::PARAM(0), Token::PARAM(1), Token::PARAM(2), Token::OP(Operator::ITE)], session.defs[1].body.shared_rpn);
+        assert_eq!(vec![Token::DEF(0), Token::VAR('c'), Token::DEF(0), Token::OP(Operator::NOT), Token::DEF(1)], session.exprs[0].shared_rpn);
+
+        // Variables are listed as if the definitions had been written out
+        let inline = create_session(vec![String::from("(a & b) ? c : ~(a & b)"), String::from("~(c ? a : b) ^ (a & b)")]).unwrap();
+        assert_eq!(inline.exprs[0].uses, session.exprs[0].uses);
+        assert_eq!(inline.exprs[1].uses, session.exprs[1].uses);
+        assert_eq!(vec![Token::VAR('c'), Token::VAR('a'), Token::VAR('b')], session.exprs[1].uses);
+        assert_eq!(vec!['a', 'b', 'c'], session.ast_order.keys().copied().collect::<Vec<char>>());
+    }
+
+    #[test]
+    fn macro_parameters_do_not_capture_variables() {
+        let inputs = ["def both(x) = x & y", "def twice(y) = both(y)", "twice(a)"];
+        let session = create_session(inputs.iter().map(|i| i.to_string()).collect()).unwrap();
+        assert_eq!(vec![Token::PARAM(0), Token::VAR('y')], session.defs[1].body.uses);
+        assert_eq!(vec![Token::VAR('a'), Token::VAR('y')], session.exprs[0].uses);
+    }
+
+    #[test]
+    fn deep_definition_chains() {
+        let session = create_session(definition_chain(40)).unwrap();
+        assert_eq!(vec![Token::DEF(40)], session.exprs[0].shared_rpn);
+        assert_eq!(vec![Token::VAR('a'), Token::VAR('b'), Token::VAR('c')], session.exprs[0].uses);
+        assert_eq!(vec!['a', 'b', 'c'], session.ast_order.keys().copied().collect::<Vec<char>>());
+    }
+
+    #[test]
+    fn sizes_without_expanding() {
+        let inputs = ["carry = a & b", "def mux(s, x, y) = s ? x : y", "mux(carry, c, ~carry)", "d"];
+        let session = create_session(inputs.iter().map(|i| i.to_string()).collect()).unwrap();
+        // a b & c a b & ~ ?: is the expanded RPN of the first expression
+        assert_eq!(9, session.expanded_size());
+        assert_eq!(1, session.definition_depth());
+
+        let session = create_session(definition_chain(80)).unwrap();
+        assert_eq!(usize::MAX, session.expanded_size());
+        assert_eq!(81, session.definition_depth());
+    }
+
+    #[test]
+    fn definition_errors() {
+        let cases = [
+            vec!["x = a"], vec!["ite = a"], vec!["def f(xy) = a"], vec!["def ff(x, x) = x"], vec!["ab = a", "ab = b"],
+            vec!["def ff(x, y) = x", "ff(a)"], vec!["def ff(x) = x", "ff & a"], vec!["def ff(x = x"], vec!["ab = cd"]
+        ];
+        for inputs in cases {
+            let mut session = create_session(Vec::new()).unwrap();
+            assert!(session.add_expr(inputs.iter().map(|i| i.to_string()).collect()).is_err(), "{inputs:?} should not parse");
+            assert!(session.defs.is_empty());
+        }
+    }
+
+    #[test]
+    fn named_definitions_take_no_arguments() {
+        for expr in ["carry(a)", "c & carry(a)"] {
+            let err = create_session(vec![String::from("carry = a & b"), String::from(expr)]).err();
+            assert_eq!(Some(String::from("carry takes no arguments")), err, "{expr} should not parse");
+        }
+    }
+
+    #[test]
+    fn remove_and_reorder() {
+        let inputs = ["a & b", "s[1..0] = b | c, d", "c ^ a"];
+        let mut session = create_session(inputs.iter().map(|i| i.to_string()).collect()).unwrap();
+        assert_eq!(vec!['a', 'b', 'c', 'd'], session.ast_order.keys().copied().collect::<Vec<char>>());
+
+        assert_eq!(Ok(String::from("s[0]")), session.remove_expr(2));
+        assert_eq!(vec![String::from("a & b"), String::from("s[1]"), String::from("c ^ a")], session.raw_exprs());
+        assert_eq!(vec![(1, 1)], session.buses[0].bits.iter().map(|(b, e)| (*b, *e)).collect::<Vec<(usize, usize)>>());
+        assert_eq!(vec![('a', 0), ('b', 1), ('c', 2)], session.ast_order.iter().map(|(c, n)| (*c, *n)).collect::<Vec<(char, usize)>>());
+
+        session.remove_expr(1).unwrap();
+        assert!(session.buses.is_empty());
+        assert!(session.remove_expr(2).is_err());
+
+        session.set_order(&['c', 'a']).unwrap();
+        assert_eq!(vec![('c', 0), ('a', 1), ('b', 2)], session.ast_order.iter().map(|(c, n)| (*c, *n)).collect::<Vec<(char, usize)>>());
+        assert!(session.set_order(&['d']).is_err());
+        assert!(session.set_order(&['a', 'a']).is_err());
+    }
+
+    #[test]
+    fn huge_bus_ranges_are_rejected_up_front() {
+        // Neither range is ever built, so these fail at once rather than trying to allocate the bits
+        let err = |input: String| create_session(vec![input]).err().unwrap();
+        assert_eq!("s[0..9999999999] is 10000000000 bits wide; buses are limited to 4096 bits", err(String::from("s[0..9999999999] = a")));
+        assert!(err(format!("s[{}..0] = a, b", usize::MAX)).contains("limited to"));
+        assert_eq!("s[3..0] has 4 bits but 2 expressions were given", err(String::from("s[3..0] = a, b")));
+
+        let exprs = vec!["a"; MAX_BUS_WIDTH].join(", ");
+        let session = create_session(vec![format!("s[{}..0] = {exprs}", MAX_BUS_WIDTH - 1)]).unwrap();
+        assert_eq!(MAX_BUS_WIDTH, session.buses[0].bits.len());
+    }
+
+    #[test]
+    fn replace_keeps_bus_labels() {
+        let mut session = create_session(vec![String::from("s[1..0] = a, b"), String::from("a | b")]).unwrap();
+        assert_eq!(Ok(String::from("s[0]")), session.replace_expr(1, String::from("c")));
+        assert_eq!(Ok(String::from("a | b")), session.replace_expr(2, String::from("c & a")));
+        assert_eq!(vec![String::from("s[1]"), String::from("s[0]"), String::from("c & a")], session.raw_exprs());
+        assert_eq!(vec!['a', 'c'], session.ast_order.keys().copied().collect::<Vec<char>>());
+        assert!(session.replace_expr(0, String::from("xy = a")).is_err());
+    }
+}
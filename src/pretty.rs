@@ -0,0 +1,277 @@
+// Turns parsed expressions, AST nodes and BDDs back into infix text with as few parentheses as possible
+// The ASCII dialect follows the parser: every binary operator has the same precedence and groups to the right,
+// so its output reads back into the same tree. The C and Unicode dialects are for export and use their own
+// conventional precedences (tighter to looser: xor, and, or in C; and, xor, or in Unicode), grouping to the left.
+// In every dialect the conditional binds loosest and ! or ~ binds tightest.
+
+use std::fmt;
+use crate::areabdd::{self, BDD};
+use crate::parser::{Definition, Operator, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Ascii,
+    C,
+    Unicode
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Var(char),
+    Not(Box<Expr>),
+    Bin(Operator, Box<Expr>, Box<Expr>),
+    Ite(Box<Expr>, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>) // Use of a named definition or macro
+}
+
+// Wraps an expression with the dialect it is displayed in
+pub struct Pretty<'a> {
+    expr: &'a Expr,
+    dialect: Dialect
+}
+
+const ATOM: u8 = u8::MAX;
+const CONDITIONAL: u8 = 0;
+
+impl Dialect {
+    fn spelling(&self, op: Operator) -> &'static str {
+        match (self, op) {
+            (Dialect::Ascii, Operator::NOT) => "~",
+            (Dialect::Ascii, Operator::AND) => "&",
+            (Dialect::Ascii, Operator::OR) => "|",
+            (Dialect::C, Operator::NOT) => "!",
+            (Dialect::C, Operator::AND) => "&&",
+            (Dialect::C, Operator::OR) => "||",
+            (Dialect::Unicode, Operator::NOT) => "¬",
+            (Dialect::Unicode, Operator::AND) => "∧",
+            (Dialect::Unicode, Operator::OR) => "∨",
+            (Dialect::Unicode, Operator::XOR) => "⊕",
+            (_, Operator::XOR) => "^",
+            (_, Operator::ITE) => "?"
+        }
+    }
+
+    // Binding strength of a binary operator, and whether it groups to the right
+    fn precedence(&self, op: Operator) -> (u8, bool) {
+        match (self, op) {
+            (Dialect::Ascii, _) => (1, true),
+            (Dialect::C, Operator::XOR) | (Dialect::Unicode, Operator::AND) => (3, false),
+            (Dialect::C, Operator::AND) | (Dialect::Unicode, Operator::XOR) => (2, false),
+            _ => (1, false)
+        }
+    }
+
+    fn binding(&self, expr: &Expr) -> u8 {
+        match expr {
+            Expr::Var(_) | Expr::Not(_) | Expr::Call(_, _) => ATOM,
+            Expr::Bin(op, _, _) => self.precedence(*op).0,
+            Expr::Ite(_, _, _) => CONDITIONAL
+        }
+    }
+}
+
+impl Expr {
+    pub fn from_rpn(rpn: &[Token]) -> Expr {
+        return Expr::from_shared_rpn(rpn, &[], &[]);
+    }
+
+    // Keeps the references to definitions in a shared RPN as calls; params names a macro body's parameters
+    pub fn from_shared_rpn(rpn: &[Token], defs: &[Definition], params: &[char]) -> Expr {
+        let mut op_stack: Vec<Expr> = Vec::with_capacity(rpn.len());
+
+        for t in rpn.iter() {
+            match t {
+                Token::VAR(c) => op_stack.push(Expr::Var(*c)),
+                Token::PARAM(ndx) => op_stack.push(Expr::Var(params[*ndx])),
+                Token::DEF(id) => {
+                    let args = op_stack.split_off(op_stack.len() - defs[*id].params.len());
+                    op_stack.push(Expr::Call(defs[*id].name.clone(), args));
+                },
+                Token::OP(Operator::NOT) => {
+                    let top = op_stack.pop().unwrap();
+                    op_stack.push(Expr::Not(Box::new(top)));
+                },
+                Token::OP(Operator::ITE) => {
+                    let h = op_stack.pop().unwrap();
+                    let g = op_stack.pop().unwrap();
+                    let f = op_stack.pop().unwrap();
+                    op_stack.push(Expr::Ite(Box::new(f), Box::new(g), Box::new(h)));
+                },
+                Token::OP(op) => {
+                    let rhs = op_stack.pop().unwrap();
+                    let lhs = op_stack.pop().unwrap();
+                    op_stack.push(Expr::Bin(*op, Box::new(lhs), Box::new(rhs)));
+                },
+                _ => panic!("Unexpected token while printing expression")
+            }
+        }
+
+        return op_stack.pop().expect("No expression left on the stack");
+    }
+
+    pub fn display(&self, dialect: Dialect) -> Pretty<'_> {
+        return Pretty {expr: self, dialect};
+    }
+
+    // Removes a negation instead of stacking a second one
+    pub fn negate(self) -> Expr {
+        match self {
+            Expr::Not(inner) => *inner,
+            expr => Expr::Not(Box::new(expr))
+        }
+    }
+}
+
+impl fmt::Display for Pretty<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let dialect = self.dialect;
+        let operand = |f: &mut fmt::Formatter<'_>, expr: &Expr, parens: bool| -> fmt::Result {
+            if parens {
+                write!(f, "({})", expr.display(dialect))
+            } else {
+                write!(f, "{}", expr.display(dialect))
+            }
+        };
+
+        match self.expr {
+            Expr::Var(c) => write!(f, "{c}"),
+            Expr::Not(inner) => {
+                write!(f, "{}", dialect.spelling(Operator::NOT))?;
+                operand(f, inner, dialect.binding(inner) != ATOM)
+            },
+            Expr::Bin(op, lhs, rhs) => {
+                let (prec, right) = dialect.precedence(*op);
+                let (lhs_prec, rhs_prec) = (dialect.binding(lhs), dialect.binding(rhs));
+                operand(f, lhs, lhs_prec < prec || (lhs_prec == prec && right))?;
+                write!(f, " {} ", dialect.spelling(*op))?;
+                operand(f, rhs, rhs_prec < prec || (rhs_prec == prec && !right))
+            },
+            Expr::Ite(cond, then, other) => {
+                operand(f, cond, dialect.binding(cond) == CONDITIONAL)?;
+                write!(f, " ? ")?;
+                operand(f, then, false)?;
+                write!(f, " : ")?;
+                operand(f, other, false)
+            },
+            Expr::Call(name, args) if args.is_empty() => write!(f, "{name}"),
+            Expr::Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.display(dialect).to_string()).collect();
+                write!(f, "{name}({})", args.join(", "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.display(Dialect::Ascii))
+    }
+}
+
+// Writes a definition back in the form it is entered in, "name = expr" or "def name(x, y) = expr"
+pub fn definition(def: &Definition, defs: &[Definition], dialect: Dialect) -> String {
+    let body = Expr::from_shared_rpn(&def.body.shared_rpn, defs, &def.params);
+    if def.params.is_empty() {
+        return format!("{} = {}", def.name, body.display(dialect));
+    }
+
+    let params: Vec<String> = def.params.iter().map(|p| p.to_string()).collect();
+    return format!("def {}({}) = {}", def.name, params.join(", "), body.display(dialect));
+}
+
+// Reads a BDD back as nested conditionals, using and, or and xor where a branch is constant or complementary
+// There are no constant tokens, so a constant root is written with the first variable of the ordering
+pub fn from_bdd(bdd: &BDD, root: isize) -> Expr {
+    let names: Vec<char> = bdd.ordering().keys().copied().collect();
+    if root.abs() == 1 {
+        let var = Expr::Var(names[0]);
+        let op = if root == 1 { Operator::OR } else { Operator::AND };
+        return Expr::Bin(op, Box::new(var.clone()), Box::new(var.negate()));
+    }
+
+    return from_bdd_helper(bdd, root, &names);
+}
+
+fn from_bdd_helper(bdd: &BDD, edge: isize, names: &[char]) -> Expr {
+    if edge < 0 {
+        return from_bdd_helper(bdd, -edge, names).negate();
+    }
+
+    // hi edges are never complemented, so hi is never the 0-terminal
+    let (var, lo, hi) = areabdd::vertex_parts(bdd, edge);
+    let var = Expr::Var(names[var as usize - 1]);
+    let sub = |edge: isize| Box::new(from_bdd_helper(bdd, edge, names));
+    return match (lo, hi) {
+        (-1, 1) => var,
+        (1, hi) => Expr::Bin(Operator::OR, Box::new(var.negate()), sub(hi)),
+        (-1, hi) => Expr::Bin(Operator::AND, Box::new(var), sub(hi)),
+        (lo, 1) => Expr::Bin(Operator::OR, Box::new(var), sub(lo)),
+        (lo, hi) if lo == -hi => Expr::Bin(Operator::XOR, Box::new(var), sub(lo)),
+        (lo, hi) => Expr::Ite(Box::new(var), sub(hi), sub(lo))
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn expr(input: &str) -> Expr {
+        let parser = parser::create_session(vec![String::from(input)]).unwrap();
+        return Expr::from_rpn(&parser.exprs[0].shared_rpn);
+    }
+
+    #[test]
+    fn minimal_parentheses() {
+        let cases = [
+            ("((a) & ((b) | c))", "a & b | c"),
+            ("(a & b) | c", "(a & b) | c"),
+            ("!(~a) ^ ~(b & c)", "~~a ^ ~(b & c)"),
+            ("ite(a & b, c, ite(d, e, f))", "a & b ? c : d ? e : f"),
+            ("(a ? b : c) ? (d ? e : f) : g", "(a ? b : c) ? d ? e : f : g"),
+            ("a & (b ? c : d)", "a & (b ? c : d)"),
+            ("(a ? b : c) & d", "(a ? b : c) & d")
+        ];
+
+        for (input, expected) in cases {
+            let printed = expr(input).to_string();
+            assert_eq!(expected, printed);
+            assert_eq!(expr(input), expr(&printed), "{printed} should read back as {input}");
+        }
+    }
+
+    #[test]
+    fn dialects() {
+        let e = expr("(a & b) | ~c ^ (d & e) | f");
+        assert_eq!("(a & b) | ~c ^ (d & e) | f", e.to_string());
+        assert_eq!("a && b || !c ^ (d && e || f)", e.display(Dialect::C).to_string());
+        assert_eq!("a ∧ b ∨ ¬c ⊕ (d ∧ e ∨ f)", e.display(Dialect::Unicode).to_string());
+
+        let e = expr("(a ^ b) & c");
+        assert_eq!("(a ^ b) & c", e.to_string());
+        assert_eq!("a ^ b && c", e.display(Dialect::C).to_string());
+        assert_eq!("(a ⊕ b) ∧ c", e.display(Dialect::Unicode).to_string());
+    }
+
+    #[test]
+    fn tokenized_and_bdd() {
+        let parser = parser::create_session(vec![String::from("a ? b : ~b"), String::from("(a & b) | (~a & c)"), String::from("a | ~a")]).unwrap();
+        assert_eq!("a ? b : ~b", Expr::from_shared_rpn(&parser.exprs[0].shared_rpn, &parser.defs, &[]).to_string());
+
+        let bdd = areabdd::build(BDD::new(), &parser);
+        let printed: Vec<String> = bdd.roots().iter().map(|root| from_bdd(&bdd, *root).to_string()).collect();
+        assert_eq!(vec!["a ^ ~b", "a ? b : c", "a | ~a"], printed);
+    }
+
+    #[test]
+    fn definitions_and_calls() {
+        let inputs = ["cy = (a & b) | c", "def mux(s,x,y) = s ? x : (y)", "~mux(cy, a ? b : c, ~cy) & cy"];
+        let parser = parser::create_session(inputs.iter().map(|i| i.to_string()).collect()).unwrap();
+        let printed: Vec<String> = parser.defs.iter().map(|def| definition(def, &parser.defs, Dialect::Ascii)).collect();
+        assert_eq!(vec!["cy = (a & b) | c", "def mux(s, x, y) = s ? x : y"], printed);
+
+        let expr = Expr::from_shared_rpn(&parser.exprs[0].shared_rpn, &parser.defs, &[]);
+        assert_eq!("~mux(cy, a ? b : c, ~cy) & cy", expr.to_string());
+        assert_eq!("!mux(cy, a ? b : c, !cy) && cy", expr.display(Dialect::C).to_string());
+    }
+}
@@ -0,0 +1,230 @@
+// Tautology, satisfiability, contradiction and implication checks on top of the existing engines
+// Every question reduces to a search for an input on which some expressions take given values: f is a tautology
+// unless ~f has a solution, and f implies g unless f & ~g does. Searches only consider inputs consistent with the
+// fixed assignments and the care set, and a solution is the witness, reported like a counterexample row:
+// the input values followed by every expression's result.
+
+use std::collections::HashMap;
+use crate::ast;
+use crate::miter::{self, Tseitin};
+use crate::parser::{Operator, Parser};
+use crate::sat::{Lit, Solver};
+use crate::sharedbdd::{self, SharedBDD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Query {
+    Tautology,
+    Unsat,
+    Sat
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verdict {
+    pub holds: bool,
+    pub witness: Option<Vec<bool>> // A falsifying row when the property fails, or a satisfying row for sat
+}
+
+// Each engine is set up once per session and then answers any number of searches
+pub enum Searcher {
+    Ast {
+        rows: Vec<Vec<bool>>, // Every row of the truth table in the domain
+        num_inputs: usize
+    },
+    Bdd {
+        bdd: SharedBDD,
+        roots: Vec<isize>,
+        domain: isize
+    },
+    Sat {
+        solver: Box<Solver>,
+        inputs: Vec<Lit>,
+        outputs: Vec<Lit>
+    }
+}
+
+impl Searcher {
+    pub fn ast(parser: &Parser, fixed: &[(char, bool)]) -> Self {
+        let session = ast::build_ast_session_fixed(parser, fixed);
+        let rows = session.cases.into_iter().zip(session.results)
+            .map(|(mut case, mut res)| {
+                case.append(&mut res);
+                case
+            })
+            .collect();
+        return Searcher::Ast {rows, num_inputs: parser.ast_order.len()};
+    }
+
+    pub fn bdd(parser: &Parser, fixed: &[(char, bool)]) -> Self {
+        let bdd = SharedBDD::new();
+        let roots = sharedbdd::build_parallel(&bdd, parser);
+        let mut domain = match parser.care.as_ref() {
+            Some(care) => bdd.build_rpn(&care.shared_rpn, &parser.defs),
+            None => 1
+        };
+        for (var, val) in fixed.iter() {
+            let var = bdd.var_edge(*var);
+            domain = bdd.apply(&Operator::AND, domain, if *val { var } else { -var });
+        }
+        return Searcher::Bdd {bdd, roots, domain};
+    }
+
+    // Expression outputs are left free and pinned by assumptions, so one solver serves every search
+    pub fn sat(parser: &Parser, fixed: &[(char, bool)]) -> Self {
+        let mut solver = Solver::new();
+        let vars = miter::input_vars(&mut solver, &parser.ast_order);
+        let inputs: Vec<Lit> = parser.ast_order.keys().map(|c| vars[c]).collect();
+        let mut tseitin = Tseitin::new();
+        let outputs: Vec<Lit> = parser.exprs.iter()
+            .map(|expr| tseitin.encode(&mut solver, &expr.shared_rpn, &parser.defs, &vars))
+            .collect();
+
+        for (c, val) in fixed.iter() {
+            if let Some(lit) = vars.get(c) {
+                solver.add_clause(&[if *val { *lit } else { -*lit }]);
+            }
+        }
+        if let Some(care) = parser.care.as_ref() {
+            let care = tseitin.encode(&mut solver, &care.shared_rpn, &parser.defs, &vars);
+            solver.add_clause(&[care]);
+        }
+        return Searcher::Sat {solver: Box::new(solver), inputs, outputs};
+    }
+
+    // Finds an input in the domain on which every listed expression has the given value
+    pub fn find(&mut self, lits: &[(usize, bool)]) -> Option<Vec<bool>> {
+        match self {
+            Searcher::Ast {rows, num_inputs} => {
+                rows.iter().find(|row| lits.iter().all(|(e, val)| row[*num_inputs + e] == *val)).cloned()
+            },
+            Searcher::Bdd {bdd, roots, domain} => {
+                let mut target = *domain;
+                for (e, val) in lits.iter() {
+                    target = bdd.apply(&Operator::AND, target, if *val { roots[*e] } else { -roots[*e] });
+                }
+                bdd.pick_one(target).map(|mut row| {
+                    let mut results: Vec<bool> = roots.iter().map(|root| bdd.evaluate(*root, &row)).collect();
+                    row.append(&mut results);
+                    row
+                })
+            },
+            Searcher::Sat {solver, inputs, outputs} => {
+                let assumptions: Vec<Lit> = lits.iter().map(|(e, val)| if *val { outputs[*e] } else { -outputs[*e] }).collect();
+                if !solver.solve_with(&assumptions) {
+                    return None;
+                }
+                Some(inputs.iter().chain(outputs.iter()).map(|lit| solver.model_value(*lit)).collect())
+            }
+        }
+    }
+}
+
+pub fn check(searcher: &mut Searcher, query: Query, expr: usize) -> Verdict {
+    match query {
+        Query::Tautology => {
+            let witness = searcher.find(&[(expr, false)]);
+            Verdict {holds: witness.is_none(), witness}
+        },
+        Query::Sat => {
+            let witness = searcher.find(&[(expr, true)]);
+            Verdict {holds: witness.is_some(), witness}
+        },
+        Query::Unsat => {
+            let witness = searcher.find(&[(expr, true)]);
+            Verdict {holds: witness.is_none(), witness}
+        }
+    }
+}
+
+// Whether lhs implies rhs, with an input where lhs holds and rhs does not when it fails
+pub fn implies(searcher: &mut Searcher, lhs: usize, rhs: usize) -> Verdict {
+    let witness = searcher.find(&[(lhs, true), (rhs, false)]);
+    return Verdict {holds: witness.is_none(), witness};
+}
+
+// lattice[i][j] is whether expression i implies expression j
+pub fn lattice(searcher: &mut Searcher, num_exprs: usize) -> Vec<Vec<Verdict>> {
+    return (0..num_exprs)
+        .map(|i| (0..num_exprs).map(|j| if i == j { Verdict {holds: true, witness: None} } else { implies(searcher, i, j) }).collect())
+        .collect();
+}
+
+// The Hasse diagram of the lattice: equivalent expressions are merged into their first member, and only the
+// implications between those that no third expression sits strictly between are kept
+pub fn covers(lattice: &[Vec<Verdict>]) -> Vec<(usize, usize)> {
+    let n = lattice.len();
+    let imp = |i: usize, j: usize| lattice[i][j].holds;
+    let rep: HashMap<usize, usize> = (0..n).map(|i| (i, (0..n).find(|j| imp(i, *j) && imp(*j, i)).unwrap())).collect();
+    let reps: Vec<usize> = (0..n).filter(|i| rep[i] == *i).collect();
+
+    let mut res = Vec::new();
+    for i in reps.iter() {
+        for j in reps.iter() {
+            if i == j || !imp(*i, *j) {
+                continue;
+            }
+            if !reps.iter().any(|k| k != i && k != j && imp(*i, *k) && imp(*k, *j)) {
+                res.push((*i, *j));
+            }
+        }
+    }
+    return res;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+
+    fn session(exprs: &[&str]) -> Parser {
+        return parser::create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+    }
+
+    fn searchers(parser: &Parser, fixed: &[(char, bool)]) -> Vec<Searcher> {
+        return vec![Searcher::ast(parser, fixed), Searcher::bdd(parser, fixed), Searcher::sat(parser, fixed)];
+    }
+
+    #[test]
+    fn classification_with_witnesses() {
+        let parser = session(&["a | ~a", "a & ~a", "a ^ b", "(a ? b : c) | ~b | ~c"]);
+        for mut searcher in searchers(&parser, &[]) {
+            let holds = |searcher: &mut Searcher, query| (0..4).map(|e| check(searcher, query, e).holds).collect::<Vec<bool>>();
+            assert_eq!(vec![true, false, false, true], holds(&mut searcher, Query::Tautology));
+            assert_eq!(vec![true, false, true, true], holds(&mut searcher, Query::Sat));
+            assert_eq!(vec![false, true, false, false], holds(&mut searcher, Query::Unsat));
+
+            // A failed tautology is falsified by its witness, and a satisfying witness satisfies
+            let verdict = check(&mut searcher, Query::Tautology, 2);
+            let row = verdict.witness.unwrap();
+            assert_eq!(3 + 4, row.len());
+            assert!(!row[3 + 2] && row[0] == row[1]);
+            assert!(check(&mut searcher, Query::Sat, 2).witness.unwrap()[3 + 2]);
+            assert_eq!(None, check(&mut searcher, Query::Sat, 1).witness);
+        }
+    }
+
+    #[test]
+    fn implication_lattice() {
+        let parser = session(&["a & b", "a", "a | b", "~~a", "b"]);
+        for mut searcher in searchers(&parser, &[]) {
+            let lattice = lattice(&mut searcher, 5);
+            assert!(lattice[0][1].holds && lattice[0][2].holds && lattice[1][3].holds && lattice[3][1].holds);
+            assert!(!lattice[2][0].holds);
+
+            let row = lattice[1][4].witness.clone().unwrap();
+            assert!(row[2 + 1] && !row[2 + 4]);
+            assert_eq!(vec![(0, 1), (0, 4), (1, 2), (4, 2)], covers(&lattice));
+        }
+    }
+
+    #[test]
+    fn fixed_inputs_and_care_narrow_the_domain() {
+        let mut parser = session(&["a & b", "a"]);
+        parser.set_care(String::from("a ^ ~b")).unwrap();
+        for mut searcher in searchers(&parser, &[]) {
+            assert!(implies(&mut searcher, 1, 0).holds);
+        }
+        for mut searcher in searchers(&parser, &[('a', true)]) {
+            assert!(check(&mut searcher, Query::Tautology, 0).holds);
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Interactive sessions: plain lines are added to the session, and lines starting with : are commands
+// Errors are reported and the session carries on, so a typo never loses the expressions entered so far
+// Edits go through session::Session, so only the expression being changed is built again
+
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use bool_eq::{parser, pretty, session};
+use bool_eq::session::Session;
+use super::{Engine, Options};
+
+const HELP: &str = "\
+Enter an expression, definition or bus assignment to add it to the session, or one of these commands:
+  :add <input>       add an input, even one starting with :
+  :rm <n>            remove expression n, as numbered by :list
+  :edit <n> <expr>   replace expression n
+  :list              list the definitions and the numbered expressions
+  :check             compare the expressions with the current engine
+  :table             print the truth table of the expressions
+  :order [vars]      print the variable ordering, or move the given variables to its front
+  :engine [name]     print the engine, or switch to ast, bdd or sat
+  :eval a=1 b=0 ...  evaluate every expression on an assignment to all of its variables
+  :save <path>       write the session to a file
+  :load <path>       add every line of a file to the session
+  :help              print this message
+  :quit              leave the session";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cmd {
+    Add(String),
+    Rm(usize),
+    Edit(usize, String),
+    List,
+    Check,
+    Table,
+    Order(Vec<char>),
+    Engine(Option<Engine>),
+    Eval(Vec<(char, bool)>),
+    Save(String),
+    Load(String),
+    Help,
+    Quit
+}
+
+pub fn run(mut options: Options) {
+    let mut editor = DefaultEditor::new().unwrap_or_else(|e| {
+        eprintln!("Failed to start the line editor: {e}");
+        std::process::exit(1);
+    });
+    let mut session = session::create_session(Vec::new()).unwrap();
+
+    println!("Boolean Formula Equivalence Checker; enter :help for a list of commands");
+    loop {
+        let line = match editor.readline("> ") {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("{e}");
+                break;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+
+        match parse_command(&line) {
+            Ok(Cmd::Quit) => break,
+            Ok(cmd) => {
+                if let Err(e) = execute(&mut session, &mut options, cmd) {
+                    eprintln!("{e}");
+                }
+            },
+            Err(e) => eprintln!("{e}")
+        }
+    }
+}
+
+fn parse_command(line: &str) -> Result<Cmd, String> {
+    let line = line.trim();
+    let (name, rest) = match line.strip_prefix(':') {
+        Some(cmd) => cmd.split_once(char::is_whitespace).map(|(n, r)| (n, r.trim())).unwrap_or((cmd, "")),
+        None => return Ok(Cmd::Add(line.to_string()))
+    };
+
+    let no_args = |cmd: Cmd| if rest.is_empty() { Ok(cmd) } else { Err(format!(":{name} takes no arguments")) };
+    match name {
+        "add" if !rest.is_empty() => Ok(Cmd::Add(rest.to_string())),
+        "add" => Err(String::from(":add expects an input")),
+        "rm" => match rest.parse::<usize>() {
+            Ok(n) if n > 0 => Ok(Cmd::Rm(n - 1)),
+            _ => Err(String::from(":rm expects an expression number from :list"))
+        },
+        "edit" => match rest.split_once(char::is_whitespace).map(|(n, e)| (n.parse::<usize>(), e.trim())) {
+            Some((Ok(n), expr)) if n > 0 => Ok(Cmd::Edit(n - 1, expr.to_string())),
+            _ => Err(String::from(":edit expects an expression number from :list and the expression replacing it"))
+        },
+        "list" => no_args(Cmd::List),
+        "check" => no_args(Cmd::Check),
+        "table" => no_args(Cmd::Table),
+        "order" => {
+            let vars: Vec<char> = rest.chars().filter(|c| !c.is_whitespace() && *c != ',').collect();
+            match vars.iter().find(|c| !c.is_ascii_alphabetic()) {
+                Some(c) => Err(format!("Invalid variable name {c} given to :order")),
+                None => Ok(Cmd::Order(vars))
+            }
+        },
+        "engine" => match rest {
+            "" => Ok(Cmd::Engine(None)),
+            "ast" => Ok(Cmd::Engine(Some(Engine::Ast))),
+            "bdd" => Ok(Cmd::Engine(Some(Engine::Bdd))),
+            "sat" => Ok(Cmd::Engine(Some(Engine::Sat))),
+            _ => Err(String::from(":engine expects one of ast, bdd or sat"))
+        },
+        "eval" => Ok(Cmd::Eval(parser::parse_assignments(rest)?)),
+        "save" | "load" if rest.is_empty() => Err(format!(":{name} expects a file path")),
+        "save" => Ok(Cmd::Save(rest.to_string())),
+        "load" => Ok(Cmd::Load(rest.to_string())),
+        "help" => no_args(Cmd::Help),
+        "quit" | "q" => no_args(Cmd::Quit),
+        _ => Err(format!("Unrecognized command :{name}; enter :help for a list of commands"))
+    }
+}
+
+fn execute(session: &mut Session, options: &mut Options, cmd: Cmd) -> Result<(), String> {
+    let input = session.parser();
+    match cmd {
+        Cmd::Add(line) => session.add_expr(vec![line])?,
+        Cmd::Rm(ndx) => println!("Removed {}", session.remove_expr(ndx)?),
+        Cmd::Edit(ndx, expr) => println!("Replaced {}", session.replace_expr(ndx, expr)?),
+        Cmd::List => {
+            for def in input.defs.iter() {
+                println!("{}", pretty::definition(def, &input.defs, pretty::Dialect::Ascii));
+            }
+            input.list_expr();
+        },
+        Cmd::Check if input.exprs.is_empty() => return Err(String::from("There are no expressions to check")),
+        Cmd::Check if !input.buses.is_empty() => super::check_buses(input, input.raw_exprs(), &mut super::searcher(input, options.engine, &[]))?,
+        Cmd::Check => match options.engine {
+            Engine::Ast => {
+                let (rows, cex) = (session.rows()?, session.cex()?);
+                let input = session.parser();
+                super::print_truth_table(input, input.raw_exprs(), &rows);
+                super::print_verdict(input, input.raw_exprs(), session.all_eq(), &cex);
+            },
+            Engine::Bdd => {
+                // Like the one-shot bdd engine, only a single failure case is reported
+                let cex: Vec<Vec<bool>> = session.pick_cex().into_iter().collect();
+                let input = session.parser();
+                super::print_verdict(input, input.raw_exprs(), session.all_eq(), &cex);
+            },
+            Engine::Sat => super::check_exprs(input, input.raw_exprs(), options)
+        },
+        Cmd::Table if input.exprs.is_empty() => return Err(String::from("There are no expressions to tabulate")),
+        Cmd::Table => {
+            let rows = session.rows()?;
+            super::print_truth_table(session.parser(), session.parser().raw_exprs(), &rows);
+        },
+        Cmd::Order(vars) => {
+            session.set_order(&vars)?;
+            println!("{}", session.parser().ast_order.keys().map(|c| c.to_string()).collect::<Vec<String>>().join(" "));
+        },
+        Cmd::Engine(Some(engine)) => options.engine = engine,
+        Cmd::Engine(None) => println!("{}", format!("{:?}", options.engine).to_lowercase()),
+        Cmd::Eval(assignment) => {
+            for (raw, res) in input.raw_exprs().iter().zip(session.evaluate(&assignment)?) {
+                println!("{raw} = {}", usize::from(res));
+            }
+        },
+        Cmd::Save(path) => {
+            let contents = super::session_lines(input, pretty::Dialect::Ascii).join("\n") + "\n";
+            std::fs::write(&path, contents).map_err(|e| format!("Failed to write {path}: {e}"))?;
+        },
+        Cmd::Load(path) => {
+            let contents = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {path}: {e}"))?;
+            session.add_expr(contents.lines().filter(|line| !line.trim().is_empty()).map(|line| line.to_string()).collect())
+                .map_err(|e| format!("Failed to load {path}: {e}"))?;
+        },
+        Cmd::Help => println!("{HELP}"),
+        Cmd::Quit => ()
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(Ok(Cmd::Add(String::from("a & b"))), parse_command("  a & b "));
+        assert_eq!(Ok(Cmd::Add(String::from(":x"))), parse_command(":add :x"));
+        assert_eq!(Ok(Cmd::Rm(1)), parse_command(":rm 2"));
+        assert_eq!(Ok(Cmd::Edit(0, String::from("a | b"))), parse_command(":edit 1  a | b"));
+        assert!(parse_command(":edit 1").is_err());
+        assert!(parse_command(":rm 0").is_err());
+        assert_eq!(Ok(Cmd::Order(vec!['c', 'a'])), parse_command(":order c, a"));
+        assert_eq!(Ok(Cmd::Engine(Some(Engine::Sat))), parse_command(":engine sat"));
+        assert_eq!(Ok(Cmd::Eval(vec![('a', true), ('b', false)])), parse_command(":eval a=1 b=0"));
+        assert!(parse_command(":list all").is_err());
+        assert!(parse_command(":frobnicate").is_err());
+    }
+
+    #[test]
+    fn session_commands() {
+        let mut options = super::super::parse_args(std::iter::once(String::from("repl"))).unwrap();
+        let mut session = session::create_session(Vec::new()).unwrap();
+        for line in ["maj = (a & b) | (c & (a | b))", "a ? b : c", ":rm 1", ":add c & d", ":order d", ":engine bdd", ":edit 1 d & c"] {
+            execute(&mut session, &mut options, parse_command(line).unwrap()).unwrap();
+        }
+        assert_eq!(vec![String::from("d & c")], session.parser().raw_exprs());
+        assert_eq!(vec!['d', 'c'], session.parser().ast_order.keys().copied().collect::<Vec<char>>());
+        assert_eq!(Engine::Bdd, options.engine);
+        assert!(execute(&mut session, &mut options, Cmd::Eval(vec![('c', true)])).is_err());
+        assert!(execute(&mut session, &mut options, Cmd::Rm(1)).is_err());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mut options = super::super::parse_args(std::iter::once(String::from("repl"))).unwrap();
+        let path = std::env::temp_dir().join(format!("bool-eq-repl-{}.txt", std::process::id())).display().to_string();
+        let mut saved = session::create_session(Vec::new()).unwrap();
+        for line in ["carry = a & b", "def mux(s, x, y) = s ? x : y", "sum[1..0] = carry | (c & (a ^ b)), mux(c, ~(a ^ b), a ^ b)", "alt[0] = a ^ b ^ c",
+            "alt[1] = mux(a, b | c, b & c)"] {
+            execute(&mut saved, &mut options, parse_command(line).unwrap()).unwrap();
+        }
+        execute(&mut saved, &mut options, Cmd::Save(path.clone())).unwrap();
+
+        let mut loaded = session::create_session(Vec::new()).unwrap();
+        execute(&mut loaded, &mut options, Cmd::Load(path.clone())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let (before, after) = (saved.parser(), loaded.parser());
+        assert_eq!(before.defs.iter().map(|def| &def.name).collect::<Vec<_>>(), after.defs.iter().map(|def| &def.name).collect::<Vec<_>>());
+        assert_eq!(before.raw_exprs(), after.raw_exprs());
+        assert_eq!(vec!["sum[1]", "sum[0]", "alt[0]", "alt[1]"], after.raw_exprs());
+        assert_eq!(before.buses.iter().map(|bus| (&bus.name, &bus.bits)).collect::<Vec<_>>(),
+            after.buses.iter().map(|bus| (&bus.name, &bus.bits)).collect::<Vec<_>>());
+        assert_eq!(super::super::session_lines(before, pretty::Dialect::Ascii), super::super::session_lines(after, pretty::Dialect::Ascii));
+        for case in 0..8 {
+            let assignment: Vec<(char, bool)> = ['a', 'b', 'c'].iter().enumerate().map(|(i, c)| (*c, case & (1 << i) != 0)).collect();
+            assert_eq!(saved.evaluate(&assignment), loaded.evaluate(&assignment));
+        }
+    }
+}
@@ -0,0 +1,699 @@
+// Conflict-driven clause learning SAT solver
+// Literals use the DIMACS convention, matching the signed edges of areabdd: variable v is v and its negation is -v
+// Internally a literal is coded as 2 * (v - 1), plus 1 when negated, so that it can index the watch lists
+//
+// The solver keeps two watched literals per clause, picks decisions by VSIDS activity with saved phases,
+// learns first-UIP clauses, restarts on the Luby sequence and halves the learnt clause database when it grows
+
+use std::mem;
+
+pub type Lit = isize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SolverStats {
+    pub decisions: usize,
+    pub propagations: usize,
+    pub conflicts: usize,
+    pub restarts: usize,
+    pub learnts: usize
+}
+
+#[derive(Debug, Clone)]
+struct Clause {
+    lits: Vec<usize>, // The first two literals are watched; a reason clause keeps its implied literal first
+    learnt: bool,
+    activity: f64,
+    deleted: bool
+}
+
+#[derive(Debug, Clone)]
+pub struct Solver {
+    clauses: Vec<Clause>,
+    watches: Vec<Vec<usize>>, // Indexed by literal code; clauses to visit when that literal becomes false
+    assigns: Vec<Option<bool>>, // Indexed by variable
+    level: Vec<usize>,
+    reason: Vec<Option<usize>>,
+    polarity: Vec<bool>, // Last value each variable held, reused when deciding on it again
+    trail: Vec<usize>,
+    trail_lim: Vec<usize>, // Start of each decision level on the trail
+    qhead: usize,
+    activity: Vec<f64>,
+    var_inc: f64,
+    clause_inc: f64,
+    heap: VarHeap,
+    seen: Vec<bool>,
+    model: Vec<bool>,
+    num_learnts: usize,
+    max_learnts: f64,
+    ok: bool, // False once the clauses are known to be unsatisfiable
+    stats: SolverStats
+}
+
+// Max-heap of unassigned variables keyed on activity
+#[derive(Debug, Clone, Default)]
+struct VarHeap {
+    heap: Vec<usize>,
+    indices: Vec<Option<usize>> // Position of each variable in heap
+}
+
+const VAR_DECAY: f64 = 0.95;
+const CLAUSE_DECAY: f64 = 0.999;
+const RESTART_BASE: usize = 100;
+
+fn code(lit: Lit) -> usize {
+    assert!(lit != 0, "0 is not a literal");
+    let var = lit.unsigned_abs() - 1;
+    return 2 * var + (lit < 0) as usize;
+}
+
+fn var_of(code: usize) -> usize {
+    return code >> 1;
+}
+
+fn negate(code: usize) -> usize {
+    return code ^ 1;
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Solver {
+            clauses: Vec::new(),
+            watches: Vec::new(),
+            assigns: Vec::new(),
+            level: Vec::new(),
+            reason: Vec::new(),
+            polarity: Vec::new(),
+            trail: Vec::new(),
+            trail_lim: Vec::new(),
+            qhead: 0,
+            activity: Vec::new(),
+            var_inc: 1.0,
+            clause_inc: 1.0,
+            heap: VarHeap::default(),
+            seen: Vec::new(),
+            model: Vec::new(),
+            num_learnts: 0,
+            max_learnts: 0.0,
+            ok: true,
+            stats: SolverStats::default()
+        }
+    }
+
+    // Creates a fresh variable and returns its positive literal
+    pub fn new_var(&mut self) -> Lit {
+        let var = self.assigns.len();
+        self.assigns.push(None);
+        self.level.push(0);
+        self.reason.push(None);
+        self.polarity.push(false);
+        self.activity.push(0.0);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        self.heap.insert(var, &self.activity);
+        return var as Lit + 1;
+    }
+
+    pub fn num_vars(&self) -> usize {
+        return self.assigns.len();
+    }
+
+    pub fn stats(&self) -> SolverStats {
+        return self.stats;
+    }
+
+    // Adds a clause over existing variables, returning false if the clauses have become unsatisfiable
+    // Clauses may be added between calls to solve
+    pub fn add_clause(&mut self, lits: &[Lit]) -> bool {
+        if !self.ok {
+            return false;
+        }
+
+        let mut codes: Vec<usize> = Vec::with_capacity(lits.len());
+        for lit in lits.iter() {
+            let lit = code(*lit);
+            assert!(var_of(lit) < self.num_vars(), "Clause refers to an unknown variable");
+            match self.value(lit) {
+                Some(true) => return true, // Already satisfied at the top level
+                Some(false) => continue,
+                None => {
+                    if codes.contains(&negate(lit)) {
+                        return true; // Tautology
+                    } else if !codes.contains(&lit) {
+                        codes.push(lit);
+                    }
+                }
+            }
+        }
+
+        if codes.is_empty() {
+            self.ok = false;
+        } else if codes.len() == 1 {
+            self.enqueue(codes[0], None);
+            self.ok = self.propagate().is_none();
+        } else {
+            self.attach(codes, false);
+        }
+
+        return self.ok;
+    }
+
+    // Searches for a model, returning whether one exists
+    // The solver is back at the top level afterwards, ready for more clauses
+    pub fn solve(&mut self) -> bool {
+        return self.solve_with(&[]);
+    }
+
+    // Searches for a model in which every assumption holds
+    // Unlike clauses, assumptions only last for this call, so failing under them leaves the solver usable
+    pub fn solve_with(&mut self, assumptions: &[Lit]) -> bool {
+        if !self.ok {
+            return false;
+        }
+        let assumptions: Vec<usize> = assumptions.iter().map(|lit| code(*lit)).collect();
+
+        self.max_learnts = self.max_learnts.max(self.clauses.len() as f64 / 3.0 + 1000.0);
+        let mut restart: u32 = 0;
+        loop {
+            let budget = luby(restart) * RESTART_BASE;
+            match self.search(budget, &assumptions) {
+                Some(sat) => {
+                    self.cancel_until(0);
+                    return sat;
+                },
+                None => {
+                    restart += 1;
+                    self.stats.restarts += 1;
+                    self.max_learnts *= 1.1;
+                }
+            }
+        }
+    }
+
+    // Value of a literal in the last model found
+    pub fn model_value(&self, lit: Lit) -> bool {
+        let lit = code(lit);
+        return self.model[var_of(lit)] ^ (lit & 1 == 1);
+    }
+
+    pub fn model(&self) -> &[bool] {
+        return &self.model;
+    }
+
+    // Runs CDCL until a verdict, or gives up after max_conflicts so the caller can restart
+    // The assumptions are decided first, one per decision level
+    fn search(&mut self, max_conflicts: usize, assumptions: &[usize]) -> Option<bool> {
+        let mut conflicts = 0;
+        loop {
+            if let Some(confl) = self.propagate() {
+                self.stats.conflicts += 1;
+                conflicts += 1;
+                if self.trail_lim.is_empty() {
+                    self.ok = false;
+                    return Some(false);
+                }
+
+                let (learnt, bt_level) = self.analyze(confl);
+                self.cancel_until(bt_level);
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let implied = learnt[0];
+                    let cref = self.attach(learnt, true);
+                    self.bump_clause(cref);
+                    self.enqueue(implied, Some(cref));
+                }
+
+                self.var_inc /= VAR_DECAY;
+                self.clause_inc /= CLAUSE_DECAY;
+            } else {
+                if conflicts >= max_conflicts {
+                    self.cancel_until(0);
+                    return None;
+                }
+
+                if self.num_learnts as f64 - self.trail.len() as f64 >= self.max_learnts {
+                    self.reduce_db();
+                }
+
+                let mut next = None;
+                while self.decision_level() < assumptions.len() {
+                    let lit = assumptions[self.decision_level()];
+                    match self.value(lit) {
+                        Some(true) => self.trail_lim.push(self.trail.len()), // Already holds; open an empty level
+                        Some(false) => return Some(false),
+                        None => {
+                            next = Some(lit);
+                            break;
+                        }
+                    }
+                }
+
+                match next.or_else(|| self.pick_branch()) {
+                    None => {
+                        self.model = self.assigns.iter().map(|val| val.unwrap()).collect();
+                        return Some(true);
+                    },
+                    Some(lit) => {
+                        self.stats.decisions += 1;
+                        self.trail_lim.push(self.trail.len());
+                        self.enqueue(lit, None);
+                    }
+                }
+            }
+        }
+    }
+
+    fn value(&self, lit: usize) -> Option<bool> {
+        return self.assigns[var_of(lit)].map(|val| val ^ (lit & 1 == 1));
+    }
+
+    fn decision_level(&self) -> usize {
+        return self.trail_lim.len();
+    }
+
+    fn enqueue(&mut self, lit: usize, reason: Option<usize>) {
+        let var = var_of(lit);
+        self.assigns[var] = Some(lit & 1 == 0);
+        self.level[var] = self.decision_level();
+        self.reason[var] = reason;
+        self.trail.push(lit);
+    }
+
+    fn attach(&mut self, lits: Vec<usize>, learnt: bool) -> usize {
+        let cref = self.clauses.len();
+        self.watches[lits[0]].push(cref);
+        self.watches[lits[1]].push(cref);
+        self.clauses.push(Clause {lits, learnt, activity: 0.0, deleted: false});
+        if learnt {
+            self.num_learnts += 1;
+            self.stats.learnts += 1;
+        }
+        return cref;
+    }
+
+    // Unit propagation over the watch lists; returns the conflicting clause if there is one
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let false_lit = negate(self.trail[self.qhead]);
+            self.qhead += 1;
+            self.stats.propagations += 1;
+
+            // Clauses that keep watching false_lit are compacted to the front of the list
+            let mut watching = mem::take(&mut self.watches[false_lit]);
+            let mut kept = 0;
+            let mut conflict = None;
+
+            for ndx in 0..watching.len() {
+                let cref = watching[ndx];
+                if conflict.is_some() {
+                    watching[kept] = cref;
+                    kept += 1;
+                    continue;
+                }
+
+                // Keep the false literal in the second watch
+                let lits = &mut self.clauses[cref].lits;
+                if lits[0] == false_lit {
+                    lits.swap(0, 1);
+                }
+
+                let first = lits[0];
+                if self.assigns[var_of(first)].map(|val| val ^ (first & 1 == 1)) == Some(true) {
+                    watching[kept] = cref;
+                    kept += 1;
+                    continue;
+                }
+
+                // Look for another literal that is not false to watch instead
+                let replacement = (2..lits.len()).find(|k| {
+                    let lit = lits[*k];
+                    self.assigns[var_of(lit)].map(|val| val ^ (lit & 1 == 1)) != Some(false)
+                });
+                if let Some(k) = replacement {
+                    lits.swap(1, k);
+                    let watch = lits[1];
+                    self.watches[watch].push(cref);
+                    continue;
+                }
+
+                watching[kept] = cref;
+                kept += 1;
+                if self.value(first) == Some(false) {
+                    conflict = Some(cref);
+                } else {
+                    self.enqueue(first, Some(cref));
+                }
+            }
+
+            watching.truncate(kept);
+            self.watches[false_lit] = watching;
+            if conflict.is_some() {
+                self.qhead = self.trail.len();
+                return conflict;
+            }
+        }
+
+        return None;
+    }
+
+    // First-UIP conflict analysis; returns the learnt clause, asserting literal first, and the level to jump back to
+    fn analyze(&mut self, mut confl: usize) -> (Vec<usize>, usize) {
+        let mut learnt: Vec<usize> = vec![0]; // Slot for the asserting literal
+        let mut path_count = 0;
+        let mut implied: Option<usize> = None;
+        let mut ndx = self.trail.len();
+
+        loop {
+            if self.clauses[confl].learnt {
+                self.bump_clause(confl);
+            }
+
+            // A reason clause's first literal is the one it implied, which has already been resolved on
+            let skip = if implied.is_some() { 1 } else { 0 };
+            for k in skip..self.clauses[confl].lits.len() {
+                let lit = self.clauses[confl].lits[k];
+                let var = var_of(lit);
+                if !self.seen[var] && self.level[var] > 0 {
+                    self.seen[var] = true;
+                    self.bump_var(var);
+                    if self.level[var] == self.decision_level() {
+                        path_count += 1;
+                    } else {
+                        learnt.push(lit);
+                    }
+                }
+            }
+
+            // Walk back along the trail to the next literal involved in the conflict
+            loop {
+                ndx -= 1;
+                if self.seen[var_of(self.trail[ndx])] {
+                    break;
+                }
+            }
+            let lit = self.trail[ndx];
+            self.seen[var_of(lit)] = false;
+            implied = Some(lit);
+            path_count -= 1;
+            if path_count == 0 {
+                break;
+            }
+            confl = self.reason[var_of(lit)].expect("Implied literal without a reason");
+        }
+        learnt[0] = negate(implied.unwrap());
+
+        // Drop literals whose reason is made up of literals already in the clause
+        let minimized: Vec<usize> = learnt.iter().enumerate()
+            .filter(|(k, lit)| *k == 0 || !self.redundant(**lit))
+            .map(|(_, lit)| *lit)
+            .collect();
+        for lit in learnt.iter() {
+            self.seen[var_of(*lit)] = false;
+        }
+        let mut learnt = minimized;
+
+        // The second watch goes to the literal from the highest remaining level
+        let mut bt_level = 0;
+        if learnt.len() > 1 {
+            let max_ndx = (1..learnt.len()).max_by_key(|k| self.level[var_of(learnt[*k])]).unwrap();
+            learnt.swap(1, max_ndx);
+            bt_level = self.level[var_of(learnt[1])];
+        }
+
+        return (learnt, bt_level);
+    }
+
+    fn redundant(&self, lit: usize) -> bool {
+        match self.reason[var_of(lit)] {
+            None => false,
+            Some(cref) => self.clauses[cref].lits[1..].iter()
+                .all(|other| self.seen[var_of(*other)] || self.level[var_of(*other)] == 0)
+        }
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+
+        let start = self.trail_lim[level];
+        for ndx in (start..self.trail.len()).rev() {
+            let var = var_of(self.trail[ndx]);
+            self.polarity[var] = self.assigns[var].unwrap();
+            self.assigns[var] = None;
+            self.reason[var] = None;
+            self.heap.insert(var, &self.activity);
+        }
+
+        self.trail.truncate(start);
+        self.trail_lim.truncate(level);
+        self.qhead = start;
+    }
+
+    fn pick_branch(&mut self) -> Option<usize> {
+        while let Some(var) = self.heap.pop(&self.activity) {
+            if self.assigns[var].is_none() {
+                return Some(2 * var + (!self.polarity[var]) as usize);
+            }
+        }
+
+        return None;
+    }
+
+    fn bump_var(&mut self, var: usize) {
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100 {
+            for act in self.activity.iter_mut() {
+                *act *= 1e-100;
+            }
+            self.var_inc *= 1e-100;
+        }
+        self.heap.increase(var, &self.activity);
+    }
+
+    fn bump_clause(&mut self, cref: usize) {
+        self.clauses[cref].activity += self.clause_inc;
+        if self.clauses[cref].activity > 1e20 {
+            for clause in self.clauses.iter_mut().filter(|c| c.learnt) {
+                clause.activity *= 1e-20;
+            }
+            self.clause_inc *= 1e-20;
+        }
+    }
+
+    // Deletes the less active half of the learnt clauses, keeping binary clauses and current reasons
+    fn reduce_db(&mut self) {
+        let mut learnts: Vec<usize> = (0..self.clauses.len())
+            .filter(|cref| self.clauses[*cref].learnt && !self.clauses[*cref].deleted)
+            .collect();
+        learnts.sort_by(|a, b| self.clauses[*a].activity.total_cmp(&self.clauses[*b].activity));
+
+        for cref in learnts[..learnts.len() / 2].iter() {
+            let first = self.clauses[*cref].lits[0];
+            let locked = self.reason[var_of(first)] == Some(*cref) && self.value(first) == Some(true);
+            if self.clauses[*cref].lits.len() > 2 && !locked {
+                self.clauses[*cref].deleted = true;
+                self.clauses[*cref].lits = Vec::new();
+                self.num_learnts -= 1;
+            }
+        }
+
+        let clauses = &self.clauses;
+        for watch in self.watches.iter_mut() {
+            watch.retain(|cref| !clauses[*cref].deleted);
+        }
+    }
+}
+
+impl Default for Solver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VarHeap {
+    fn insert(&mut self, var: usize, activity: &[f64]) {
+        if self.indices.len() <= var {
+            self.indices.resize(var + 1, None);
+        }
+        if self.indices[var].is_some() {
+            return;
+        }
+
+        self.indices[var] = Some(self.heap.len());
+        self.heap.push(var);
+        self.sift_up(self.heap.len() - 1, activity);
+    }
+
+    fn increase(&mut self, var: usize, activity: &[f64]) {
+        if let Some(ndx) = self.indices[var] {
+            self.sift_up(ndx, activity);
+        }
+    }
+
+    fn pop(&mut self, activity: &[f64]) -> Option<usize> {
+        if self.heap.is_empty() {
+            return None;
+        }
+
+        let top = self.heap.swap_remove(0);
+        self.indices[top] = None;
+        if !self.heap.is_empty() {
+            self.indices[self.heap[0]] = Some(0);
+            self.sift_down(0, activity);
+        }
+        return Some(top);
+    }
+
+    fn sift_up(&mut self, mut ndx: usize, activity: &[f64]) {
+        while ndx > 0 {
+            let parent = (ndx - 1) / 2;
+            if activity[self.heap[parent]] >= activity[self.heap[ndx]] {
+                break;
+            }
+            self.swap(ndx, parent);
+            ndx = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut ndx: usize, activity: &[f64]) {
+        loop {
+            let (left, right) = (2 * ndx + 1, 2 * ndx + 2);
+            let mut largest = ndx;
+            if left < self.heap.len() && activity[self.heap[left]] > activity[self.heap[largest]] {
+                largest = left;
+            }
+            if right < self.heap.len() && activity[self.heap[right]] > activity[self.heap[largest]] {
+                largest = right;
+            }
+            if largest == ndx {
+                break;
+            }
+            self.swap(ndx, largest);
+            ndx = largest;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        self.indices[self.heap[a]] = Some(a);
+        self.indices[self.heap[b]] = Some(b);
+    }
+}
+
+// Luby restart sequence: 1, 1, 2, 1, 1, 2, 4, 1, 1, 2, ...
+fn luby(mut ndx: u32) -> usize {
+    let mut size: u32 = 1;
+    let mut seq: u32 = 0;
+    while size < ndx + 1 {
+        seq += 1;
+        size = 2 * size + 1;
+    }
+
+    while size - 1 != ndx {
+        size = (size - 1) >> 1;
+        seq -= 1;
+        ndx %= size;
+    }
+
+    return 1 << seq;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solver_with(num_vars: usize, clauses: &[&[Lit]]) -> Solver {
+        let mut solver = Solver::new();
+        for _ in 0..num_vars {
+            solver.new_var();
+        }
+        for clause in clauses.iter() {
+            solver.add_clause(clause);
+        }
+        return solver;
+    }
+
+    // Variable for pigeon p sitting in hole h
+    fn pigeonhole(pigeons: usize, holes: usize) -> Solver {
+        let var = |p: usize, h: usize| (p * holes + h + 1) as Lit;
+        let mut solver = solver_with(pigeons * holes, &[]);
+        for p in 0..pigeons {
+            let clause: Vec<Lit> = (0..holes).map(|h| var(p, h)).collect();
+            solver.add_clause(&clause);
+        }
+        for h in 0..holes {
+            for p in 0..pigeons {
+                for q in p + 1..pigeons {
+                    solver.add_clause(&[-var(p, h), -var(q, h)]);
+                }
+            }
+        }
+        return solver;
+    }
+
+    #[test]
+    fn luby_sequence() {
+        let seq: Vec<usize> = (0..15).map(luby).collect();
+        assert_eq!(vec![1, 1, 2, 1, 1, 2, 4, 1, 1, 2, 1, 1, 2, 4, 8], seq);
+    }
+
+    #[test]
+    fn satisfiable_model_satisfies_clauses() {
+        let clauses: &[&[Lit]] = &[&[1, 2, -3], &[-1, 3], &[-2, 3, 4], &[-4, -1], &[2, 4], &[-3, -2, 1]];
+        let mut solver = solver_with(4, clauses);
+        assert!(solver.solve());
+        for clause in clauses.iter() {
+            assert!(clause.iter().any(|lit| solver.model_value(*lit)));
+        }
+    }
+
+    #[test]
+    fn unsatisfiable_instances() {
+        let mut solver = solver_with(2, &[&[1, 2], &[-1, 2], &[1, -2], &[-1, -2]]);
+        assert!(!solver.solve());
+        assert!(!solver.add_clause(&[1]));
+
+        let mut solver = solver_with(1, &[&[1]]);
+        assert!(!solver.add_clause(&[-1]));
+
+        // Needs real clause learning rather than a lucky assignment
+        let mut solver = pigeonhole(6, 5);
+        assert!(!solver.solve());
+        assert!(solver.stats().learnts > 0);
+        assert!(pigeonhole(5, 5).solve());
+    }
+
+    #[test]
+    fn assumptions_are_temporary() {
+        let mut solver = solver_with(3, &[&[-1, 2], &[-2, 3]]);
+        assert!(!solver.solve_with(&[1, -3]));
+        assert!(solver.solve_with(&[1]));
+        assert!(solver.model_value(3));
+        assert!(solver.solve_with(&[-3]));
+        assert!(!solver.model_value(1));
+
+        // A failed call leaves the clauses satisfiable
+        assert!(solver.solve());
+        assert!(!pigeonhole(4, 3).solve_with(&[1]));
+    }
+
+    #[test]
+    fn incremental_blocking_clauses() {
+        // Enumerate the models of a | b by blocking each one in turn
+        let mut solver = solver_with(2, &[&[1, 2]]);
+        let mut models = Vec::new();
+        while solver.solve() {
+            let model = solver.model().to_vec();
+            let block: Vec<Lit> = model.iter().enumerate()
+                .map(|(v, val)| if *val { -(v as Lit + 1) } else { v as Lit + 1 })
+                .collect();
+            models.push(model);
+            solver.add_clause(&block);
+        }
+
+        models.sort();
+        assert_eq!(vec![vec![false, true], vec![true, false], vec![true, true]], models);
+    }
+}
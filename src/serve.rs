@@ -0,0 +1,379 @@
+// A local HTTP server answering JSON requests, for front-ends that use this crate as their backend
+// Every endpoint takes a POST whose body is an object holding the session's inputs, {"exprs": ["a & b", ...]}, and
+// the options of the matching command. Only localhost is served, one thread per connection, and each connection
+// carries a single request. Bodies past the size limit are refused unread, and the whole request has to arrive within
+// the time limit. Sessions past the bounds on variables and definitions are refused before anything is built.
+// A request still running at the time limit is answered with an error; its work cannot be interrupted, so it
+// finishes in the background and keeps its worker until then. Connections and workers are capped, and requests
+// past either cap are answered with an error straight away. For tests, SERVE_DELAY_MS makes every worker wait that
+// many milliseconds before starting, so that a request is sure to outlast the time limit.
+
+use std::env;
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+use bool_eq::{areabdd, ast, bddio, bus, parser, pretty};
+use bool_eq::json::{self, json_list, json_string, Value};
+use super::{Engine, Options};
+
+const DEFAULT_PORT: u16 = 7878;
+const DEFAULT_MAX_BODY: usize = 64 * 1024;
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADER: usize = 8 * 1024;
+const MAX_TABLE_VARS: usize = 16; // Truth tables are sent whole, so they stay at 65536 rows or fewer
+const MAX_CONNECTIONS: usize = 64;
+const MAX_VARS: usize = 32;
+const MAX_DEFINITION_DEPTH: usize = 64; // Builders recurse once per level of definitions
+const MAX_EXPANDED_SIZE: usize = 1 << 20; // The ast engine walks every expression as if it were written out
+
+const ENDPOINTS: [&str; 5] = ["/parse", "/check", "/table", "/minimize", "/bdd"];
+
+struct Response {
+    status: u16,
+    body: String
+}
+
+impl Response {
+    fn error(status: u16, message: &str) -> Self {
+        Response {status, body: format!("{{\"error\": {}}}", json_string(message))}
+    }
+}
+
+// Counts the threads of one kind that are running, up to a limit
+#[derive(Clone)]
+struct Slots {
+    used: Arc<AtomicUsize>,
+    limit: usize
+}
+
+// One running thread's place in its Slots, given back when dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Slots {
+    fn new(limit: usize) -> Self {
+        Slots {used: Arc::new(AtomicUsize::new(0)), limit}
+    }
+
+    fn acquire(&self) -> Option<Slot> {
+        self.used.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| if used < self.limit { Some(used + 1) } else { None }).ok()?;
+        Some(Slot(self.used.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Clone)]
+struct Limits {
+    max_body: usize,
+    timeout: Duration,
+    workers: Slots
+}
+
+pub fn run(options: &Options) {
+    let listener = TcpListener::bind(("127.0.0.1", options.port.unwrap_or(DEFAULT_PORT))).unwrap_or_else(|e| {
+        eprintln!("Failed to listen on localhost: {e}");
+        std::process::exit(1);
+    });
+    let workers = options.workers.unwrap_or_else(|| thread::available_parallelism().map(|n| n.get()).unwrap_or(4));
+    let limits = Limits {
+        max_body: options.max_body.unwrap_or(DEFAULT_MAX_BODY),
+        timeout: options.timeout.unwrap_or(DEFAULT_TIMEOUT),
+        workers: Slots::new(workers)
+    };
+    let connections = Slots::new(MAX_CONNECTIONS);
+
+    // Port 0 picks a free port, so the address is only known once bound
+    match listener.local_addr() {
+        Ok(addr) => println!("Listening on http://{addr}"),
+        Err(e) => eprintln!("Listening on an unknown address: {e}")
+    }
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => match connections.acquire() {
+                Some(slot) => {
+                    let limits = limits.clone();
+                    thread::spawn(move || {
+                        handle(stream, &limits);
+                        drop(slot);
+                    });
+                },
+                // The request is never read, so the answer is all that is sent
+                None => {
+                    let _ = stream.set_write_timeout(Some(limits.timeout));
+                    send(&stream, &Response::error(503, &format!("The server is busy with {MAX_CONNECTIONS} connections")));
+                }
+            },
+            Err(e) => eprintln!("Failed to accept a connection: {e}")
+        }
+    }
+}
+
+fn handle(stream: TcpStream, limits: &Limits) {
+    let response = match read_request(&stream, limits.max_body, Instant::now() + limits.timeout) {
+        Ok((method, path, body)) => respond(method, path, body, limits),
+        Err(response) => response
+    };
+    send(&stream, &response);
+
+    // Closing with a refused body still unread would reset the connection before the client sees the error
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = stream.set_read_timeout(Some(limits.timeout));
+    let _ = std::io::copy(&mut stream.take((limits.max_body + MAX_HEADER) as u64), &mut std::io::sink());
+}
+
+fn send(mut stream: &TcpStream, response: &Response) {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error"
+    };
+    let head = format!("HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status, response.body.len());
+    let _ = stream.write_all(head.as_bytes()).and_then(|_| stream.write_all(response.body.as_bytes()));
+}
+
+// Reads into buf, giving up once the deadline has passed however steadily the client sends
+fn read_before(mut stream: &TcpStream, buf: &mut [u8], deadline: Instant) -> Result<usize, Response> {
+    let timed_out = || Response::error(408, "Timed out reading the request");
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(timed_out());
+    }
+
+    let _ = stream.set_read_timeout(Some(remaining));
+    stream.read(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => timed_out(),
+        _ => Response::error(400, &format!("Failed to read the request: {e}"))
+    })
+}
+
+// Returns the method, path and body, or the error response to send instead
+fn read_request(stream: &TcpStream, max_body: usize, deadline: Instant) -> Result<(String, String, String), Response> {
+    let mut data: Vec<u8> = Vec::new();
+    let mut buf = [0; 4096];
+    let header_end = loop {
+        if let Some(end) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break end;
+        } else if data.len() > MAX_HEADER {
+            return Err(Response::error(431, &format!("Request headers are longer than {MAX_HEADER} bytes")));
+        }
+        match read_before(stream, &mut buf, deadline)? {
+            0 => return Err(Response::error(400, "The request ended before its headers did")),
+            n => data.extend_from_slice(&buf[..n])
+        }
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or("").split_whitespace();
+    let (method, path) = match (request_line.next(), request_line.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(Response::error(400, "Malformed request line"))
+    };
+
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+        .map(|(_, value)| value.trim().parse::<usize>());
+    let length = match content_length {
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Err(Response::error(400, "Invalid Content-Length")),
+        None if method == "POST" => return Err(Response::error(411, "Requests need a Content-Length")),
+        None => 0
+    };
+    if length > max_body {
+        return Err(Response::error(413, &format!("Request bodies are limited to {max_body} bytes")));
+    }
+
+    let mut body = data.split_off(header_end + 4);
+    body.truncate(length);
+    while body.len() < length {
+        let want = (length - body.len()).min(buf.len());
+        match read_before(stream, &mut buf[..want], deadline)? {
+            0 => return Err(Response::error(400, "The request ended before its body did")),
+            n => body.extend_from_slice(&buf[..n])
+        }
+    }
+    let body = String::from_utf8(body).map_err(|_| Response::error(400, "The request body is not UTF-8"))?;
+    Ok((method, path, body))
+}
+
+// The work runs on its own thread, so that the time limit holds however long it takes
+// The thread keeps its worker slot until the work is done, even once the client has been answered
+fn respond(method: String, path: String, body: String, limits: &Limits) -> Response {
+    if !ENDPOINTS.contains(&path.as_str()) {
+        return Response::error(404, &format!("Unknown endpoint {path}; try one of {}", ENDPOINTS.join(", ")));
+    } else if method != "POST" {
+        return Response::error(405, &format!("{path} only accepts POST"));
+    }
+    let slot = match limits.workers.acquire() {
+        Some(slot) => slot,
+        None => return Response::error(503, &format!("All {} workers are busy; try again later", limits.workers.limit))
+    };
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        if let Some(delay) = env::var("SERVE_DELAY_MS").ok().and_then(|ms| ms.parse().ok()) {
+            thread::sleep(Duration::from_millis(delay));
+        }
+        let res = json::parse(&body).and_then(|request| route(&path, &request));
+        // Given back before the answer, so a client sending its next request never finds its own worker still busy
+        drop(slot);
+        let _ = tx.send(res);
+    });
+    match rx.recv_timeout(limits.timeout) {
+        Ok(Ok(body)) => Response {status: 200, body},
+        Ok(Err(e)) => Response::error(400, &e),
+        Err(mpsc::RecvTimeoutError::Timeout) => Response::error(503, &format!("The request took longer than {:?}", limits.timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Response::error(500, "The request failed unexpectedly")
+    }
+}
+
+fn route(path: &str, request: &Value) -> Result<String, String> {
+    let input = session(request)?;
+    let fixed = match request.get("fix") {
+        Some(fix) => parser::parse_assignments(fix.as_str().ok_or("fix must be a string such as \"a=1,b=0\"")?)?,
+        None => Vec::new()
+    };
+    if let Some((var, _)) = fixed.iter().find(|(var, _)| !input.ast_order.contains_key(var)) {
+        return Err(format!("Variable {var} given to fix does not appear in any expression"));
+    }
+
+    match path {
+        "/parse" => Ok(parse(&input)),
+        "/check" => check(&input, request, &fixed),
+        "/table" => table(&input, &fixed),
+        "/minimize" => minimize(&input, request),
+        _ => export(&input, request)
+    }
+}
+
+// Sessions are read from "exprs", the lines of an interactive session, and "assume", as for --assume
+fn session(request: &Value) -> Result<parser::Parser, String> {
+    let exprs = request.get("exprs").and_then(|exprs| exprs.as_array()).ok_or("Requests need an exprs array of strings")?;
+    let exprs = exprs.iter()
+        .map(|expr| expr.as_str().map(|expr| expr.to_string()).ok_or(String::from("exprs may only hold strings")))
+        .collect::<Result<Vec<String>, String>>()?;
+    let mut input = parser::create_session(exprs)?;
+    if input.exprs.is_empty() {
+        return Err(String::from("Requests need at least one expression"));
+    }
+
+    if let Some(care) = request.get("assume") {
+        let care = care.as_str().ok_or("assume must be an expression")?;
+        input.set_care(care.to_string()).map_err(|e| format!("Invalid assumption: {e}"))?;
+    }
+
+    // Parsing never writes definitions out, so these are all known before any engine builds the session
+    if input.ast_order.len() > MAX_VARS {
+        return Err(format!("Sessions are limited to {MAX_VARS} variables"));
+    } else if input.definition_depth() > MAX_DEFINITION_DEPTH {
+        return Err(format!("Definitions may only be nested {MAX_DEFINITION_DEPTH} deep"));
+    } else if input.expanded_size() > MAX_EXPANDED_SIZE {
+        return Err(format!("Expressions would be longer than {MAX_EXPANDED_SIZE} tokens with their definitions written out"));
+    }
+    Ok(input)
+}
+
+fn names(input: &parser::Parser) -> String {
+    json_list(input.ast_order.keys().map(|c| json_string(&c.to_string())))
+}
+
+fn labels(input: &parser::Parser) -> String {
+    json_list(input.raw_exprs().iter().map(|e| json_string(e)))
+}
+
+// A failure case or witness row as the inputs by name, then every expression's result
+fn row_json(input: &parser::Parser, row: &[bool]) -> String {
+    let (inputs, results) = row.split_at(input.ast_order.len());
+    let inputs: Vec<String> = input.ast_order.keys().zip(inputs.iter())
+        .map(|(c, val)| format!("{}: {val}", json_string(&c.to_string())))
+        .collect();
+    format!("{{\"inputs\": {{{}}}, \"results\": {}}}", inputs.join(", "), json_list(results.iter()))
+}
+
+fn parse(input: &parser::Parser) -> String {
+    let dialect = pretty::Dialect::Ascii;
+    let defs = input.defs.iter().map(|def| json_string(&pretty::definition(def, &input.defs, dialect)));
+    let formatted = input.exprs.iter()
+        .map(|expr| json_string(&pretty::Expr::from_shared_rpn(&expr.shared_rpn, &input.defs, &[]).display(dialect).to_string()));
+    format!("{{\"variables\": {}, \"definitions\": {}, \"expressions\": {}, \"formatted\": {}}}",
+        names(input), json_list(defs), labels(input), json_list(formatted))
+}
+
+// Takes "engine" as --engine does; sessions with buses are compared bit by bit
+fn check(input: &parser::Parser, request: &Value, fixed: &[(char, bool)]) -> Result<String, String> {
+    let engine = match request.get("engine").map(|engine| engine.as_str()) {
+        None | Some(Some("ast")) => Engine::Ast,
+        Some(Some("bdd")) => Engine::Bdd,
+        Some(Some("sat")) => Engine::Sat,
+        _ => return Err(String::from("engine must be one of ast, bdd or sat"))
+    };
+    if engine == Engine::Ast && input.ast_order.len() > MAX_TABLE_VARS {
+        return Err(format!("The ast engine is limited to {MAX_TABLE_VARS} variables here; use bdd or sat"));
+    }
+
+    if !input.buses.is_empty() {
+        let verdicts = bus::compare(input, &mut super::searcher(input, engine, fixed))?;
+        let bits: Vec<String> = verdicts.iter().map(|v| {
+            let witness = v.witness.as_ref().map(|row| row_json(input, row)).unwrap_or(String::from("null"));
+            format!("{{\"bus\": {}, \"bit\": {}, \"equal\": {}, \"counterexample\": {witness}}}",
+                json_string(&input.buses[v.bus].name), v.bit, v.equal)
+        }).collect();
+        return Ok(format!("{{\"expressions\": {}, \"all_eq\": {}, \"bits\": [{}]}}",
+            labels(input), verdicts.iter().all(|v| v.equal), bits.join(", ")));
+    }
+
+    let (all_eq, cex) = super::compare_exprs(input, engine, fixed);
+    let cex = cex.iter().map(|row| row_json(input, row));
+    Ok(format!("{{\"variables\": {}, \"expressions\": {}, \"all_eq\": {all_eq}, \"counterexamples\": {}}}",
+        names(input), labels(input), json_list(cex)))
+}
+
+fn table(input: &parser::Parser, fixed: &[(char, bool)]) -> Result<String, String> {
+    if input.ast_order.len() > MAX_TABLE_VARS {
+        return Err(format!("Truth tables are limited to {MAX_TABLE_VARS} variables"));
+    }
+
+    let session = ast::build_ast_session_fixed(input, fixed);
+    let rows = session.cases.iter().zip(session.results.iter())
+        .map(|(case, res)| json_list(case.iter().chain(res.iter()).map(|b| usize::from(*b))));
+    Ok(format!("{{\"variables\": {}, \"expressions\": {}, \"rows\": {}}}", names(input), labels(input), json_list(rows)))
+}
+
+// Takes "shared" and "heuristic" as the minimize command does
+fn minimize(input: &parser::Parser, request: &Value) -> Result<String, String> {
+    let flag = |name: &str| match request.get(name) {
+        None => Ok(false),
+        Some(value) => value.as_bool().ok_or(format!("{name} must be true or false"))
+    };
+    let minimized = super::minimize_session(input, flag("shared")?, flag("heuristic")?)?;
+    Ok(format!("{{\"expressions\": {}, \"minimized\": {}}}", labels(input), json_list(minimized.iter().map(|e| json_string(e)))))
+}
+
+// Takes "format", either "dot" for Graphviz or "dddmp" for the text format --save writes
+fn export(input: &parser::Parser, request: &Value) -> Result<String, String> {
+    let format = request.get("format").map(|format| format.as_str().ok_or("format must be dot or dddmp")).transpose()?;
+    let bdd = areabdd::build(areabdd::BDD::new(), input);
+    let roots = super::named_roots(&bdd, input.raw_exprs());
+    let (format, data) = match format {
+        None | Some("dot") => ("dot", areabdd::to_dot(&bdd, &roots)),
+        Some("dddmp") => ("dddmp", bddio::save_text(&bdd, &roots)),
+        Some(_) => return Err(String::from("format must be dot or dddmp"))
+    };
+    Ok(format!("{{\"format\": {}, \"data\": {}}}", json_string(format), json_string(&data)))
+}
@@ -0,0 +1,255 @@
+// Sessions that are edited in place instead of rebuilt from scratch
+// Every expression keeps its root in one BDD, and its column of the truth table once a table has been asked for.
+// Adding, replacing or removing an expression only builds that expression, and its column waits until the next
+// table; the other columns are carried over, since a column does not depend on variables its expression never uses,
+// and a replaced or removed root gives its references back. Columns are only kept up to MAX_TABLE_VARS variables,
+// but the verdict and a failure case can always be read off the BDD instead.
+// Comparisons run over every input: care sets and fixed inputs are left to the one-shot engines.
+
+use crate::areabdd::{self, BDD};
+use crate::ast;
+use crate::parser::{self, Operator, Parser};
+
+// Sessions with more variables than this have no truth table
+pub const MAX_TABLE_VARS: usize = 20;
+
+pub struct Session {
+    parser: Parser,
+    bdd: BDD, // One root per expression, in the same order
+    columns: Vec<Option<Vec<bool>>> // Indexed by expression, then by row of the truth table over the parser's ordering
+}
+
+impl Session {
+    pub fn new(parser: Parser) -> Self {
+        let bdd = areabdd::build(BDD::new(), &parser);
+        let columns = vec![None; parser.exprs.len()];
+        return Session {parser, bdd, columns};
+    }
+
+    pub fn parser(&self) -> &Parser {
+        return &self.parser;
+    }
+
+    pub fn bdd(&self) -> &BDD {
+        return &self.bdd;
+    }
+
+    // Takes the same inputs as Parser::add_expr, so a line may add a definition or several bus bits
+    pub fn add_expr(&mut self, raw_inputs: Vec<String>) -> Result<(), String> {
+        let order = self.order();
+        let num_exprs = self.parser.exprs.len();
+        self.parser.add_expr(raw_inputs)?;
+
+        self.remap(&order);
+        for ndx in num_exprs..self.parser.exprs.len() {
+            areabdd::add_root(&mut self.bdd, &self.parser, ndx);
+            self.columns.push(None);
+        }
+        return Ok(());
+    }
+
+    pub fn replace_expr(&mut self, ndx: usize, raw_input: String) -> Result<String, String> {
+        let order = self.order();
+        let old = self.parser.replace_expr(ndx, raw_input)?;
+
+        self.remap(&order);
+        areabdd::replace_root(&mut self.bdd, &self.parser, ndx);
+        self.columns[ndx] = None;
+        return Ok(old);
+    }
+
+    pub fn remove_expr(&mut self, ndx: usize) -> Result<String, String> {
+        let order = self.order();
+        let old = self.parser.remove_expr(ndx)?;
+
+        areabdd::remove_root(&mut self.bdd, ndx);
+        self.columns.remove(ndx);
+        self.remap(&order);
+        return Ok(old);
+    }
+
+    // Only the truth table follows the new ordering; the BDD keeps its own
+    pub fn set_order(&mut self, vars: &[char]) -> Result<(), String> {
+        let order = self.order();
+        self.parser.set_order(vars)?;
+        self.remap(&order);
+        return Ok(());
+    }
+
+    // Equivalent expressions share a root, so comparing them never walks the BDD
+    pub fn all_eq(&self) -> bool {
+        return self.bdd.roots().windows(2).all(|pair| pair[0] == pair[1]);
+    }
+
+    // Every row of the truth table: the input values followed by each expression's result
+    // Only the columns of expressions added or replaced since the last table are evaluated
+    pub fn rows(&mut self) -> Result<Vec<Vec<bool>>, String> {
+        let num_inputs = self.parser.ast_order.len();
+        if num_inputs > MAX_TABLE_VARS {
+            return Err(format!("The truth table of {num_inputs} variables is too large; tables are limited to {MAX_TABLE_VARS} variables"));
+        }
+
+        for (ndx, column) in self.columns.iter_mut().enumerate() {
+            if column.is_none() {
+                *column = Some(ast::evaluate_column(&self.parser, ndx));
+            }
+        }
+        let columns: Vec<&Vec<bool>> = self.columns.iter().flatten().collect();
+        return Ok(ast::get_cases(num_inputs).into_iter().enumerate()
+            .map(|(row, mut case)| {
+                case.extend(columns.iter().map(|column| column[row]));
+                case
+            })
+            .collect());
+    }
+
+    // The rows of the truth table on which the expressions disagree
+    pub fn cex(&mut self) -> Result<Vec<Vec<bool>>, String> {
+        let num_inputs = self.parser.ast_order.len();
+        return Ok(self.rows()?.into_iter()
+            .filter(|row| row[num_inputs..].iter().any(|res| *res != row[num_inputs]))
+            .collect());
+    }
+
+    // One row on which the expressions disagree, shaped like the rows of cex but read off the BDD
+    pub fn pick_cex(&mut self) -> Option<Vec<bool>> {
+        let roots = self.bdd.roots().to_vec();
+        let other = roots.iter().find(|root| **root != roots[0])?;
+        let diff = areabdd::apply(&mut self.bdd, &Operator::XOR, roots[0], *other);
+        let values = areabdd::pick_one(&self.bdd, diff).expect("Distinct roots always differ somewhere");
+
+        // values follow the BDD's ordering and rows the parser's
+        let mut row: Vec<bool> = self.parser.ast_order.keys().map(|c| values[self.bdd.ordering()[c] - 1]).collect();
+        row.extend(roots.iter().map(|root| areabdd::evaluate(&self.bdd, *root, &values)));
+        return Some(row);
+    }
+
+    // The results of every expression on a full assignment, read off the BDD
+    pub fn evaluate(&self, assignment: &[(char, bool)]) -> Result<Vec<bool>, String> {
+        if let Some(c) = self.parser.ast_order.keys().find(|c| !assignment.iter().any(|(v, _)| v == *c)) {
+            return Err(format!("No value given for variable {c}"));
+        }
+
+        // The BDD may still order variables that no expression uses any more; their value does not matter
+        let values: Vec<bool> = self.bdd.ordering().keys()
+            .map(|c| assignment.iter().find(|(v, _)| v == c).map(|(_, val)| *val).unwrap_or(false))
+            .collect();
+        return Ok(self.bdd.roots().iter().map(|root| areabdd::evaluate(&self.bdd, *root, &values)).collect());
+    }
+
+    fn order(&self) -> Vec<char> {
+        return self.parser.ast_order.keys().copied().collect();
+    }
+
+    // Carries the columns over to the parser's current ordering from the old one
+    // Rows follow ast::get_cases, where the first variable is the most significant bit of the row number; a
+    // variable that is new to the ordering cannot affect the old columns, and one that has left it affects none
+    // Past MAX_TABLE_VARS the columns are dropped instead, and evaluated again if the session shrinks back
+    fn remap(&mut self, old: &[char]) {
+        let new = self.order();
+        if new == old || self.columns.iter().all(|column| column.is_none()) {
+            return;
+        } else if new.len() > MAX_TABLE_VARS {
+            self.columns.iter_mut().for_each(|column| *column = None);
+            return;
+        }
+
+        let shifts: Vec<(usize, usize)> = old.iter().enumerate()
+            .filter_map(|(i, c)| new.iter().position(|d| d == c).map(|j| (old.len() - 1 - i, new.len() - 1 - j)))
+            .collect();
+        let old_rows: Vec<usize> = (0..1usize << new.len())
+            .map(|row| shifts.iter().map(|(old_shift, new_shift)| (row >> new_shift & 1) << old_shift).sum())
+            .collect();
+        for column in self.columns.iter_mut().flatten() {
+            *column = old_rows.iter().map(|row| column[*row]).collect();
+        }
+    }
+}
+
+pub fn create_session(raw_inputs: Vec<String>) -> Result<Session, String> {
+    return Ok(Session::new(parser::create_session(raw_inputs)?));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn session(exprs: &[&str]) -> Session {
+        return create_session(exprs.iter().map(|e| e.to_string()).collect()).unwrap();
+    }
+
+    // An edited session must end up where a session built from its final expressions starts
+    fn assert_matches_rebuild(session: &mut Session) {
+        let mut rebuilt = Session::new(parser::create_session(session.parser().raw_exprs().to_vec()).unwrap());
+        assert_eq!(rebuilt.parser().ast_order.keys().collect::<Vec<&char>>(), session.parser().ast_order.keys().collect::<Vec<&char>>());
+        assert_eq!(rebuilt.rows(), session.rows());
+        assert_eq!(rebuilt.all_eq(), session.all_eq());
+    }
+
+    #[test]
+    fn edits_match_a_rebuild() {
+        let mut session = session(&["a & b", "~(~a | ~b)"]);
+        assert!(session.all_eq() && session.cex().unwrap().is_empty());
+        assert_eq!(None, session.pick_cex());
+
+        session.add_expr(vec![String::from("c ^ a")]).unwrap();
+        assert_matches_rebuild(&mut session);
+        assert!(!session.all_eq());
+        assert_eq!(vec![false, false, true, false, false, true], session.cex().unwrap()[0]);
+        assert!(session.cex().unwrap().contains(&session.pick_cex().unwrap()));
+
+        session.replace_expr(2, String::from("b & (a | d)")).unwrap();
+        assert_matches_rebuild(&mut session);
+        session.remove_expr(2).unwrap();
+        assert_matches_rebuild(&mut session);
+        assert!(session.all_eq());
+
+        session.add_expr(vec![String::from("d | a & b & ~d")]).unwrap();
+        session.set_order(&['d', 'b']).unwrap();
+        assert_eq!(vec!['d', 'b', 'a'], session.parser().ast_order.keys().copied().collect::<Vec<char>>());
+        assert_eq!(vec![false, false, true], session.evaluate(&[('a', false), ('b', true), ('d', true)]).unwrap());
+        let rows = session.rows().unwrap();
+        assert_eq!(rows.iter().map(|row| row[3..].to_vec()).collect::<Vec<Vec<bool>>>(),
+            rows.iter().map(|row| session.evaluate(&[('d', row[0]), ('b', row[1]), ('a', row[2])]).unwrap()).collect::<Vec<Vec<bool>>>());
+        assert!(session.cex().unwrap().contains(&session.pick_cex().unwrap()));
+        assert!(session.evaluate(&[('a', true)]).is_err());
+    }
+
+    #[test]
+    fn wide_sessions_skip_the_table() {
+        // A table of 26 variables would hold 2^26 rows per expression; the BDD still decides and finds a failure case
+        let vars: Vec<String> = ('a'..='z').map(|c| c.to_string()).collect();
+        let mut session = session(&[&vars.join(" & "), &vars.join(" | ")]);
+        assert!(!session.all_eq());
+        assert!(session.rows().is_err());
+
+        let row = session.pick_cex().unwrap();
+        assert_eq!(26 + 2, row.len());
+        assert!(row[26] != row[27]);
+        assert_eq!(row[26..].to_vec(), session.evaluate(&('a'..='z').zip(row.iter().copied()).collect::<Vec<(char, bool)>>()).unwrap());
+
+        // Dropping below the limit brings the table back
+        session.replace_expr(0, String::from("a & b")).unwrap();
+        session.replace_expr(1, String::from("b & a")).unwrap();
+        assert_eq!(4, session.rows().unwrap().len());
+        assert_eq!(None, session.pick_cex());
+    }
+
+    #[test]
+    fn replaced_roots_are_released() {
+        let mut session = session(&["a & b", "(a | c) & (b | d)"]);
+        let old = session.bdd().roots()[1];
+        assert_eq!(1, session.bdd().ref_count(old));
+
+        session.replace_expr(1, String::from("b & a")).unwrap();
+        assert_eq!(0, session.bdd().ref_count(old));
+        assert!(session.all_eq());
+        assert_eq!(2, session.bdd().ref_count(session.bdd().roots()[0]));
+
+        session.remove_expr(0).unwrap();
+        assert_eq!(1, session.bdd().ref_count(session.bdd().roots()[0]));
+        // Only the terminal and the two vertices of a & b are still alive
+        assert_eq!(session.bdd().vertex_count() - 3, session.bdd().dead_count());
+        assert!(session.replace_expr(0, String::from("s[0] = a")).is_err());
+    }
+}
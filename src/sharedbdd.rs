@@ -0,0 +1,399 @@
+// Thread-safe BDD manager for building several expressions at once
+// Edges follow the same conventions as areabdd: the 1-terminal has ID 1 and a negative edge is a complement
+//
+// Vertices live in shards picked by hashing (var, lo, hi), so finding or creating a vertex only ever locks
+// the one shard that could hold it and canonicity holds across threads. A vertex ID records its shard:
+// ID = 2 + index * SHARDS + shard. The computed table is sharded the same way by key.
+// There is no reference counting; vertices live as long as the manager.
+
+use linked_hash_map::LinkedHashMap;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::{env, thread};
+use crate::areabdd::{standard_triple, IteForm, DEFAULT_CACHE_BUDGET};
+use crate::cache::{fx_hash, CacheStats, ComputedTable};
+use crate::parser::{Definition, Operator, Parser, Token};
+
+const SHARDS: usize = 64;
+
+type Edge = isize;
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+struct Vertex {
+    var: isize,
+    lo: Edge,
+    hi: Edge
+}
+
+#[derive(Debug, Default)]
+struct Shard {
+    vertices: Vec<Vertex>,
+    unique: HashMap<Vertex, Edge>
+}
+
+#[derive(Debug)]
+pub struct SharedBDD {
+    shards: Vec<RwLock<Shard>>,
+    caches: Vec<Mutex<ComputedTable<(Edge, Edge, Edge)>>>,
+    ordering: RwLock<LinkedHashMap<char, usize>>
+}
+
+impl SharedBDD {
+    pub fn new() -> Self {
+        return Self::with_cache_budget(DEFAULT_CACHE_BUDGET);
+    }
+
+    // The budget is split evenly between the computed table shards
+    pub fn with_cache_budget(budget: usize) -> Self {
+        SharedBDD {
+            shards: (0..SHARDS).map(|_| RwLock::new(Shard::default())).collect(),
+            caches: (0..SHARDS).map(|_| Mutex::new(ComputedTable::with_budget(budget / SHARDS))).collect(),
+            ordering: RwLock::new(LinkedHashMap::new())
+        }
+    }
+
+    // Returns the level of the named variable, placing it last in the ordering if it is new
+    pub fn add_var(&self, var: char) -> usize {
+        let mut ordering = self.ordering.write().unwrap();
+        if let Some(level) = ordering.get(&var) {
+            return *level;
+        }
+
+        let level = ordering.len() + 1; // Level 0 is reserved for the terminal
+        ordering.insert(var, level);
+        return level;
+    }
+
+    // Returns the function of a single variable
+    pub fn var_edge(&self, var: char) -> Edge {
+        let level = self.add_var(var);
+        return self.make(level as isize, -1, 1);
+    }
+
+    pub fn ordering(&self) -> LinkedHashMap<char, usize> {
+        return self.ordering.read().unwrap().clone();
+    }
+
+    // Number of vertices created so far, including the terminal
+    pub fn vertex_count(&self) -> usize {
+        return 1 + self.shards.iter().map(|shard| shard.read().unwrap().vertices.len()).sum::<usize>();
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.caches.iter()
+            .map(|cache| cache.lock().unwrap().stats())
+            .fold(CacheStats::default(), |acc, stats| acc.merge(&stats));
+    }
+
+    fn make(&self, var: isize, mut lo: Edge, mut hi: Edge) -> Edge {
+        if lo == hi {
+            return lo;
+        }
+
+        // Prevent creation of vertices with complemented high edge
+        let mut complement_flag: isize = 1;
+        if hi < 0 {
+            lo = -lo;
+            hi = -hi;
+            complement_flag = -1;
+        }
+
+        let vertex = Vertex {var, lo, hi};
+        let shard_ndx = fx_hash(&vertex) as usize % SHARDS;
+        if let Some(id) = self.shards[shard_ndx].read().unwrap().unique.get(&vertex) {
+            return *id * complement_flag;
+        }
+
+        // Another thread may have added the vertex between the two locks, so check again
+        let mut shard = self.shards[shard_ndx].write().unwrap();
+        if let Some(id) = shard.unique.get(&vertex) {
+            return *id * complement_flag;
+        }
+
+        let id = (2 + shard.vertices.len() * SHARDS + shard_ndx) as Edge;
+        shard.vertices.push(vertex);
+        shard.unique.insert(vertex, id);
+        return id * complement_flag;
+    }
+
+    fn vertex(&self, id: Edge) -> Vertex {
+        let ndx = id.unsigned_abs() - 2;
+        return self.shards[ndx % SHARDS].read().unwrap().vertices[ndx / SHARDS];
+    }
+
+    // Returns the variable at the top of the given edge; terminals sit below every variable
+    fn top_var(&self, id: Edge) -> isize {
+        if id.abs() == 1 {
+            return isize::MAX;
+        }
+
+        return self.vertex(id).var;
+    }
+
+    // Returns the (lo, hi) cofactors of an edge with respect to var
+    fn cofactors(&self, id: Edge, var: isize) -> (Edge, Edge) {
+        if id.abs() == 1 {
+            return (id, id);
+        }
+
+        let vertex = self.vertex(id);
+        if vertex.var != var {
+            return (id, id);
+        } else if id < 0 {
+            return (-vertex.lo, -vertex.hi);
+        } else {
+            return (vertex.lo, vertex.hi);
+        }
+    }
+
+    // If-then-else: (f & g) | (~f & h)
+    pub fn ite(&self, f: Edge, g: Edge, h: Edge) -> Edge {
+        match standard_triple(f, g, h, |id| self.top_var(id)) {
+            IteForm::Done(res) => res,
+            IteForm::Triple(f, g, h, complement_flag) => self.ite_helper(f, g, h) * complement_flag
+        }
+    }
+
+    fn ite_helper(&self, f: Edge, g: Edge, h: Edge) -> Edge {
+        let key = (f, g, h);
+        let cache = &self.caches[fx_hash(&key) as usize % SHARDS];
+        if let Some(id) = cache.lock().unwrap().get(&key) {
+            return id;
+        }
+
+        let min_var = self.top_var(f).min(self.top_var(g)).min(self.top_var(h));
+        let (f_lo, f_hi) = self.cofactors(f, min_var);
+        let (g_lo, g_hi) = self.cofactors(g, min_var);
+        let (h_lo, h_hi) = self.cofactors(h, min_var);
+
+        let hi_cofactor = self.ite(f_hi, g_hi, h_hi);
+        let lo_cofactor = self.ite(f_lo, g_lo, h_lo);
+        let res = self.make(min_var, lo_cofactor, hi_cofactor);
+
+        cache.lock().unwrap().insert(key, res);
+        return res;
+    }
+
+    // Binary operators are rewritten in terms of ite
+    pub fn apply(&self, op: &Operator, lhs: Edge, rhs: Edge) -> Edge {
+        match op {
+            Operator::AND => self.ite(lhs, rhs, -1),
+            Operator::OR => self.ite(lhs, 1, rhs),
+            Operator::XOR => self.ite(lhs, -rhs, rhs),
+            _ => panic!("Non-binary operator {:?} is passed into bin op apply", op)
+        }
+    }
+
+    // Evaluates the function rooted at the given edge
+    // values is indexed by position in the ordering, like areabdd::evaluate
+    pub fn evaluate(&self, root: Edge, values: &[bool]) -> bool {
+        let mut curr = root;
+        while curr.abs() != 1 {
+            let var = self.top_var(curr);
+            let (lo, hi) = self.cofactors(curr, var);
+            curr = if values[var as usize - 1] { hi } else { lo };
+        }
+
+        return curr == 1;
+    }
+
+    // Returns an assignment on which the function holds, indexed like evaluate
+    // Variables that the chosen path skips are set to false
+    pub fn pick_one(&self, root: Edge) -> Option<Vec<bool>> {
+        if root == -1 {
+            return None;
+        }
+
+        let mut values = vec![false; self.ordering.read().unwrap().len()];
+        let mut curr = root;
+        while curr.abs() != 1 {
+            let var = self.top_var(curr);
+            let (lo, hi) = self.cofactors(curr, var);
+            if hi != -1 {
+                values[var as usize - 1] = true;
+                curr = hi;
+            } else {
+                curr = lo;
+            }
+        }
+
+        return Some(values);
+    }
+
+    // Each definition is built once per distinct argument list, however often the expression uses it
+    pub fn build_rpn(&self, rpn: &[Token], defs: &[Definition]) -> Edge {
+        return self.build_helper(rpn, defs, &[], &mut HashMap::new());
+    }
+
+    fn build_helper(&self, rpn: &[Token], defs: &[Definition], params: &[Edge],
+        shared: &mut HashMap<(usize, Vec<Edge>), Edge>) -> Edge {
+        let mut op_stack: Vec<Edge> = Vec::with_capacity(rpn.len());
+
+        for t in rpn.iter() {
+            match t {
+                Token::VAR(c) => op_stack.push(self.var_edge(*c)),
+                Token::PARAM(ndx) => op_stack.push(params[*ndx]),
+                Token::DEF(id) => {
+                    let args = op_stack.split_off(op_stack.len() - defs[*id].params.len());
+                    let res = match shared.get(&(*id, args.clone())) {
+                        Some(res) => *res,
+                        None => {
+                            let res = self.build_helper(&defs[*id].body.shared_rpn, defs, &args, shared);
+                            shared.insert((*id, args), res);
+                            res
+                        }
+                    };
+                    op_stack.push(res);
+                },
+                Token::OP(Operator::NOT) => {
+                    let top = op_stack.last_mut().unwrap();
+                    *top = -(*top);
+                },
+                Token::OP(Operator::ITE) => {
+                    let h = op_stack.pop().unwrap();
+                    let g = op_stack.pop().unwrap();
+                    let f = op_stack.pop().unwrap();
+                    op_stack.push(self.ite(f, g, h));
+                },
+                Token::OP(op) => {
+                    let rhs = op_stack.pop().unwrap();
+                    let lhs = op_stack.pop().unwrap();
+                    op_stack.push(self.apply(op, lhs, rhs));
+                },
+                _ => panic!("Unexpected token while building BDD")
+            }
+        }
+
+        return op_stack.pop().expect("No vertex left to assign as root");
+    }
+}
+
+impl Default for SharedBDD {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Builds every expression of the session on its own thread, returning the roots in input order
+// Variables are registered up front in ast_order so every thread sees the same ordering
+// Like the ast engine, setting NO_THREAD builds the expressions one after another instead
+pub fn build_parallel(bdd: &SharedBDD, parser: &Parser) -> Vec<Edge> {
+    for var in parser.ast_order.keys() {
+        bdd.add_var(*var);
+    }
+
+    if env::var("NO_THREAD").is_ok() {
+        return parser.exprs.iter().map(|expr| bdd.build_rpn(&expr.shared_rpn, &parser.defs)).collect();
+    }
+
+    // A fixed pool of workers takes expressions in turn from a shared counter
+    let workers = thread::available_parallelism().map_or(1, |n| n.get()).min(parser.exprs.len());
+    let next = AtomicUsize::new(0);
+    let mut roots: Vec<Edge> = vec![-1; parser.exprs.len()];
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| scope.spawn(|| {
+            let mut built = Vec::new();
+            loop {
+                let ndx = next.fetch_add(1, Ordering::Relaxed);
+                if ndx >= parser.exprs.len() {
+                    return built;
+                }
+                built.push((ndx, bdd.build_rpn(&parser.exprs[ndx].shared_rpn, &parser.defs)));
+            }
+        })).collect();
+
+        for handle in handles {
+            for (ndx, root) in handle.join().unwrap() {
+                roots[ndx] = root;
+            }
+        }
+    });
+    return roots;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::areabdd;
+
+    fn is_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shared_manager_is_send_sync() {
+        is_send_sync::<SharedBDD>();
+    }
+
+    #[test]
+    fn parallel_build_is_canonical() {
+        let exprs: Vec<String> = ["(a & b) | (c ^ d)", "(c ^ d) | (b & a)", "~(~(a & b) & ~(d ^ c))", "a ? b | (c ^ d) : c ^ d", "a & b & c & d"]
+            .iter().map(|e| e.to_string()).collect();
+        let parser = crate::parser::create_session(exprs).unwrap();
+
+        let bdd = SharedBDD::new();
+        let roots = build_parallel(&bdd, &parser);
+        assert!(roots[..4].iter().all(|r| *r == roots[0]));
+        assert_ne!(roots[0], roots[4]);
+
+        let diff = bdd.apply(&Operator::XOR, roots[0], roots[4]);
+        let witness = bdd.pick_one(diff).unwrap();
+        assert_ne!(bdd.evaluate(roots[0], &witness), bdd.evaluate(roots[4], &witness));
+        assert_eq!(None, bdd.pick_one(bdd.apply(&Operator::XOR, roots[0], roots[1])));
+
+        // Same functions as the single-threaded manager
+        let reference = areabdd::build(areabdd::BDD::new(), &parser);
+        for case in 0..16 {
+            let values: Vec<bool> = (0..4).map(|i| case & (1 << i) != 0).collect();
+            for (root, expected) in roots.iter().zip(reference.roots().iter()) {
+                assert_eq!(areabdd::evaluate(&reference, *expected, &values), bdd.evaluate(*root, &values));
+            }
+        }
+    }
+
+    #[test]
+    fn more_expressions_than_workers() {
+        let exprs: Vec<String> = (0..200).map(|n| if n % 2 == 0 { String::from("a & b") } else { String::from("b ^ c") }).collect();
+        let parser = crate::parser::create_session(exprs).unwrap();
+
+        let bdd = SharedBDD::new();
+        let roots = build_parallel(&bdd, &parser);
+        assert_eq!(200, roots.len());
+        assert!(roots.iter().enumerate().all(|(n, root)| *root == roots[n % 2]));
+        assert_ne!(roots[0], roots[1]);
+    }
+
+    #[test]
+    fn definition_chains() {
+        let mut inputs = crate::parser::definition_chain(40);
+        inputs.push(String::from("(a ^ b) & ~c"));
+        let parser = crate::parser::create_session(inputs).unwrap();
+
+        let bdd = SharedBDD::new();
+        let roots = build_parallel(&bdd, &parser);
+        assert_eq!(roots[0], roots[1]);
+    }
+
+    #[test]
+    fn concurrent_builders_share_vertices() {
+        let bdd = SharedBDD::with_cache_budget(1 << 12);
+        let vars: Vec<char> = ('a'..='l').collect();
+
+        // Every thread builds the same parity function in a different association order
+        let roots: Vec<Edge> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8).map(|offset| {
+                let (bdd, vars) = (&bdd, &vars);
+                scope.spawn(move || {
+                    let mut acc = -1;
+                    for ndx in 0..vars.len() {
+                        let var = bdd.var_edge(vars[(ndx + offset) % vars.len()]);
+                        acc = bdd.apply(&Operator::XOR, var, acc);
+                    }
+                    acc
+                })
+            }).collect();
+            handles.into_iter().map(|handle| handle.join().unwrap()).collect()
+        });
+
+        assert!(roots.iter().all(|r| *r == roots[0]));
+    }
+}
@@ -0,0 +1,571 @@
+// Zero-suppressed decision diagrams for families of sets
+// A path to the 1-terminal is a set holding the variables whose hi edge it takes
+// Vertices whose hi edge leads to the empty family are never created, so absent variables cost nothing
+// There are no complement edges; ID 0 is the empty family and ID 1 is the family holding only the empty set
+
+use linked_hash_map::LinkedHashMap;
+use std::collections::{HashMap, HashSet};
+use crate::areabdd::{self, BDD, DEFAULT_CACHE_BUDGET};
+use crate::cache::{CacheStats, ComputedTable};
+
+type ID = isize;
+
+pub const EMPTY: ID = 0;
+pub const BASE: ID = 1;
+
+#[derive(Debug, Clone)]
+pub struct ZDD {
+    vertices: Vec<Vertex>, // Indexed by ID; the first two slots stand in for the terminals
+    unique: HashMap<Vertex, ID>,
+    cache: ComputedTable<ZddExpr>,
+    ordering: LinkedHashMap<char, usize>
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+struct Vertex {
+    var: isize,
+    lo: ID,
+    hi: ID
+}
+
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+enum ZddOp {
+    Union,
+    Intersect,
+    Diff,
+    SymDiff,
+    Change,
+    Subset0,
+    Subset1
+}
+
+// Single-family operations store the variable level in g
+#[derive(Eq, PartialEq, Hash, Debug, Clone, Copy)]
+struct ZddExpr {
+    op: ZddOp,
+    f: ID,
+    g: isize
+}
+
+impl ZDD {
+    pub fn new() -> Self {
+        return Self::with_cache_budget(DEFAULT_CACHE_BUDGET);
+    }
+
+    pub fn with_cache_budget(budget: usize) -> Self {
+        let terminal = Vertex {var: 0, lo: 0, hi: 0};
+        Self {
+            vertices: vec![terminal, terminal],
+            unique: HashMap::new(),
+            cache: ComputedTable::with_budget(budget),
+            ordering: LinkedHashMap::new()
+        }
+    }
+
+    // Variable names mapped to their levels, from the top of the ZDD down
+    pub fn ordering(&self) -> &LinkedHashMap<char, usize> {
+        return &self.ordering;
+    }
+
+    // Number of vertices ever created, including both terminals
+    pub fn vertex_count(&self) -> usize {
+        return self.vertices.len();
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        return self.cache.stats();
+    }
+}
+
+impl Default for ZDD {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the level of the named variable, placing it last in the ordering if it is new
+fn ensure_var(zdd: &mut ZDD, var: char) -> isize {
+    if let Some(level) = zdd.ordering.get(&var) {
+        return *level as isize;
+    }
+
+    let level = zdd.ordering.len() + 1;
+    zdd.ordering.insert(var, level);
+    return level as isize;
+}
+
+fn make(zdd: &mut ZDD, var: isize, lo: ID, hi: ID) -> ID {
+    // Zero-suppression: a variable that no set contains gets no vertex
+    if hi == EMPTY {
+        return lo;
+    }
+
+    let vertex = Vertex {var, lo, hi};
+    if let Some(id) = zdd.unique.get(&vertex) {
+        return *id;
+    }
+
+    let id = zdd.vertices.len() as ID;
+    zdd.vertices.push(vertex);
+    zdd.unique.insert(vertex, id);
+    return id;
+}
+
+// Terminals sit below every variable
+fn top_var(zdd: &ZDD, id: ID) -> isize {
+    if id <= BASE {
+        return isize::MAX;
+    }
+
+    return zdd.vertices[id as usize].var;
+}
+
+fn children(zdd: &ZDD, id: ID) -> (ID, ID) {
+    let vertex = &zdd.vertices[id as usize];
+    return (vertex.lo, vertex.hi);
+}
+
+// The family holding just the set {var}
+pub fn singleton(zdd: &mut ZDD, var: char) -> ID {
+    let level = ensure_var(zdd, var);
+    return make(zdd, level, EMPTY, BASE);
+}
+
+// Builds the family holding exactly the given sets
+pub fn from_sets(zdd: &mut ZDD, sets: &[Vec<char>]) -> ID {
+    let mut family = EMPTY;
+    for set in sets.iter() {
+        let mut member = BASE;
+        for var in set.iter() {
+            member = change(zdd, member, *var);
+        }
+        family = union(zdd, family, member);
+    }
+
+    return family;
+}
+
+// Lists every set in the family, each with its variables in ordering order
+pub fn sets(zdd: &ZDD, f: ID) -> Vec<Vec<char>> {
+    let names: Vec<char> = zdd.ordering.keys().copied().collect();
+    let mut res: Vec<Vec<char>> = Vec::new();
+    let mut path: Vec<char> = Vec::new();
+    sets_helper(zdd, f, &names, &mut path, &mut res);
+    return res;
+}
+
+fn sets_helper(zdd: &ZDD, f: ID, names: &[char], path: &mut Vec<char>, res: &mut Vec<Vec<char>>) {
+    if f == EMPTY {
+        return;
+    } else if f == BASE {
+        res.push(path.clone());
+        return;
+    }
+
+    let (lo, hi) = children(zdd, f);
+    sets_helper(zdd, lo, names, path, res);
+    path.push(names[top_var(zdd, f) as usize - 1]);
+    sets_helper(zdd, hi, names, path, res);
+    path.pop();
+}
+
+// Number of vertices reachable from f, not counting terminals
+pub fn size(zdd: &ZDD, f: ID) -> usize {
+    let mut seen: HashSet<ID> = HashSet::new();
+    let mut stack: Vec<ID> = vec![f];
+    while let Some(id) = stack.pop() {
+        if id <= BASE || !seen.insert(id) {
+            continue;
+        }
+        let (lo, hi) = children(zdd, id);
+        stack.push(lo);
+        stack.push(hi);
+    }
+
+    return seen.len();
+}
+
+// Number of sets in the family
+pub fn count(zdd: &ZDD, f: ID) -> usize {
+    let mut memo: HashMap<ID, usize> = HashMap::new();
+    return count_helper(zdd, f, &mut memo);
+}
+
+fn count_helper(zdd: &ZDD, f: ID, memo: &mut HashMap<ID, usize>) -> usize {
+    if f <= BASE {
+        return f as usize;
+    } else if let Some(count) = memo.get(&f) {
+        return *count;
+    }
+
+    let (lo, hi) = children(zdd, f);
+    let count = count_helper(zdd, lo, memo) + count_helper(zdd, hi, memo);
+    memo.insert(f, count);
+    return count;
+}
+
+// Size of the largest set in the family, or None for the empty family
+pub fn max_cardinality(zdd: &ZDD, f: ID) -> Option<usize> {
+    let mut memo: HashMap<ID, Option<usize>> = HashMap::new();
+    return max_cardinality_helper(zdd, f, &mut memo);
+}
+
+fn max_cardinality_helper(zdd: &ZDD, f: ID, memo: &mut HashMap<ID, Option<usize>>) -> Option<usize> {
+    if f == EMPTY {
+        return None;
+    } else if f == BASE {
+        return Some(0);
+    } else if let Some(res) = memo.get(&f) {
+        return *res;
+    }
+
+    // Zero-suppression keeps hi non-empty
+    let (lo, hi) = children(zdd, f);
+    let with_var = max_cardinality_helper(zdd, hi, memo).map(|size| size + 1);
+    let res = max_cardinality_helper(zdd, lo, memo).max(with_var);
+    memo.insert(f, res);
+    return res;
+}
+
+pub fn union(zdd: &mut ZDD, f: ID, g: ID) -> ID {
+    if f == EMPTY || f == g {
+        return g;
+    } else if g == EMPTY {
+        return f;
+    }
+
+    // Union is commutative, so both orders share a cache entry
+    let (f, g) = if f < g { (f, g) } else { (g, f) };
+    let expr = ZddExpr {op: ZddOp::Union, f, g};
+    if let Some(id) = zdd.cache.get(&expr) {
+        return id;
+    }
+
+    let (f_var, g_var) = (top_var(zdd, f), top_var(zdd, g));
+    let res = if f_var < g_var {
+        let (lo, hi) = children(zdd, f);
+        let lo = union(zdd, lo, g);
+        make(zdd, f_var, lo, hi)
+    } else if f_var > g_var {
+        let (lo, hi) = children(zdd, g);
+        let lo = union(zdd, f, lo);
+        make(zdd, g_var, lo, hi)
+    } else {
+        let ((f_lo, f_hi), (g_lo, g_hi)) = (children(zdd, f), children(zdd, g));
+        let lo = union(zdd, f_lo, g_lo);
+        let hi = union(zdd, f_hi, g_hi);
+        make(zdd, f_var, lo, hi)
+    };
+
+    zdd.cache.insert(expr, res);
+    return res;
+}
+
+pub fn intersect(zdd: &mut ZDD, f: ID, g: ID) -> ID {
+    if f == EMPTY || g == EMPTY {
+        return EMPTY;
+    } else if f == g {
+        return f;
+    }
+
+    let (f, g) = if f < g { (f, g) } else { (g, f) };
+    let expr = ZddExpr {op: ZddOp::Intersect, f, g};
+    if let Some(id) = zdd.cache.get(&expr) {
+        return id;
+    }
+
+    // Sets that contain the top variable of only one side cannot be in both
+    let (f_var, g_var) = (top_var(zdd, f), top_var(zdd, g));
+    let res = if f_var < g_var {
+        let (lo, _) = children(zdd, f);
+        intersect(zdd, lo, g)
+    } else if f_var > g_var {
+        let (lo, _) = children(zdd, g);
+        intersect(zdd, f, lo)
+    } else {
+        let ((f_lo, f_hi), (g_lo, g_hi)) = (children(zdd, f), children(zdd, g));
+        let lo = intersect(zdd, f_lo, g_lo);
+        let hi = intersect(zdd, f_hi, g_hi);
+        make(zdd, f_var, lo, hi)
+    };
+
+    zdd.cache.insert(expr, res);
+    return res;
+}
+
+// Sets in f that are not in g
+pub fn diff(zdd: &mut ZDD, f: ID, g: ID) -> ID {
+    if f == EMPTY || f == g {
+        return EMPTY;
+    } else if g == EMPTY {
+        return f;
+    }
+
+    let expr = ZddExpr {op: ZddOp::Diff, f, g};
+    if let Some(id) = zdd.cache.get(&expr) {
+        return id;
+    }
+
+    let (f_var, g_var) = (top_var(zdd, f), top_var(zdd, g));
+    let res = if f_var < g_var {
+        let (lo, hi) = children(zdd, f);
+        let lo = diff(zdd, lo, g);
+        make(zdd, f_var, lo, hi)
+    } else if f_var > g_var {
+        let (lo, _) = children(zdd, g);
+        diff(zdd, f, lo)
+    } else {
+        let ((f_lo, f_hi), (g_lo, g_hi)) = (children(zdd, f), children(zdd, g));
+        let lo = diff(zdd, f_lo, g_lo);
+        let hi = diff(zdd, f_hi, g_hi);
+        make(zdd, f_var, lo, hi)
+    };
+
+    zdd.cache.insert(expr, res);
+    return res;
+}
+
+// Sets in exactly one of f and g; over monomials this is the sum of two polynomials in GF(2)
+pub fn sym_diff(zdd: &mut ZDD, f: ID, g: ID) -> ID {
+    if f == EMPTY {
+        return g;
+    } else if g == EMPTY {
+        return f;
+    } else if f == g {
+        return EMPTY;
+    }
+
+    let (f, g) = if f < g { (f, g) } else { (g, f) };
+    let expr = ZddExpr {op: ZddOp::SymDiff, f, g};
+    if let Some(id) = zdd.cache.get(&expr) {
+        return id;
+    }
+
+    let (f_var, g_var) = (top_var(zdd, f), top_var(zdd, g));
+    let res = if f_var < g_var {
+        let (lo, hi) = children(zdd, f);
+        let lo = sym_diff(zdd, lo, g);
+        make(zdd, f_var, lo, hi)
+    } else if f_var > g_var {
+        let (lo, hi) = children(zdd, g);
+        let lo = sym_diff(zdd, f, lo);
+        make(zdd, g_var, lo, hi)
+    } else {
+        let ((f_lo, f_hi), (g_lo, g_hi)) = (children(zdd, f), children(zdd, g));
+        let lo = sym_diff(zdd, f_lo, g_lo);
+        let hi = sym_diff(zdd, f_hi, g_hi);
+        make(zdd, f_var, lo, hi)
+    };
+
+    zdd.cache.insert(expr, res);
+    return res;
+}
+
+// Toggles var in every set of the family
+pub fn change(zdd: &mut ZDD, f: ID, var: char) -> ID {
+    let level = ensure_var(zdd, var);
+    return var_op(zdd, ZddOp::Change, f, level);
+}
+
+// Sets that do not contain var
+pub fn subset0(zdd: &mut ZDD, f: ID, var: char) -> ID {
+    let level = ensure_var(zdd, var);
+    return var_op(zdd, ZddOp::Subset0, f, level);
+}
+
+// Sets that contain var, with var taken out of them
+pub fn subset1(zdd: &mut ZDD, f: ID, var: char) -> ID {
+    let level = ensure_var(zdd, var);
+    return var_op(zdd, ZddOp::Subset1, f, level);
+}
+
+fn var_op(zdd: &mut ZDD, op: ZddOp, f: ID, var: isize) -> ID {
+    let f_var = top_var(zdd, f);
+    if f_var > var { // No set mentions var
+        return match op {
+            ZddOp::Change => make(zdd, var, EMPTY, f),
+            ZddOp::Subset0 => f,
+            _ => EMPTY
+        };
+    } else if f_var == var {
+        let (lo, hi) = children(zdd, f);
+        return match op {
+            ZddOp::Change => make(zdd, var, hi, lo),
+            ZddOp::Subset0 => lo,
+            _ => hi
+        };
+    }
+
+    let expr = ZddExpr {op, f, g: var};
+    if let Some(id) = zdd.cache.get(&expr) {
+        return id;
+    }
+
+    let (lo, hi) = children(zdd, f);
+    let lo = var_op(zdd, op, lo, var);
+    let hi = var_op(zdd, op, hi, var);
+    let res = make(zdd, f_var, lo, hi);
+
+    zdd.cache.insert(expr, res);
+    return res;
+}
+
+// Converts the BDD function f into the family of its satisfying assignments,
+// each given as the set of variables assigned true
+// Every variable in the BDD's ordering is part of the universe, and the ZDD's ordering may differ from it
+pub fn from_bdd(zdd: &mut ZDD, bdd: &BDD, f: isize) -> ID {
+    let universe: Vec<char> = bdd.ordering().keys().copied().collect();
+    let mut memo: HashMap<(isize, usize), ID> = HashMap::new();
+    return from_bdd_helper(zdd, bdd, f, &universe, 0, &mut memo);
+}
+
+fn from_bdd_helper(zdd: &mut ZDD, bdd: &BDD, f: isize, universe: &[char], ndx: usize,
+                   memo: &mut HashMap<(isize, usize), ID>) -> ID {
+    if ndx == universe.len() {
+        return if f == 1 { BASE } else { EMPTY };
+    } else if f == -1 {
+        return EMPTY;
+    } else if let Some(id) = memo.get(&(f, ndx)) {
+        return *id;
+    }
+
+    // BDD levels count from 1 in universe order
+    let (lo, hi) = bdd_cofactors(bdd, f, ndx as isize + 1);
+    let lo = from_bdd_helper(zdd, bdd, lo, universe, ndx + 1, memo);
+    let hi = from_bdd_helper(zdd, bdd, hi, universe, ndx + 1, memo);
+
+    // Neither side mentions the variable, so lo | {s + var : s in hi} works for any ZDD ordering
+    let hi = change(zdd, hi, universe[ndx]);
+    let res = union(zdd, lo, hi);
+    memo.insert((f, ndx), res);
+    return res;
+}
+
+fn bdd_cofactors(bdd: &BDD, f: isize, var: isize) -> (isize, isize) {
+    if f.abs() == 1 {
+        return (f, f);
+    }
+
+    let (f_var, lo, hi) = areabdd::vertex_parts(bdd, f);
+    if f_var != var {
+        return (f, f);
+    } else if f < 0 {
+        return (-lo, -hi);
+    } else {
+        return (lo, hi);
+    }
+}
+
+// Converts a family into the BDD function that holds exactly on its sets
+// The universe is the ZDD's ordering; variables outside it are left unconstrained
+pub fn to_bdd(bdd: &mut BDD, zdd: &ZDD, f: ID) -> isize {
+    let universe: Vec<char> = zdd.ordering.keys().copied().collect();
+    let mut memo: HashMap<(ID, usize), isize> = HashMap::new();
+    return to_bdd_helper(bdd, zdd, f, &universe, 0, &mut memo);
+}
+
+fn to_bdd_helper(bdd: &mut BDD, zdd: &ZDD, f: ID, universe: &[char], ndx: usize,
+                 memo: &mut HashMap<(ID, usize), isize>) -> isize {
+    if f == EMPTY {
+        return -1;
+    } else if ndx == universe.len() {
+        return 1;
+    } else if let Some(edge) = memo.get(&(f, ndx)) {
+        return *edge;
+    }
+
+    // A variable skipped by the ZDD is false in every set
+    let level = ndx as isize + 1;
+    let (lo, hi) = if top_var(zdd, f) == level { children(zdd, f) } else { (f, EMPTY) };
+    let lo = to_bdd_helper(bdd, zdd, lo, universe, ndx + 1, memo);
+    let hi = to_bdd_helper(bdd, zdd, hi, universe, ndx + 1, memo);
+
+    let var = areabdd::var_edge(bdd, universe[ndx]);
+    let res = areabdd::ite(bdd, var, hi, lo);
+    memo.insert((f, ndx), res);
+    return res;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn family(zdd: &mut ZDD, sets: &[&str]) -> ID {
+        let sets: Vec<Vec<char>> = sets.iter().map(|s| s.chars().collect()).collect();
+        return from_sets(zdd, &sets);
+    }
+
+    #[test]
+    fn set_operations() {
+        let mut zdd = ZDD::new();
+        for var in "abc".chars() {
+            ensure_var(&mut zdd, var);
+        }
+
+        let f = family(&mut zdd, &["a", "ab", "c", ""]);
+        let g = family(&mut zdd, &["ab", "bc", ""]);
+        assert_eq!(4, count(&zdd, f));
+
+        let both = union(&mut zdd, f, g);
+        assert_eq!(family(&mut zdd, &["", "a", "ab", "bc", "c"]), both);
+        assert_eq!(family(&mut zdd, &["a", "bc", "c"]), sym_diff(&mut zdd, f, g));
+        assert_eq!(Some(2), max_cardinality(&zdd, f));
+        assert_eq!(None, max_cardinality(&zdd, EMPTY));
+        assert_eq!(family(&mut zdd, &["ab", ""]), intersect(&mut zdd, f, g));
+        assert_eq!(family(&mut zdd, &["a", "c"]), diff(&mut zdd, f, g));
+        assert_eq!(EMPTY, diff(&mut zdd, g, both));
+
+        // Building the same family in another order gives the same ID
+        assert_eq!(f, family(&mut zdd, &["", "c", "ab", "a"]));
+        assert_eq!(vec![vec![], vec!['c'], vec!['a'], vec!['a', 'b']], sets(&zdd, f));
+    }
+
+    #[test]
+    fn change_and_subsets() {
+        let mut zdd = ZDD::new();
+        for var in "abc".chars() {
+            ensure_var(&mut zdd, var);
+        }
+
+        let f = family(&mut zdd, &["a", "ab", "bc", ""]);
+        assert_eq!(family(&mut zdd, &["ab", "a", "c", "b"]), change(&mut zdd, f, 'b'));
+        assert_eq!(family(&mut zdd, &["a", ""]), subset0(&mut zdd, f, 'b'));
+        assert_eq!(family(&mut zdd, &["a", "c"]), subset1(&mut zdd, f, 'b'));
+        assert_eq!(EMPTY, subset1(&mut zdd, BASE, 'c'));
+        assert_eq!(f, subset0(&mut zdd, f, 'd'));
+    }
+
+    #[test]
+    fn bdd_round_trip() {
+        let parser = crate::parser::create_session(vec![String::from("(a & ~b) | (c ^ d)")]).unwrap();
+        let mut bdd = areabdd::build(BDD::new(), &parser);
+        let root = bdd.roots()[0];
+
+        // A reversed ordering exercises the ordering-independent conversion
+        let mut zdd = ZDD::new();
+        for var in "dcba".chars() {
+            ensure_var(&mut zdd, var);
+        }
+        let f = from_bdd(&mut zdd, &bdd, root);
+        assert_eq!(areabdd::satisfy_count(&bdd, root), count(&zdd, f));
+
+        for set in sets(&zdd, f) {
+            let values: Vec<bool> = "abcd".chars().map(|c| set.contains(&c)).collect();
+            assert!(areabdd::evaluate(&bdd, root, &values));
+        }
+
+        assert_eq!(root, to_bdd(&mut bdd, &zdd, f));
+    }
+
+    #[test]
+    fn sparse_families_stay_small() {
+        let mut zdd = ZDD::new();
+        let vars: Vec<char> = ('a'..='z').collect();
+        let singletons: Vec<Vec<char>> = vars.iter().map(|v| vec![*v]).collect();
+        let f = from_sets(&mut zdd, &singletons);
+
+        // One vertex per variable, where a BDD over the same universe needs the full one-hot function
+        assert_eq!(26, count(&zdd, f));
+        assert_eq!(26, size(&zdd, f));
+    }
+}
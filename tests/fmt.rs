@@ -0,0 +1,50 @@
+// Runs `bool-eq fmt` over files in a scratch directory
+
+use std::path::PathBuf;
+use std::process::Command;
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("bool-eq-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn errors_only_skip_their_own_file() {
+    let dir = scratch_dir("fmt");
+    let (bad, good) = (dir.join("bad.txt"), dir.join("good.txt"));
+    std::fs::write(&bad, "a &\n(a & b)\n").unwrap();
+    std::fs::write(&good, "(a&b)|c\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bool-eq"))
+        .arg("fmt")
+        .args([&bad, &good])
+        .output()
+        .unwrap();
+    let (bad_contents, good_contents) = (std::fs::read_to_string(&bad).unwrap(), std::fs::read_to_string(&good).unwrap());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bad.txt:1:"));
+    assert_eq!("a &\n(a & b)\n", bad_contents);
+    assert_eq!("(a & b) | c\n", good_contents);
+}
+
+#[test]
+fn unreadable_files_do_not_stop_the_rest() {
+    let dir = scratch_dir("fmt-missing");
+    let (missing, good) = (dir.join("missing.txt"), dir.join("good.txt"));
+    std::fs::write(&good, "a|~b\n").unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_bool-eq"))
+        .arg("fmt")
+        .args([&missing, &good])
+        .output()
+        .unwrap();
+    let good_contents = std::fs::read_to_string(&good).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(Some(1), output.status.code());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to read"));
+    assert_eq!("a | ~b\n", good_contents);
+}
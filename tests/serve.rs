@@ -0,0 +1,201 @@
+// Runs `bool-eq serve` on a free localhost port and talks to it over plain TCP
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use bool_eq::json::{self, Value};
+
+struct Server {
+    child: Child,
+    addr: String
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn start(args: &[&str], envs: &[(&str, &str)]) -> Server {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bool-eq"))
+        .args(["serve", "--port", "0"])
+        .args(args)
+        .envs(envs.iter().copied())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    let addr = line.trim().strip_prefix("Listening on http://").unwrap_or_else(|| panic!("Unexpected banner {line}")).to_string();
+    Server {child, addr}
+}
+
+// Sends a raw request and returns the status code and the body
+fn send(server: &Server, request: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, body.to_string())
+}
+
+fn post(server: &Server, path: &str, body: &str) -> (u16, Value) {
+    let request = format!("POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}", body.len());
+    let (status, body) = send(server, &request);
+    (status, json::parse(&body).unwrap_or_else(|e| panic!("{e} in response {body}")))
+}
+
+fn strings(value: &Value) -> Vec<&str> {
+    value.as_array().unwrap().iter().map(|v| v.as_str().unwrap()).collect()
+}
+
+fn error((status, res): (u16, Value)) -> (u16, String) {
+    (status, res.get("error").and_then(|e| e.as_str()).unwrap().to_string())
+}
+
+// A request body whose last expression is the end of a chain of len definitions, each using the one before twice
+fn name(n: usize) -> String {
+    format!("d{}{}", (b'a' + (n / 26) as u8) as char, (b'a' + (n % 26) as u8) as char)
+}
+
+// Each link uses the one before it twice, so the last is 2^(len - 1) copies of a ^ b written out
+fn chain(len: usize, last: &str) -> String {
+    let links = (1..len).map(|n| format!("\"{} = {} ^ ({} & c)\"", name(n), name(n - 1), name(n - 1)));
+    let exprs: Vec<String> = std::iter::once(format!("\"{} = a ^ b\"", name(0))).chain(links).collect();
+    format!(r#"{{"exprs": [{}, "{}", "{last}"], "engine": "bdd"}}"#, exprs.join(", "), name(len - 1))
+}
+
+#[test]
+fn parse_and_check() {
+    let server = start(&[], &[]);
+
+    let (status, res) = post(&server, "/parse", r#"{"exprs": ["maj = (a & b) | (c & (a | b))", "maj ^ ~~d"]}"#);
+    assert_eq!(200, status);
+    assert_eq!(vec!["a", "b", "c", "d"], strings(res.get("variables").unwrap()));
+    assert_eq!(vec!["maj = (a & b) | c & a | b"], strings(res.get("definitions").unwrap()));
+    assert_eq!(vec!["maj ^ ~~d"], strings(res.get("expressions").unwrap()));
+
+    for engine in ["ast", "bdd", "sat"] {
+        let (status, res) = post(&server, "/check", &format!(r#"{{"exprs": ["a & b", "~(~a | ~b)"], "engine": "{engine}"}}"#));
+        assert_eq!(200, status);
+        assert_eq!(Some(true), res.get("all_eq").and_then(|v| v.as_bool()));
+
+        let (_, res) = post(&server, "/check", &format!(r#"{{"exprs": ["a & b", "a | b"], "engine": "{engine}", "assume": "a | ~b"}}"#));
+        assert_eq!(Some(false), res.get("all_eq").and_then(|v| v.as_bool()));
+        let cex = &res.get("counterexamples").unwrap().as_array().unwrap()[0];
+        let inputs = cex.get("inputs").unwrap();
+        assert_eq!((Some(true), Some(false)), (inputs.get("a").and_then(|v| v.as_bool()), inputs.get("b").and_then(|v| v.as_bool())));
+    }
+
+    let (_, res) = post(&server, "/check", r#"{"exprs": ["s[1..0] = a & b, a ^ b", "t[1..0] = a | b, a ^ b"], "engine": "sat"}"#);
+    let bits = res.get("bits").unwrap().as_array().unwrap();
+    let equal: Vec<Option<bool>> = bits.iter().map(|bit| bit.get("equal").and_then(|v| v.as_bool())).collect();
+    assert_eq!(vec![Some(true), Some(false)], equal);
+    assert_eq!(Some(&Value::Null), bits[0].get("counterexample"));
+}
+
+#[test]
+fn table_minimize_and_export() {
+    let server = start(&[], &[]);
+
+    let (status, res) = post(&server, "/table", r#"{"exprs": ["a ^ b"], "fix": "a=1"}"#);
+    assert_eq!(200, status);
+    let rows: Vec<Value> = [[1.0, 0.0, 1.0], [1.0, 1.0, 0.0]].iter()
+        .map(|row| Value::Array(row.iter().map(|b| Value::Number(*b)).collect()))
+        .collect();
+    assert_eq!(Some(&Value::Array(rows)), res.get("rows"));
+
+    let (_, res) = post(&server, "/minimize", r#"{"exprs": ["(a & b) | (a & ~b)", "a ? b : b"]}"#);
+    assert_eq!(vec!["a", "b"], strings(res.get("minimized").unwrap()));
+
+    let (_, res) = post(&server, "/bdd", r#"{"exprs": ["a & b"]}"#);
+    assert_eq!(Some("dot"), res.get("format").and_then(|v| v.as_str()));
+    assert!(res.get("data").and_then(|v| v.as_str()).unwrap().starts_with("digraph"));
+    let (_, res) = post(&server, "/bdd", r#"{"exprs": ["a & b"], "format": "dddmp"}"#);
+    assert!(res.get("data").and_then(|v| v.as_str()).unwrap().contains(".roots"));
+}
+
+#[test]
+fn errors_and_limits() {
+    let server = start(&["--max-body", "200"], &[]);
+
+    assert_eq!(400, error(post(&server, "/check", "{\"exprs\": [")).0);
+    assert_eq!(400, error(post(&server, "/check", r#"{"expressions": ["a"]}"#)).0);
+    assert!(error(post(&server, "/check", r#"{"exprs": ["a &"]}"#)).1.contains("Missing operator or operand"));
+    assert_eq!(400, error(post(&server, "/check", r#"{"exprs": ["a"], "engine": "magic"}"#)).0);
+    assert_eq!(404, error(post(&server, "/solve", "{}")).0);
+    assert_eq!(405, send(&server, "GET /check HTTP/1.1\r\nHost: localhost\r\n\r\n").0);
+    assert_eq!(411, send(&server, "POST /check HTTP/1.1\r\nHost: localhost\r\n\r\n").0);
+
+    let big = format!(r#"{{"exprs": ["{}"]}}"#, vec!["a"; 200].join(" | "));
+    assert_eq!(413, error(post(&server, "/check", &big)).0);
+
+    assert_eq!(200, post(&server, "/parse", r#"{"exprs": ["a"]}"#).0);
+}
+
+#[test]
+fn sessions_past_the_bounds_are_refused() {
+    let server = start(&[], &[]);
+
+    // 26 chained definitions fit in under a kilobyte, but written out the last one would hold 2^25 copies of a ^ b
+    let body = chain(26, "a");
+    assert!(body.len() < 1024);
+    let (status, message) = error(post(&server, "/parse", &body));
+    assert_eq!((400, true), (status, message.contains("written out")));
+
+    let (status, res) = post(&server, "/check", &chain(12, "(a ^ b) & ~c"));
+    assert_eq!(200, status);
+    assert_eq!(Some(true), res.get("all_eq").and_then(|v| v.as_bool()));
+
+    let vars: Vec<String> = ('a'..='z').chain('A'..='G').map(|c| c.to_string()).collect();
+    let (status, message) = error(post(&server, "/check", &format!(r#"{{"exprs": ["{}"], "engine": "sat"}}"#, vars.join(" & "))));
+    assert_eq!((400, true), (status, message.contains("variables")));
+
+    // Parity of 32 inputs would start Espresso on 2^31 products
+    let (status, message) = error(post(&server, "/minimize", &format!(r#"{{"exprs": ["{}"]}}"#, vars[..32].join(" ^ "))));
+    assert_eq!((400, true), (status, message.contains("paths")));
+
+    let deep: Vec<String> = (0..100).map(|n| if n == 0 { format!("\"{} = a\"", name(0)) } else { format!("\"{} = {} & b\"", name(n), name(n - 1)) }).collect();
+    let (status, message) = error(post(&server, "/parse", &format!(r#"{{"exprs": [{}, "{}"]}}"#, deep.join(", "), name(99))));
+    assert_eq!((400, true), (status, message.contains("nested")));
+}
+
+#[test]
+fn slow_requests_and_busy_workers() {
+    // Every worker waits a second before starting, far past the 50ms limit
+    let server = start(&["--timeout", "0.05", "--workers", "1"], &[("SERVE_DELAY_MS", "1000")]);
+
+    let (status, message) = error(post(&server, "/parse", r#"{"exprs": ["a"]}"#));
+    assert_eq!((503, true), (status, message.contains("took longer")));
+
+    // The timed-out request still holds the only worker, so the next one is turned away without waiting
+    let (status, message) = error(post(&server, "/parse", r#"{"exprs": ["a"]}"#));
+    assert_eq!((503, true), (status, message.contains("workers are busy")));
+
+    // Once it finishes, its worker is free again
+    std::thread::sleep(std::time::Duration::from_millis(1500));
+    let (status, message) = error(post(&server, "/parse", r#"{"exprs": ["a"]}"#));
+    assert_eq!((503, true), (status, message.contains("took longer")));
+}
+
+#[test]
+fn slow_clients_run_out_of_time() {
+    let server = start(&["--timeout", "0.3"], &[]);
+
+    // A byte every 100ms never trips a single read's timeout, but the request as a whole takes too long
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    for byte in b"POST /parse".iter() {
+        if stream.write_all(&[*byte]).is_err() {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 408"));
+}